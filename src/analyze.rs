@@ -0,0 +1,144 @@
+//! Summary statistics over lexed tokens, for sanity-checking large or
+//! generated sources and spotting changes in how they lex.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::{cmp::Reverse, fmt::Write};
+
+use crate::{LineIndex, Token, TokenKind, Type};
+
+/// How many identifiers [`TokenStats::render`] lists.
+const TOP_IDENTIFIERS: usize = 10;
+
+/// Counts gathered by [`token_stats`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TokenStats {
+    pub tokens: usize,
+    /// How many tokens there are of each kind present, most frequent first.
+    pub kinds: Vec<(TokenKind, usize)>,
+    /// How often each identifier occurs, most frequent first, ties in
+    /// alphabetical order.
+    pub identifiers: Vec<(String, usize)>,
+    pub strings: usize,
+    /// The total length of the strings' contents, in bytes.
+    pub string_bytes: usize,
+    pub numbers: usize,
+    /// The lines of the source, counting a last line without a newline.
+    pub lines: usize,
+    /// The lines on which a token other than trivia starts.
+    pub code_lines: usize,
+}
+
+impl TokenStats {
+    /// A plain text report of the statistics, listing the most frequent
+    /// identifiers only.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "lines        {} ({} with code)",
+            self.lines, self.code_lines
+        );
+        let _ = writeln!(out, "tokens       {}", self.tokens);
+        let _ = writeln!(
+            out,
+            "strings      {} ({} bytes)",
+            self.strings, self.string_bytes
+        );
+        let _ = writeln!(out, "numbers      {}", self.numbers);
+        let _ = writeln!(out, "\nkinds:");
+        for (kind, count) in &self.kinds {
+            let _ = writeln!(out, "  {:<20} {}", format!("{:?}", kind), count);
+        }
+        if !self.identifiers.is_empty() {
+            let _ = writeln!(out, "\nidentifiers:");
+        }
+        for (name, count) in self.identifiers.iter().take(TOP_IDENTIFIERS) {
+            let _ = writeln!(out, "  {:<20} {}", name, count);
+        }
+        out
+    }
+}
+
+/// Gathers statistics about `tokens`, which were lexed from `source`.
+pub fn token_stats(tokens: &[Token], source: &str) -> TokenStats {
+    let index = LineIndex::new(source);
+    let mut stats = TokenStats {
+        tokens: tokens.len(),
+        lines: match source.ends_with('\n') || source.is_empty() {
+            true => index.line_count() - 1,
+            false => index.line_count(),
+        },
+        ..TokenStats::default()
+    };
+    let mut kinds = [0usize; 128];
+    let mut identifiers = BTreeMap::new();
+    let mut last_line = None;
+    for token in tokens {
+        kinds[token.kind() as usize] += 1;
+        match &token.token_type {
+            Type::Identifier(name) => *identifiers.entry(name.as_str()).or_insert(0) += 1,
+            Type::String(s) | Type::StringPart(s) => {
+                stats.strings += 1;
+                stats.string_bytes += s.len();
+            }
+            Type::Number(_) => stats.numbers += 1,
+            _ => (),
+        }
+        if token.is_trivia() || token.span.start >= source.len() {
+            continue;
+        }
+        let line = index.line_col(token.span.start).line;
+        if last_line != Some(line) {
+            stats.code_lines += 1;
+            last_line = Some(line);
+        }
+    }
+    for token in tokens {
+        let kind = token.kind();
+        if kinds[kind as usize] > 0 {
+            stats.kinds.push((kind, kinds[kind as usize]));
+            kinds[kind as usize] = 0;
+        }
+    }
+    stats.kinds.sort_by_key(|&(_, count)| Reverse(count));
+    stats.identifiers = identifiers
+        .into_iter()
+        .map(|(name, count)| (String::from(name), count))
+        .collect();
+    stats.identifiers.sort_by_key(|&(_, count)| Reverse(count));
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Lexer, TokenKind, analyze::token_stats};
+
+    #[test]
+    fn token_stats_counts() {
+        let source = "define x = \"ab\";\n\n/* y */ x = x + 1 + f(\"c\");\n";
+        let tokens = Lexer::default().lex(source).unwrap();
+        let stats = token_stats(&tokens, source);
+        assert_eq!(stats.tokens, tokens.len());
+        assert_eq!((stats.lines, stats.code_lines), (3, 2));
+        assert_eq!(
+            (stats.strings, stats.string_bytes, stats.numbers),
+            (2, 3, 1)
+        );
+        assert_eq!(
+            stats.identifiers,
+            vec![("x".to_string(), 3), ("f".to_string(), 1)]
+        );
+        assert_eq!(stats.kinds[0], (TokenKind::Identifier, 4));
+        assert_eq!(
+            stats.kinds.iter().map(|(_, n)| n).sum::<usize>(),
+            tokens.len()
+        );
+        let report = stats.render();
+        assert!(report.starts_with("lines        3 (2 with code)\n"));
+        assert!(report.contains("\n  x                    3\n"));
+
+        let stats = token_stats(&[], "");
+        assert_eq!((stats.lines, stats.code_lines), (0, 0));
+        assert_eq!(token_stats(&[], "a").lines, 1);
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    Cursor, LexError, LexOutput, Lexer, Limit, Span, Token, Type, error_limit_reached,
+    token_limit_reached,
+};
+
+/// Stands in for each byte of an invalid sequence in the text handed to
+/// layout and filters, keeping every offset the same as in the input.
+const SUBSTITUTE: char = '\u{1a}';
+
+impl Lexer {
+    /// Like [`Lexer::lex_with_diagnostics`], for input that need not be
+    /// valid UTF-8, such as an uploaded file.
+    ///
+    /// Every maximal run of bytes that is not UTF-8 becomes a [`Type::None`]
+    /// token spanning it, along with a [`LexError::InvalidUtf8`], and ends any
+    /// token before it: a string literal cut off by one is unterminated.
+    /// Layout and filters see each such byte as U+001A.
+    pub fn lex_bytes(&self, bytes: &[u8]) -> LexOutput {
+        if let Ok(s) = core::str::from_utf8(bytes) {
+            return self.lex_with_diagnostics(s);
+        }
+        let mut text = String::with_capacity(bytes.len());
+        let mut invalid: Vec<Span> = Vec::new();
+        for chunk in bytes.utf8_chunks() {
+            text.push_str(chunk.valid());
+            let start = text.len();
+            text.extend(chunk.invalid().iter().map(|_| SUBSTITUTE));
+            match invalid.last_mut() {
+                Some(last) if last.end == start && !chunk.invalid().is_empty() => {
+                    last.end = text.len()
+                }
+                _ if !chunk.invalid().is_empty() => invalid.push(Span::new(start, text.len())),
+                _ => (),
+            }
+        }
+        let mut output = LexOutput::default();
+        let mut start = 0;
+        for span in invalid
+            .into_iter()
+            .chain([Span::new(text.len(), text.len())])
+        {
+            // Each valid run is lexed on its own, from a cursor that ends
+            // where the run does but keeps the offsets of the whole input.
+            let chars = Cursor {
+                offset: start,
+                ..Cursor::new(&text[..span.start])
+            };
+            self.lex_raw(chars, &mut output);
+            if span.start == span.end
+                || error_limit_reached(&self.options, output.errors.len())
+                || matches!(
+                    output.errors.last(),
+                    Some(LexError::LimitExceeded(Limit::Tokens, _))
+                )
+            {
+                break;
+            }
+            output.tokens.push(Token::with_span(Type::None, span));
+            output.errors.push(LexError::InvalidUtf8(span.start));
+            if token_limit_reached(&self.options, 0, &mut output) {
+                break;
+            }
+            start = span.end;
+        }
+        self.finish(&text, &mut output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LexError, Lexer, Operator, Span, Token, Type, UnknownCharRule, generate::Rng};
+
+    #[test]
+    fn lex_bytes() {
+        let lexer = Lexer::default();
+        let valid = "define x = \"é\";";
+        assert_eq!(
+            lexer.lex_bytes(valid.as_bytes()),
+            lexer.lex_with_diagnostics(valid)
+        );
+
+        let output = lexer.lex_bytes(b"ab\xff\xfe cd \"e\x80f\" \xe2\x82");
+        let types: Vec<Type> = output.tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                Type::Identifier("ab".to_string()),
+                Type::None,
+                Type::Identifier("cd".to_string()),
+                Type::None,
+                Type::Identifier("f".to_string()),
+                Type::None,
+            ]
+        );
+        assert_eq!(
+            output.tokens[1],
+            Token::with_span(Type::None, Span::new(2, 4))
+        );
+        assert_eq!(output.tokens[5].span, Span::new(14, 16));
+        assert_eq!(
+            output.errors,
+            vec![
+                LexError::InvalidUtf8(2),
+                LexError::UnterminatedString(8),
+                LexError::InvalidUtf8(10),
+                LexError::UnterminatedString(12),
+                LexError::InvalidUtf8(14),
+            ]
+        );
+
+        // The preamble is only recognized at the very start.
+        let shebang = Lexer::builder().shebang(true).build();
+        let output = shebang.lex_bytes(b"\xff#!x");
+        assert_eq!(output.tokens[1].token_type, Type::Operator(Operator::Bang));
+    }
+
+    #[test]
+    fn arbitrary_bytes() {
+        // Bytes that start, end or break up tokens, with every option that
+        // adds a rule turned on.
+        let alphabet = b"\"${}[]/*`?\n\t 0x_9ab#!@=>-.:;\xef\xbb\xbf\xff\xe2\x80\xc3\xa9";
+        let lexer = Lexer::builder()
+            .trivia(true)
+            .shebang(true)
+            .labels(true)
+            .directives(true)
+            .interpolation(true)
+            .attributes(true)
+            .decorators(true)
+            .multiline_strings(true)
+            .dedent_strings(true)
+            .layout(true)
+            .doc_comments(true)
+            .eof(true)
+            .radix_prefix("0x", 16)
+            .digit_separator('_')
+            .operator("=>>")
+            .long_token_warning(4)
+            .combining_warning(true)
+            .unknown_chars(UnknownCharRule::Token)
+            .build();
+        let mut rng = Rng(17);
+        for _ in 0..2000 {
+            let len = (rng.next() % 48) as usize;
+            let input: Vec<u8> = (0..len)
+                .map(|_| alphabet[(rng.next() % alphabet.len() as u64) as usize])
+                .collect();
+            let output = lexer.lex_bytes(&input);
+            for token in &output.tokens {
+                assert!(token.span.start <= token.span.end && token.span.end <= input.len());
+            }
+            if let Ok(source) = core::str::from_utf8(&input) {
+                let tokens = lexer.lex_compact(source);
+                assert_eq!(tokens.is_ok(), output.errors.is_empty(), "{:?}", source);
+            }
+        }
+    }
+}
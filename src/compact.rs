@@ -0,0 +1,112 @@
+use alloc::vec::Vec;
+
+use crate::{
+    BYTES_PER_TOKEN, Cursor, LexError, LexOutput, Lexer, Span, Token, TokenKind, lex_preamble,
+    lex_step, token_limit_reached,
+};
+
+/// A token reduced to its kind and span, for keeping large token streams
+/// small. Its text is the span's slice of the source, and values that need
+/// more than that, such as a number's, come from lexing the slice again or
+/// from a [`crate::TokenMap`] filled alongside.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CompactToken {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+const _: () = assert!(size_of::<CompactToken>() <= 24);
+
+impl CompactToken {
+    /// The text of this token in `source`, the text it was lexed from.
+    /// Synthetic tokens, such as an inserted `;`, have empty lexemes.
+    pub fn lexeme<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.span.start..self.span.end]
+    }
+}
+
+impl From<&Token> for CompactToken {
+    fn from(token: &Token) -> Self {
+        CompactToken {
+            kind: token.kind(),
+            span: token.span,
+        }
+    }
+}
+
+impl Lexer {
+    /// Like [`Lexer::lex`], keeping only the kind and span of each token.
+    ///
+    /// Tokens are reduced as they are lexed, so their payloads are never all
+    /// held at once. Lexers with filters, layout or modes lex `s` through
+    /// [`Lexer::lex`] first and reduce the result.
+    pub fn lex_compact(&self, s: &str) -> Result<Vec<CompactToken>, LexError> {
+        let options = self.options();
+        if !self.filters.is_empty() || options.layout || !self.modes.is_empty() {
+            return Ok(self.lex(s)?.iter().map(CompactToken::from).collect());
+        }
+        let mut chars = Cursor::new(s);
+        let mut step = LexOutput::default();
+        lex_preamble(&mut chars, options, &mut step.tokens);
+        let mut tokens = Vec::with_capacity(step.tokens.len() + s.len() / BYTES_PER_TOKEN);
+        loop {
+            token_limit_reached(options, tokens.len(), &mut step);
+            tokens.extend(
+                step.tokens
+                    .drain(..)
+                    .map(|token| CompactToken::from(&token)),
+            );
+            if !step.errors.is_empty() {
+                return Err(step.errors.swap_remove(0));
+            }
+            if lex_step(&mut chars, options, &mut step).is_none() {
+                break;
+            }
+        }
+        if options.eof {
+            tokens.push(CompactToken {
+                kind: TokenKind::Eof,
+                span: Span::new(s.len(), s.len()),
+            });
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompactToken, LexError, Lexer, Span, TokenKind, filter::InsertSemicolons};
+
+    #[test]
+    fn lex_compact() {
+        let source = "#!run\ndefine x = 0x1f + f(\"é\"); /* c */ y";
+        let lexers = [
+            Lexer::builder()
+                .radix_prefix("0x", 16)
+                .trivia(true)
+                .eof(true)
+                .build(),
+            Lexer::builder().filter(InsertSemicolons).build(),
+        ];
+        for lexer in &lexers {
+            let tokens = lexer.lex(source).unwrap();
+            let compact = lexer.lex_compact(source).unwrap();
+            assert_eq!(
+                compact,
+                tokens.iter().map(CompactToken::from).collect::<Vec<_>>()
+            );
+        }
+        let compact = lexers[0].lex_compact(source).unwrap();
+        assert_eq!(compact[4].kind, TokenKind::Identifier);
+        assert_eq!(compact[4].lexeme(source), "x");
+        assert_eq!(compact[8].lexeme(source), "0x1f");
+        assert_eq!(
+            compact.last().unwrap().span,
+            Span::new(source.len(), source.len())
+        );
+        assert_eq!(
+            Lexer::default().lex_compact("a \"b"),
+            Err(LexError::UnterminatedString(2))
+        );
+    }
+}
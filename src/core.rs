@@ -0,0 +1,154 @@
+//! The building blocks the lexer is made of, for writing tokenizers of other
+//! formats (CSV fields, log lines) whose spans and errors line up with the
+//! rest of the crate.
+//!
+//! A [`Cursor`] walks a string by character while tracking its byte offset,
+//! so tokens get a [`Span`] from [`Cursor::span_from`] and failures become a
+//! [`Diagnostic`] that renders against the same source. [`crate::csv`] is a
+//! tokenizer built this way.
+
+pub use crate::{Span, diagnostics::Diagnostic};
+
+/// A position in a string, advanced one character at a time. ASCII input is
+/// read byte by byte, with UTF-8 decoded only for other characters.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a> {
+    pub(crate) source: &'a str,
+    pub(crate) offset: usize,
+    /// How many interpolated strings the lexer is inside, to bound its
+    /// recursion into them.
+    pub(crate) nesting: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Cursor {
+            source,
+            offset: 0,
+            nesting: 0,
+        }
+    }
+
+    /// The byte offset of the next character.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// The input not consumed yet.
+    pub fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.offset == self.source.len()
+    }
+
+    pub fn peek(&self) -> Option<char> {
+        match self.source.as_bytes().get(self.offset) {
+            Some(&b) if b.is_ascii() => Some(b as char),
+            Some(_) => self.rest().chars().next(),
+            None => None,
+        }
+    }
+
+    pub fn peek_second(&self) -> Option<char> {
+        let bytes = self.source.as_bytes();
+        match (bytes.get(self.offset), bytes.get(self.offset + 1)) {
+            (Some(a), Some(&b)) if a.is_ascii() && b.is_ascii() => Some(b as char),
+            _ => self.rest().chars().nth(1),
+        }
+    }
+
+    /// The next byte, which is the next character when it is ASCII. Scanners
+    /// match on it to skip decoding UTF-8 for the common case.
+    pub fn peek_byte(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.offset).copied()
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consumes bytes while `f` accepts them, returning them. `f` must only
+    /// accept ASCII bytes, so the cursor stays on a character boundary.
+    pub fn eat_bytes_while(&mut self, mut f: impl FnMut(u8) -> bool) -> &'a str {
+        let start = self.offset;
+        let len = self.source.as_bytes()[start..]
+            .iter()
+            .position(|&b| !(b.is_ascii() && f(b)))
+            .unwrap_or(self.source.len() - start);
+        self.offset += len;
+        &self.source[start..self.offset]
+    }
+
+    pub fn next_if(&mut self, f: impl FnOnce(&char) -> bool) -> Option<char> {
+        match self.peek() {
+            Some(c) if f(&c) => self.next(),
+            _ => None,
+        }
+    }
+
+    /// Consumes `prefix` if the rest of the input starts with it.
+    pub fn eat(&mut self, prefix: &str) -> bool {
+        let matches = self.rest().starts_with(prefix);
+        if matches {
+            self.offset += prefix.len();
+        }
+        matches
+    }
+
+    /// Consumes characters while `f` accepts them, returning them.
+    pub fn eat_while(&mut self, mut f: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.offset;
+        while self.next_if(|&c| f(c)).is_some() {}
+        &self.source[start..self.offset]
+    }
+
+    /// The span from `start` to the current offset.
+    pub fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.offset)
+    }
+
+    /// The text covered by `span`.
+    pub fn slice(&self, span: Span) -> &'a str {
+        &self.source[span.start..span.end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{Cursor, Diagnostic, Span};
+
+    #[test]
+    fn cursor() {
+        let source = "level=warn msg=\"é\"";
+        let mut cursor = Cursor::new(source);
+        let key = cursor.eat_while(|c| c != '=');
+        assert_eq!(key, "level");
+        assert!(cursor.eat("="));
+        assert!(!cursor.eat("="));
+        let start = cursor.offset();
+        cursor.eat_while(|c| !c.is_whitespace());
+        assert_eq!(cursor.span_from(start), Span::new(6, 10));
+        assert_eq!(cursor.slice(cursor.span_from(start)), "warn");
+        cursor.next();
+        cursor.eat_while(|c| c != '"');
+        assert_eq!(cursor.peek_second(), Some('é'));
+        assert_eq!(cursor.peek_byte(), Some(b'"'));
+        assert_eq!(cursor.eat_bytes_while(|b| b != b'x'), "\"");
+        cursor.offset -= 1;
+        assert_eq!(cursor.rest(), "\"é\"");
+        assert_eq!(cursor.eat_while(|_| true), "\"é\"");
+        assert!(cursor.is_at_end());
+        assert_eq!(cursor.next(), None);
+        let rendered = Diagnostic::new("bad level", Span::new(6, 10)).render("log", source, false);
+        assert!(rendered.ends_with("|       ^^^^\n"));
+    }
+}
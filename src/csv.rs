@@ -0,0 +1,134 @@
+//! A CSV tokenizer built on [`crate::core`].
+//!
+//! Records end at `\n` or `\r\n` and fields are separated by `,`. A field
+//! starting with `"` is quoted: it runs to the next lone `"`, may contain
+//! commas and newlines, and writes a literal quote as `""`.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::core::{Cursor, Diagnostic, Span};
+
+/// One field, unquoted, with the span it covers in the source including any
+/// quotes.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Field {
+    pub value: String,
+    pub span: Span,
+}
+
+fn quoted_field(chars: &mut Cursor) -> Result<Field, Diagnostic> {
+    let start = chars.offset();
+    chars.next();
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') if chars.eat("\"") => value.push('"'),
+            Some('"') => break,
+            Some(c) => value.push(c),
+            None => {
+                return Err(Diagnostic::new(
+                    "unterminated quoted field",
+                    Span::new(start, start + 1),
+                ));
+            }
+        }
+    }
+    match chars.peek() {
+        None | Some(',' | '\n') => {}
+        Some('\r') if chars.peek_second() == Some('\n') => {}
+        Some(c) => {
+            let offset = chars.offset();
+            return Err(Diagnostic::new(
+                "expected `,` or the end of the record after a quoted field",
+                Span::new(offset, offset + c.len_utf8()),
+            ));
+        }
+    }
+    Ok(Field {
+        value,
+        span: chars.span_from(start),
+    })
+}
+
+/// Splits `source` into records of fields. A trailing newline does not start
+/// another record.
+pub fn parse(source: &str) -> Result<Vec<Vec<Field>>, Diagnostic> {
+    let mut chars = Cursor::new(source);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    while !chars.is_at_end() {
+        let field = match chars.peek() {
+            Some('"') => quoted_field(&mut chars)?,
+            _ => {
+                let start = chars.offset();
+                let value = chars.eat_while(|c| c != ',' && c != '\n');
+                let value = value.strip_suffix('\r').unwrap_or(value);
+                Field {
+                    value: value.to_string(),
+                    span: Span::new(start, start + value.len()),
+                }
+            }
+        };
+        record.push(field);
+        if chars.eat(",") {
+            continue;
+        }
+        chars.eat("\r");
+        chars.eat("\n");
+        records.push(core::mem::take(&mut record));
+    }
+    if source.ends_with(',') {
+        let end = source.len();
+        record.push(Field {
+            value: String::new(),
+            span: Span::new(end, end),
+        });
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Span,
+        core::Diagnostic,
+        csv::{Field, parse},
+    };
+
+    fn values(source: &str) -> Vec<Vec<String>> {
+        parse(source)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.into_iter().map(|field| field.value).collect())
+            .collect()
+    }
+
+    #[test]
+    fn records() {
+        assert_eq!(
+            values("a,b\r\n\"x, \"\"y\"\"\",\n,\n"),
+            vec![vec!["a", "b"], vec!["x, \"y\"", ""], vec!["", ""]]
+        );
+        assert_eq!(values("a,"), vec![vec!["a", ""]]);
+        assert_eq!(values("\"multi\nline\""), vec![vec!["multi\nline"]]);
+        assert_eq!(
+            parse("id,\"é\"").unwrap()[0][1],
+            Field {
+                value: "é".to_string(),
+                span: Span::new(3, 7),
+            }
+        );
+        assert_eq!(
+            parse("a,\"b"),
+            Err(Diagnostic::new(
+                "unterminated quoted field",
+                Span::new(2, 3)
+            ))
+        );
+        assert_eq!(parse("\"a\"b").unwrap_err().span, Span::new(3, 4));
+    }
+}
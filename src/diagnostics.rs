@@ -0,0 +1,203 @@
+//! Rendering of errors as rustc-style messages quoting the source:
+//!
+//! ```text
+//! error: unterminated string
+//!  --> main.lx:2:5
+//!   |
+//! 2 | x = "abc
+//!   |     ^^^^
+//! ```
+
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+use crate::{LexError, LineIndex, SourceFile, Span, parser::ParseError};
+
+/// A message attached to a span of source.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Self {
+        Diagnostic::new(error.message(), error.span())
+    }
+}
+
+impl Diagnostic {
+    /// Describes `error`. Errors at the end of input point at `source_len`,
+    /// the length of the text that was parsed.
+    pub fn from_parse_error(error: &ParseError, source_len: usize) -> Self {
+        match error {
+            ParseError::Lex(error) => error.into(),
+            _ => Diagnostic::new(
+                error.message(),
+                error.span().unwrap_or(Span::new(source_len, source_len)),
+            ),
+        }
+    }
+
+    /// Renders the diagnostic as an error in file `name`, quoting the line of
+    /// `source` it starts on and underlining its span there. With `color`,
+    /// ANSI escape codes highlight the parts the way rustc does.
+    pub fn render(&self, name: &str, source: &str, color: bool) -> String {
+        self.render_indexed(name, source, &LineIndex::new(source), color)
+    }
+
+    /// Like [`Diagnostic::render`], reusing the line index of `file`.
+    pub fn render_file(&self, file: &SourceFile, color: bool) -> String {
+        self.render_indexed(file.display_name(), file.text(), file.line_index(), color)
+    }
+
+    fn render_indexed(&self, name: &str, source: &str, index: &LineIndex, color: bool) -> String {
+        let (red, blue, bold, reset) = match color {
+            true => ("\x1b[1;31m", "\x1b[1;34m", "\x1b[1m", "\x1b[0m"),
+            false => ("", "", "", ""),
+        };
+        let start = source.floor_char_boundary(self.span.start);
+        let position = index.line_col(start);
+        let line_span = index
+            .line_span(position.line)
+            .unwrap_or(Span::new(start, start));
+        let line = source[line_span.start..line_span.end].trim_end_matches(['\n', '\r']);
+        let column = source[line_span.start..start].chars().count();
+        let end =
+            source.floor_char_boundary(self.span.end.clamp(start, line_span.start + line.len()));
+        let width = source[start..end].chars().count().max(1);
+        let number = (position.line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        let mut out = String::new();
+        let _ = writeln!(out, "{red}error{reset}{bold}: {}{reset}", self.message);
+        let _ = writeln!(
+            out,
+            "{gutter}{blue}-->{reset} {}:{}:{}",
+            name,
+            position.line + 1,
+            column + 1
+        );
+        let _ = writeln!(out, "{gutter} {blue}|{reset}");
+        let _ = writeln!(out, "{blue}{number} |{reset} {}", line);
+        let _ = writeln!(
+            out,
+            "{gutter} {blue}|{reset} {}{red}{}{reset}",
+            " ".repeat(column),
+            "^".repeat(width)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        LexError, Lexer, SourceFile, Span,
+        diagnostics::Diagnostic,
+        parser::{ParseError, parse},
+    };
+
+    #[test]
+    fn render() {
+        let source = "define x = 1;\nx = \"é bc";
+        let diagnostic = Diagnostic::from(&LexError::UnterminatedString(18));
+        assert_eq!(
+            diagnostic.render("main.lx", source, false),
+            concat!(
+                "error: unterminated string\n",
+                " --> main.lx:2:5\n",
+                "  |\n",
+                "2 | x = \"é bc\n",
+                "  |     ^\n",
+            )
+        );
+        assert_eq!(
+            Diagnostic::new("bad", Span::new(19, 30)).render("m", source, false),
+            concat!(
+                "error: bad\n",
+                " --> m:2:6\n",
+                "  |\n",
+                "2 | x = \"é bc\n",
+                "  |      ^^^^\n",
+            )
+        );
+        assert_eq!(
+            Diagnostic::new("bad", Span::new(0, 1)).render("m", "x", true),
+            concat!(
+                "\x1b[1;31merror\x1b[0m\x1b[1m: bad\x1b[0m\n",
+                " \x1b[1;34m-->\x1b[0m m:1:1\n",
+                "  \x1b[1;34m|\x1b[0m\n",
+                "\x1b[1;34m1 |\x1b[0m x\n",
+                "  \x1b[1;34m|\x1b[0m \x1b[1;31m^\x1b[0m\n",
+            )
+        );
+
+        assert_eq!(
+            Diagnostic::new("x", Span::new(1, 2)).render("f", "é", false),
+            Diagnostic::new("x", Span::new(0, 2)).render("f", "é", false)
+        );
+        assert!(
+            Diagnostic::new("x", Span::new(0, 1))
+                .render("f", "é", false)
+                .ends_with("| ^\n")
+        );
+
+        let file = SourceFile::with_name("main.lx", source);
+        assert_eq!(
+            diagnostic.render_file(&file, false),
+            diagnostic.render("main.lx", source, false)
+        );
+
+        let source = "(1 + 2";
+        let error = parse(&Lexer::default(), source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(&error, source.len());
+        assert_eq!(
+            diagnostic,
+            Diagnostic::new("expected RightParen, found end of input", Span::new(6, 6))
+        );
+        assert!(
+            diagnostic
+                .render("m", source, false)
+                .ends_with("|       ^\n")
+        );
+        let error = ParseError::Lex(LexError::UnterminatedComment(0));
+        assert_eq!(
+            Diagnostic::from_parse_error(&error, 9).message,
+            "unterminated block comment"
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let error = LexError::UnterminatedString(4);
+        assert_eq!(error.to_string(), "unterminated string at 4..5 [L001]");
+        assert_eq!(error.code(), "L001");
+        assert_eq!(LexError::UnterminatedComment(3).span(), Span::new(3, 5));
+
+        let error = parse(&Lexer::default(), "(1 + 2").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected RightParen, found end of input [P002]"
+        );
+        assert_eq!(error.span(), None);
+        let error = parse(&Lexer::default(), "1 2").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unexpected `2` after the end at 2..3 [P003]"
+        );
+
+        let error = parse(&Lexer::default(), "x = \"a").unwrap_err();
+        assert_eq!(error.code(), "L001");
+        let source = core::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), error.to_string());
+    }
+}
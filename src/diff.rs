@@ -0,0 +1,288 @@
+//! Differences between two token streams, such as the tokens of a buffer
+//! before and after an edit.
+//!
+//! [`diff`] pairs up as many tokens of the two streams as it can, in order,
+//! and reports the runs in between as [`TokenEdit`]s: a minimal edit script.
+//! Tokens are compared by type alone, since an edit moves the spans of
+//! everything after it. A language server can re-classify just the new side
+//! of each edit, and a test can print [`render_diff`] to show which tokens
+//! changed.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::{fmt::Write, ops::Range};
+
+use crate::{Span, Token, Type};
+
+/// What a [`TokenEdit`] does to the old stream.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Replace,
+}
+
+/// A run of old tokens replaced by a run of new ones, either of which may be
+/// empty.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TokenEdit {
+    /// The indices of the run in the old stream. For an insertion, the
+    /// empty range at the index the new tokens go before.
+    pub old: Range<usize>,
+    /// The indices of the run in the new stream, likewise.
+    pub new: Range<usize>,
+    /// The source the old tokens cover; for an insertion, the empty span at
+    /// the end of the old token before it.
+    pub old_span: Span,
+    /// The source the new tokens cover; for a deletion, the empty span at
+    /// the end of the new token before it.
+    pub new_span: Span,
+}
+
+impl TokenEdit {
+    pub fn kind(&self) -> EditKind {
+        match (self.old.is_empty(), self.new.is_empty()) {
+            (true, _) => EditKind::Insert,
+            (_, true) => EditKind::Delete,
+            _ => EditKind::Replace,
+        }
+    }
+}
+
+/// The edits turning `old` into `new`, in order. Every token outside them is
+/// paired with an equal token on the other side, and no script with fewer
+/// insertions and deletions exists.
+pub fn diff(old: &[Token], new: &[Token]) -> Vec<TokenEdit> {
+    let a: Vec<&Type> = old.iter().map(|t| &t.token_type).collect();
+    let b: Vec<&Type> = new.iter().map(|t| &t.token_type).collect();
+    let mut kept_a = vec![false; a.len()];
+    let mut kept_b = vec![false; b.len()];
+    compare(&a, &b, &mut kept_a, &mut kept_b);
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && kept_a[i] && kept_b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (start_a, start_b) = (i, j);
+        while i < a.len() && !kept_a[i] {
+            i += 1;
+        }
+        while j < b.len() && !kept_b[j] {
+            j += 1;
+        }
+        edits.push(TokenEdit {
+            old: start_a..i,
+            new: start_b..j,
+            old_span: run_span(old, start_a..i),
+            new_span: run_span(new, start_b..j),
+        });
+    }
+    edits
+}
+
+/// The span of `tokens[run]`, or the empty span after the token before it.
+fn run_span(tokens: &[Token], run: Range<usize>) -> Span {
+    match (run.start.checked_sub(1), run.is_empty()) {
+        (_, false) => Span::new(tokens[run.start].span.start, tokens[run.end - 1].span.end),
+        (Some(before), true) => Span::new(tokens[before].span.end, tokens[before].span.end),
+        (None, true) => Span::new(0, 0),
+    }
+}
+
+/// Marks the elements of `a` and `b` that a longest common subsequence
+/// keeps, using Myers' linear-space algorithm: the middle snake of the
+/// shortest edit path splits the problem in two, each with at most half the
+/// edits.
+fn compare<T: PartialEq>(a: &[T], b: &[T], kept_a: &mut [bool], kept_b: &mut [bool]) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (n, m) = (a.len() - suffix, b.len() - suffix);
+    kept_a[..prefix].fill(true);
+    kept_a[n..].fill(true);
+    kept_b[..prefix].fill(true);
+    kept_b[m..].fill(true);
+    let (a, b) = (&a[prefix..n], &b[prefix..m]);
+    let (kept_a, kept_b) = (&mut kept_a[prefix..n], &mut kept_b[prefix..m]);
+    if a.is_empty() || b.is_empty() {
+        return;
+    }
+    let (x0, y0, x1, y1) = middle_snake(a, b);
+    kept_a[x0..x1].fill(true);
+    kept_b[y0..y1].fill(true);
+    let (head_a, tail_a) = kept_a.split_at_mut(x1);
+    let (head_b, tail_b) = kept_b.split_at_mut(y1);
+    compare(&a[..x0], &b[..y0], &mut head_a[..x0], &mut head_b[..y0]);
+    compare(&a[x1..], &b[y1..], tail_a, tail_b);
+}
+
+/// The middle snake of the shortest edit path from `a` to `b`, as the
+/// positions it starts and ends at. Both slices are non-empty and differ in
+/// their first and last elements.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    // Furthest x reached on each diagonal k = x - y, forward from the start
+    // and backward from the end, indexed by k + offset.
+    let offset = max + 1;
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                true => forward[at(k + 1)],
+                false => forward[at(k - 1)] + 1,
+            };
+            let (start_x, start_y) = (x, x - k);
+            while x < n && x - k < m && a[x as usize] == b[(x - k) as usize] {
+                x += 1;
+            }
+            forward[at(k)] = x;
+            let back = delta - k;
+            if odd && (-(d - 1)..=d - 1).contains(&back) && x + backward[at(back)] >= n {
+                return (
+                    start_x as usize,
+                    start_y as usize,
+                    x as usize,
+                    (x - k) as usize,
+                );
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                true => backward[at(k + 1)],
+                false => backward[at(k - 1)] + 1,
+            };
+            let (end_x, end_y) = (n - x, m - (x - k));
+            while x < n && x - k < m && a[(n - x - 1) as usize] == b[(m - (x - k) - 1) as usize] {
+                x += 1;
+            }
+            backward[at(k)] = x;
+            let ahead = delta - k;
+            if !odd && (-d..=d).contains(&ahead) && x + forward[at(ahead)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - (x - k)) as usize,
+                    end_x as usize,
+                    end_y as usize,
+                );
+            }
+        }
+    }
+    unreachable!("the forward and backward paths always meet")
+}
+
+/// A listing of the edits turning `old` into `new`, one line per changed
+/// token, or an empty string when the streams hold the same tokens.
+pub fn render_diff(old: &[Token], new: &[Token]) -> String {
+    let show = |t: &Token| format!("{:?} `{}` at {}..{}", t.kind(), t, t.span.start, t.span.end);
+    let mut out = String::new();
+    for edit in diff(old, new) {
+        let _ = writeln!(
+            out,
+            "@@ old {}..{}, new {}..{}",
+            edit.old.start, edit.old.end, edit.new.start, edit.new.end
+        );
+        for token in &old[edit.old] {
+            let _ = writeln!(out, "- {}", show(token));
+        }
+        for token in &new[edit.new] {
+            let _ = writeln!(out, "+ {}", show(token));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer, Span,
+        diff::{EditKind, diff, render_diff},
+        generate::Rng,
+    };
+
+    #[test]
+    fn diff_tokens() {
+        let lexer = Lexer::default();
+        let old = lexer.lex("define x = a + b; f(x)").unwrap();
+        let new = lexer.lex("define y = a + b * 2; f(x) g").unwrap();
+        let edits = diff(&old, &new);
+        let kinds: Vec<EditKind> = edits.iter().map(|e| e.kind()).collect();
+        assert_eq!(
+            kinds,
+            [EditKind::Replace, EditKind::Insert, EditKind::Insert]
+        );
+        assert_eq!((edits[0].old.clone(), edits[0].new.clone()), (1..2, 1..2));
+        assert_eq!(edits[0].new_span, Span::new(7, 8));
+        assert_eq!((edits[1].old.clone(), edits[1].new.clone()), (6..6, 6..8));
+        assert_eq!(edits[1].old_span, Span::new(16, 16));
+        assert_eq!(edits[1].new_span, Span::new(17, 20));
+        assert_eq!(edits[2].new_span, Span::new(27, 28));
+        assert_eq!(
+            render_diff(&old, &new).lines().take(3).collect::<Vec<_>>(),
+            [
+                "@@ old 1..2, new 1..2",
+                "- Identifier `x` at 7..8",
+                "+ Identifier `y` at 7..8"
+            ]
+        );
+        let deleted = diff(&new, &old);
+        assert_eq!(deleted[2].kind(), EditKind::Delete);
+        assert_eq!(deleted[2].new_span, Span::new(22, 22));
+        assert!(diff(&old, &old).is_empty());
+        assert_eq!(render_diff(&old, &old), "");
+        assert_eq!(diff(&[], &old)[0].old_span, Span::new(0, 0));
+    }
+
+    #[test]
+    fn diff_is_minimal() {
+        let mut rng = Rng(3);
+        for _ in 0..300 {
+            let mut random = |len: u64| {
+                (0..rng.next() % len)
+                    .map(|_| (b'a' + (rng.next() % 3) as u8) as char)
+                    .collect::<Vec<char>>()
+            };
+            let (a, b) = (random(12), random(12));
+            let (mut kept_a, mut kept_b) = (vec![false; a.len()], vec![false; b.len()]);
+            super::compare(&a, &b, &mut kept_a, &mut kept_b);
+            let kept: Vec<char> = a
+                .iter()
+                .zip(&kept_a)
+                .filter(|k| *k.1)
+                .map(|k| *k.0)
+                .collect();
+            let also: Vec<char> = b
+                .iter()
+                .zip(&kept_b)
+                .filter(|k| *k.1)
+                .map(|k| *k.0)
+                .collect();
+            assert_eq!(kept, also);
+            assert_eq!(kept.len(), lcs(&a, &b), "{:?} {:?}", a, b);
+        }
+    }
+
+    fn lcs(a: &[char], b: &[char]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = match a[i] == b[j] {
+                    true => table[i][j] + 1,
+                    false => table[i][j + 1].max(table[i + 1][j]),
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+}
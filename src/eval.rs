@@ -0,0 +1,313 @@
+//! A tree-walking evaluator for the statements produced by [`crate::parser`].
+//!
+//! Numbers are `i32` and wrap on overflow. `+` also concatenates strings,
+//! comparisons work on two numbers or two strings, `==` and `!=` compare any
+//! two values, and `&&`, `||`, `!` and `if` conditions need booleans, with
+//! `&&` and `||` short-circuiting. `??` evaluates its right operand only when
+//! the left one is null. Blocks open a new scope for `define`.
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    Operator, Span,
+    parser::{Expr, Literal, MAX_DEPTH, Stmt},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Value {
+    Number(i32),
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Null => f.write_str("null"),
+        }
+    }
+}
+
+/// Why evaluation stopped, with the span of the offending expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EvalError {
+    UndefinedVariable(String, Span),
+    /// An operator or condition got operands of the wrong types.
+    TypeMismatch(Span),
+    DivisionByZero(Span),
+    /// The language has no functions yet, so every call fails.
+    NotCallable(Span),
+    /// A custom operator, which has no meaning to the evaluator.
+    UnsupportedOperator(Operator, Span),
+    /// A [`Stmt::Error`], left where a statement failed to parse.
+    InvalidStatement(Span),
+    /// An [`Expr::Error`], left where an expression was missing.
+    InvalidExpression(Span),
+    /// The statement or expression nests deeper than the parser allows; see
+    /// [`MAX_DEPTH`].
+    NestingTooDeep(Span),
+}
+
+/// Variables in scope, innermost scope last.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Environment {
+    scopes: Vec<BTreeMap<String, Value>>,
+    /// The statements and expressions being evaluated.
+    depth: usize,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            scopes: vec![BTreeMap::new()],
+            depth: 0,
+        }
+    }
+
+    /// The value of `name` in the innermost scope defining it.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Binds `name` in the innermost scope, replacing any earlier binding
+    /// there.
+    pub fn define(&mut self, name: impl Into<String>, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.into(), value);
+        }
+    }
+
+    /// Goes one level deeper into the node at `span`, failing past the
+    /// deepest tree the parser builds: [`MAX_DEPTH`] levels and a leaf.
+    fn deepen(&mut self, span: Span) -> Result<(), EvalError> {
+        match self.depth > MAX_DEPTH {
+            true => Err(EvalError::NestingTooDeep(span)),
+            false => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs `stmt`, returning the value of an expression statement and
+    /// [`Value::Null`] for anything else.
+    pub fn execute(&mut self, stmt: &Stmt) -> Result<Value, EvalError> {
+        self.deepen(stmt.span())?;
+        let result = self.execute_node(stmt);
+        self.depth -= 1;
+        result
+    }
+
+    fn execute_node(&mut self, stmt: &Stmt) -> Result<Value, EvalError> {
+        match stmt {
+            Stmt::Define { name, value, .. } => {
+                let value = self.eval(value)?;
+                self.define(name.clone(), value);
+                Ok(Value::Null)
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => match self.eval(condition)? {
+                Value::Bool(true) => self.execute(then_branch),
+                Value::Bool(false) => match else_branch {
+                    Some(else_branch) => self.execute(else_branch),
+                    None => Ok(Value::Null),
+                },
+                _ => Err(EvalError::TypeMismatch(condition.span())),
+            },
+            Stmt::Expr(expr, _) => self.eval(expr),
+            Stmt::Block(statements, _) => {
+                self.scopes.push(BTreeMap::new());
+                let result = statements
+                    .iter()
+                    .try_for_each(|statement| self.execute(statement).map(|_| ()));
+                self.scopes.pop();
+                result.map(|()| Value::Null)
+            }
+            Stmt::Error(span) => Err(EvalError::InvalidStatement(*span)),
+        }
+    }
+
+    /// Runs every statement in order, stopping at the first error.
+    pub fn run(&mut self, program: &[Stmt]) -> Result<(), EvalError> {
+        for statement in program {
+            self.execute(statement)?;
+        }
+        Ok(())
+    }
+
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        self.deepen(expr.span())?;
+        let result = self.eval_node(expr);
+        self.depth -= 1;
+        result
+    }
+
+    fn eval_node(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        match expr {
+            Expr::Literal(literal, _) => Ok(match literal {
+                Literal::Number(n) => Value::Number(*n),
+                Literal::String(s) => Value::String(s.clone()),
+                Literal::Bool(b) => Value::Bool(*b),
+                Literal::Null => Value::Null,
+            }),
+            Expr::Identifier(name, span) => self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| EvalError::UndefinedVariable(name.clone(), *span)),
+            Expr::Grouping(inner, _) => self.eval(inner),
+            Expr::Call { span, .. } => Err(EvalError::NotCallable(*span)),
+            Expr::Error(span) => Err(EvalError::InvalidExpression(*span)),
+            Expr::Unary { op, operand, span } => match (op, self.eval(operand)?) {
+                (Operator::Minus, Value::Number(n)) => Ok(Value::Number(n.wrapping_neg())),
+                (Operator::BitNot, Value::Number(n)) => Ok(Value::Number(!n)),
+                (Operator::Bang, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                _ => Err(EvalError::TypeMismatch(*span)),
+            },
+            Expr::Binary {
+                op: Operator::Coalesce,
+                left,
+                right,
+                ..
+            } => match self.eval(left)? {
+                Value::Null => self.eval(right),
+                value => Ok(value),
+            },
+            Expr::Binary {
+                op: op @ (Operator::And | Operator::Or),
+                left,
+                right,
+                span,
+            } => match (op, self.eval(left)?) {
+                (Operator::And, Value::Bool(false)) => Ok(Value::Bool(false)),
+                (Operator::Or, Value::Bool(true)) => Ok(Value::Bool(true)),
+                (_, Value::Bool(_)) => match self.eval(right)? {
+                    Value::Bool(b) => Ok(Value::Bool(b)),
+                    _ => Err(EvalError::TypeMismatch(*span)),
+                },
+                _ => Err(EvalError::TypeMismatch(*span)),
+            },
+            Expr::Binary {
+                op,
+                left,
+                right,
+                span,
+            } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(op, left, right, *span)
+            }
+        }
+    }
+}
+
+fn binary(op: &Operator, left: Value, right: Value, span: Span) -> Result<Value, EvalError> {
+    use Value::{Bool, Number, String};
+    Ok(match (op, left, right) {
+        (Operator::DoubleEquals, a, b) => Bool(a == b),
+        (Operator::NotEquals, a, b) => Bool(a != b),
+        (Operator::Plus, String(a), String(b)) => String(a + &b),
+        (Operator::Less, String(a), String(b)) => Bool(a < b),
+        (Operator::Greater, String(a), String(b)) => Bool(a > b),
+        (Operator::LessEqual, String(a), String(b)) => Bool(a <= b),
+        (Operator::GreaterEqual, String(a), String(b)) => Bool(a >= b),
+        (Operator::Slash | Operator::Mod, Number(_), Number(0)) => {
+            return Err(EvalError::DivisionByZero(span));
+        }
+        (op, Number(a), Number(b)) => match op {
+            Operator::Plus => Number(a.wrapping_add(b)),
+            Operator::Minus => Number(a.wrapping_sub(b)),
+            Operator::Star => Number(a.wrapping_mul(b)),
+            Operator::Slash => Number(a.wrapping_div(b)),
+            Operator::Mod => Number(a.wrapping_rem(b)),
+            Operator::Less => Bool(a < b),
+            Operator::Greater => Bool(a > b),
+            Operator::LessEqual => Bool(a <= b),
+            Operator::GreaterEqual => Bool(a >= b),
+            Operator::BitAnd => Number(a & b),
+            Operator::BitOr => Number(a | b),
+            Operator::BitXor => Number(a ^ b),
+            Operator::Shl => Number(a.wrapping_shl(b as u32)),
+            Operator::Shr => Number(a.wrapping_shr(b as u32)),
+            Operator::Custom(_) => return Err(EvalError::UnsupportedOperator(op.clone(), span)),
+            _ => return Err(EvalError::TypeMismatch(span)),
+        },
+        (Operator::Custom(_), _, _) => {
+            return Err(EvalError::UnsupportedOperator(op.clone(), span));
+        }
+        _ => return Err(EvalError::TypeMismatch(span)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer, Span,
+        eval::{Environment, EvalError, Value},
+        parser::{Stmt, parse, parse_program},
+    };
+
+    #[test]
+    fn evaluation() {
+        let lexer = Lexer::default();
+        let mut env = Environment::new();
+        let program = parse_program(
+            &lexer,
+            "define x = 7 * 6 - 2;
+             define s = \"a\" + \"b\";
+             if (x > 30 && !(s == \"ab\")) { define y = 1; } else if (x % 2 == 0) {
+                 define x = 0;
+                 define z = -x;
+             } else { define z = 2; }
+             define big = 2147483647 + 1;",
+        )
+        .unwrap();
+        env.run(&program).unwrap();
+        assert_eq!(env.get("x"), Some(&Value::Number(40)));
+        assert_eq!(env.get("s"), Some(&Value::String("ab".to_string())));
+        assert_eq!(env.get("z"), None);
+        assert_eq!(env.get("big"), Some(&Value::Number(i32::MIN)));
+        assert_eq!(
+            env.execute(&Stmt::Error(Span::new(0, 1))),
+            Err(EvalError::InvalidStatement(Span::new(0, 1)))
+        );
+
+        let mut eval = |source: &str| env.eval(&parse(&lexer, source).unwrap());
+        assert_eq!(eval("1 << 4 | 1"), Ok(Value::Number(17)));
+        assert_eq!(eval("false && nope"), Ok(Value::Bool(false)));
+        assert_eq!(eval("null ?? 1 + 2 ?? nope"), Ok(Value::Number(3)));
+        assert_eq!(eval("null != 0"), Ok(Value::Bool(true)));
+        assert_eq!(eval("\"b\" > s"), Ok(Value::Bool(true)));
+        assert_eq!(
+            eval("x / (x - 40)"),
+            Err(EvalError::DivisionByZero(Span::new(0, 12)))
+        );
+        assert_eq!(
+            eval("1 || true"),
+            Err(EvalError::TypeMismatch(Span::new(0, 9)))
+        );
+        assert_eq!(
+            eval("nope"),
+            Err(EvalError::UndefinedVariable(
+                "nope".to_string(),
+                Span::new(0, 4)
+            ))
+        );
+        assert_eq!(eval("f(1)"), Err(EvalError::NotCallable(Span::new(0, 4))));
+        assert_eq!(Value::String("q".to_string()).to_string(), "\"q\"");
+    }
+}
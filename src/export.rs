@@ -0,0 +1,459 @@
+//! A stable JSON interchange format for token streams.
+//!
+//! A stream is a JSON array with one object per token:
+//!
+//! ```text
+//! [
+//!   {"kind": "Keyword", "value": "define", "start": 0, "end": 6},
+//!   {"kind": "Identifier", "value": "x", "start": 7, "end": 8},
+//!   {"kind": "Operator", "value": "=", "start": 9, "end": 10},
+//!   {"kind": "Number", "value": 5, "start": 11, "end": 12},
+//!   {"kind": "Semicolon", "start": 12, "end": 13}
+//! ]
+//! ```
+//!
+//! `kind` is the name of the [`Type`] variant. `value` is present only for
+//! kinds with a payload: the literal text for `String`, `StringPart`,
+//! `Identifier`, `Whitespace`, `Comment`, `DocComment` and `Shebang`, the
+//! name for `Label`, `Directive` and `Decorator`, the character for
+//! `Unknown`, an integer for `Number` and the index of a `Symbol`, and the
+//! lexeme (such as `"define"` or `"=="`) for `Keyword` and `Operator`.
+//! `start` and `end` are the byte offsets of the token's span. Keys always
+//! appear in this order, and new kinds may be added but existing ones will
+//! not be renamed.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{KEYWORDS, Keyword, OPERATORS, Operator, Span, Symbol, Token, Type};
+
+const PUNCTUATION: [(&str, Type); 26] = [
+    ("LeftParen", Type::LeftParen),
+    ("RightParen", Type::RightParen),
+    ("LeftBrace", Type::LeftBrace),
+    ("RightBrace", Type::RightBrace),
+    ("Dot", Type::Dot),
+    ("Comma", Type::Comma),
+    ("Semicolon", Type::Semicolon),
+    ("LeftBracket", Type::LeftBracket),
+    ("RightBracket", Type::RightBracket),
+    ("Colon", Type::Colon),
+    ("DoubleColon", Type::DoubleColon),
+    ("Question", Type::Question),
+    ("QuestionDot", Type::QuestionDot),
+    ("Arrow", Type::Arrow),
+    ("FatArrow", Type::FatArrow),
+    ("StringStart", Type::StringStart),
+    ("StringEnd", Type::StringEnd),
+    ("InterpolationStart", Type::InterpolationStart),
+    ("InterpolationEnd", Type::InterpolationEnd),
+    ("AttributeStart", Type::AttributeStart),
+    ("AttributeEnd", Type::AttributeEnd),
+    ("Newline", Type::Newline),
+    ("Indent", Type::Indent),
+    ("Dedent", Type::Dedent),
+    ("Eof", Type::Eof),
+    ("None", Type::None),
+];
+
+/// Why [`from_json`] rejected its input, with the byte offset where it gave
+/// up.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JsonError {
+    /// The input is not well-formed JSON.
+    Syntax(usize),
+    /// The input is JSON but does not follow the token schema.
+    Schema(usize),
+}
+
+pub(crate) fn push_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// The `kind` of a token of type `token_type`. Kinds are never renamed, so
+/// they also identify kinds where a stable name is needed.
+pub(crate) fn kind_name(token_type: &Type) -> &'static str {
+    match token_type {
+        Type::String(_) => "String",
+        Type::Number(_) => "Number",
+        Type::Keyword(_) => "Keyword",
+        Type::Operator(_) => "Operator",
+        Type::Identifier(_) => "Identifier",
+        Type::Symbol(_) => "Symbol",
+        Type::Whitespace(_) => "Whitespace",
+        Type::Comment(_) => "Comment",
+        Type::DocComment(_) => "DocComment",
+        Type::Shebang(_) => "Shebang",
+        Type::StringPart(_) => "StringPart",
+        Type::Label(_) => "Label",
+        Type::Directive(_) => "Directive",
+        Type::Decorator(_) => "Decorator",
+        Type::Unknown(_) => "Unknown",
+        t => PUNCTUATION
+            .iter()
+            .find(|(_, p)| p == t)
+            .map_or("None", |(name, _)| name),
+    }
+}
+
+/// Serializes tokens using the schema described in the [module docs](self).
+pub fn to_json(tokens: &[Token]) -> String {
+    let mut out = String::from("[");
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"kind\":");
+        let kind = kind_name(&token.token_type);
+        push_string(&mut out, kind);
+        match &token.token_type {
+            Type::Number(n) => out.push_str(&format!(",\"value\":{}", n)),
+            Type::Symbol(symbol) => out.push_str(&format!(",\"value\":{}", symbol.index())),
+            Type::Keyword(_) | Type::Operator(_) | Type::Unknown(_) => {
+                out.push_str(",\"value\":");
+                push_string(&mut out, &token.token_type.to_string());
+            }
+            Type::String(s)
+            | Type::Identifier(s)
+            | Type::Whitespace(s)
+            | Type::Comment(s)
+            | Type::DocComment(s)
+            | Type::Shebang(s)
+            | Type::StringPart(s)
+            | Type::Label(s)
+            | Type::Directive(s)
+            | Type::Decorator(s) => {
+                out.push_str(",\"value\":");
+                push_string(&mut out, s);
+            }
+            _ => (),
+        }
+        out.push_str(&format!(
+            ",\"start\":{},\"end\":{}}}",
+            token.span.start, token.span.end
+        ));
+    }
+    out.push(']');
+    out
+}
+
+enum Value {
+    String(String),
+    Number(i64),
+    Other,
+    Array(Vec<(usize, Value)>),
+    Object(Vec<(String, Value)>),
+}
+
+/// How deeply arrays and objects may nest. Tokens are flat objects in a
+/// flat array, so anything deeper is rejected rather than recursed into.
+const MAX_DEPTH: usize = 16;
+
+struct Parser<'a> {
+    source: &'a str,
+    offset: usize,
+    /// The arrays and objects open at `offset`.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        match self.source[self.offset..].starts_with(c) {
+            true => {
+                self.offset += 1;
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(JsonError::Syntax(self.offset)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        let rest = &self.source[self.offset..];
+        if rest.starts_with('"') {
+            return self.string().map(Value::String);
+        }
+        if rest.starts_with(['[', '{']) {
+            if self.depth == MAX_DEPTH {
+                return Err(JsonError::Schema(self.offset));
+            }
+            self.depth += 1;
+            let value = self.nested();
+            self.depth -= 1;
+            return value;
+        }
+        for literal in ["true", "false", "null"] {
+            if rest.starts_with(literal) {
+                self.offset += literal.len();
+                return Ok(Value::Other);
+            }
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .unwrap_or(rest.len());
+        match rest[..len].parse() {
+            Ok(n) => {
+                self.offset += len;
+                Ok(Value::Number(n))
+            }
+            Err(_) => Err(JsonError::Syntax(self.offset)),
+        }
+    }
+
+    /// An array or object, which `value` found next.
+    fn nested(&mut self) -> Result<Value, JsonError> {
+        if self.eat('[') {
+            let mut items = Vec::new();
+            if !self.eat(']') {
+                loop {
+                    self.skip_whitespace();
+                    items.push((self.offset, self.value()?));
+                    if !self.eat(',') {
+                        break;
+                    }
+                }
+                self.expect(']')?;
+            }
+            return Ok(Value::Array(items));
+        }
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        if !self.eat('}') {
+            loop {
+                self.skip_whitespace();
+                let key = self.string()?;
+                self.expect(':')?;
+                fields.push((key, self.value()?));
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect('}')?;
+        }
+        Ok(Value::Object(fields))
+    }
+
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let digits = self.source.get(self.offset..self.offset + 4);
+        match digits.and_then(|d| u32::from_str_radix(d, 16).ok()) {
+            Some(n) => {
+                self.offset += 4;
+                Ok(n)
+            }
+            None => Err(JsonError::Syntax(self.offset)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let start = self.offset;
+            let Some(c) = self.source[self.offset..].chars().next() else {
+                return Err(JsonError::Syntax(start));
+            };
+            self.offset += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.source[self.offset..].chars().next();
+                    self.offset += 1;
+                    out.push(match escaped {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let mut code = self.hex()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.source[self.offset..].starts_with("\\u")
+                            {
+                                self.offset += 2;
+                                let low = self.hex()?;
+                                code =
+                                    0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            char::from_u32(code).ok_or(JsonError::Syntax(start))?
+                        }
+                        _ => return Err(JsonError::Syntax(start)),
+                    });
+                }
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+fn token_from_object(offset: usize, fields: Vec<(String, Value)>) -> Result<Token, JsonError> {
+    let mut kind = None;
+    let mut value = None;
+    let mut start = None;
+    let mut end = None;
+    for (key, field) in fields {
+        match (key.as_str(), field) {
+            ("kind", Value::String(s)) => kind = Some(s),
+            ("value", v) => value = Some(v),
+            ("start", Value::Number(n)) => start = usize::try_from(n).ok(),
+            ("end", Value::Number(n)) => end = usize::try_from(n).ok(),
+            _ => return Err(JsonError::Schema(offset)),
+        }
+    }
+    let (Some(kind), Some(start), Some(end)) = (kind, start, end) else {
+        return Err(JsonError::Schema(offset));
+    };
+    let token_type = match (kind.as_str(), value) {
+        ("Number", Some(Value::Number(n))) => {
+            Type::Number(i32::try_from(n).map_err(|_| JsonError::Schema(offset))?)
+        }
+        ("String", Some(Value::String(s))) => Type::String(s),
+        ("Identifier", Some(Value::String(s))) => Type::Identifier(s),
+        ("Symbol", Some(Value::Number(n))) => Type::Symbol(Symbol(
+            u32::try_from(n).map_err(|_| JsonError::Schema(offset))?,
+        )),
+        ("Whitespace", Some(Value::String(s))) => Type::Whitespace(s),
+        ("Comment", Some(Value::String(s))) => Type::Comment(s),
+        ("DocComment", Some(Value::String(s))) => Type::DocComment(s),
+        ("Shebang", Some(Value::String(s))) => Type::Shebang(s),
+        ("StringPart", Some(Value::String(s))) => Type::StringPart(s),
+        ("Label", Some(Value::String(s))) => Type::Label(s),
+        ("Directive", Some(Value::String(s))) => Type::Directive(s),
+        ("Decorator", Some(Value::String(s))) => Type::Decorator(s),
+        ("Unknown", Some(Value::String(s))) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Type::Unknown(c),
+                _ => return Err(JsonError::Schema(offset)),
+            }
+        }
+        ("Keyword", Some(Value::String(s))) => Type::Keyword(
+            KEYWORDS
+                .into_iter()
+                .find(|(word, _)| *word == s)
+                .map_or(Keyword::Custom(s), |(_, keyword)| keyword),
+        ),
+        ("Operator", Some(Value::String(s))) => Type::Operator(
+            OPERATORS
+                .into_iter()
+                .find(|op| op.to_string() == s)
+                .unwrap_or(Operator::Custom(s)),
+        ),
+        (name, None) => PUNCTUATION
+            .into_iter()
+            .find(|(p, _)| *p == name)
+            .map(|(_, t)| t)
+            .ok_or(JsonError::Schema(offset))?,
+        _ => return Err(JsonError::Schema(offset)),
+    };
+    Ok(Token::with_span(token_type, Span::new(start, end)))
+}
+
+/// Reads back a token stream written by [`to_json`] (or by any other tool
+/// following the schema in the [module docs](self)).
+pub fn from_json(json: &str) -> Result<Vec<Token>, JsonError> {
+    let mut parser = Parser {
+        source: json,
+        offset: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.offset != json.len() {
+        return Err(JsonError::Syntax(parser.offset));
+    }
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|(offset, item)| match item {
+                Value::Object(fields) => token_from_object(offset, fields),
+                _ => Err(JsonError::Schema(offset)),
+            })
+            .collect(),
+        _ => Err(JsonError::Schema(0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Interner, Keyword, Lexer, Span, Token, Type,
+        export::{JsonError, from_json, to_json},
+        lex_lossless, try_lex,
+    };
+
+    #[test]
+    fn json() {
+        let tokens = try_lex("define x = \"a\tb\";").unwrap();
+        let json = to_json(&tokens);
+        assert_eq!(
+            json,
+            concat!(
+                r#"[{"kind":"Keyword","value":"define","start":0,"end":6},"#,
+                r#"{"kind":"Identifier","value":"x","start":7,"end":8},"#,
+                r#"{"kind":"Operator","value":"=","start":9,"end":10},"#,
+                r#"{"kind":"String","value":"a\tb","start":11,"end":16},"#,
+                r#"{"kind":"Semicolon","start":16,"end":17}]"#
+            )
+        );
+        assert_eq!(from_json(&json), Ok(tokens));
+
+        let source = "/* \"é😀\" */ x -> y <=> 1\n";
+        let tokens = Lexer::builder()
+            .trivia(true)
+            .operator("<=>")
+            .keyword("y", Keyword::Custom("y".to_string()))
+            .build()
+            .lex(source)
+            .unwrap();
+        assert_eq!(from_json(&to_json(&tokens)), Ok(tokens));
+        let tokens = lex_lossless("\u{1}").unwrap();
+        assert_eq!(from_json(&to_json(&tokens)), Ok(tokens));
+        let tokens = Lexer::default()
+            .lex_interned("a b a", &mut Interner::new())
+            .unwrap();
+        assert!(to_json(&tokens).contains(r#"{"kind":"Symbol","value":1,"start":2,"end":3}"#));
+        assert_eq!(from_json(&to_json(&tokens)), Ok(tokens));
+
+        assert_eq!(
+            from_json(r#" [ {"kind": "Number", "value": 7, "start": 0, "end": 1} ] "#),
+            Ok(vec![Token::with_span(Type::Number(7), Span::new(0, 1))])
+        );
+        assert_eq!(
+            from_json(r#"[{"kind": "😀", "start": 0, "end": 1}]"#),
+            Err(JsonError::Schema(1))
+        );
+        assert_eq!(from_json("[{\"kind\": }]"), Err(JsonError::Syntax(10)));
+        assert_eq!(from_json(&"[".repeat(200_000)), Err(JsonError::Schema(16)));
+    }
+}
@@ -0,0 +1,287 @@
+//! Post-processing of lexed tokens in ordered stages.
+//!
+//! A [`TokenFilter`] rewrites the whole token list of one input. Filters
+//! added through [`crate::LexerBuilder::filter`] form the lexer's
+//! [`Pipeline`] and run in the order they were added, after lexing and before
+//! [`crate::Lexer::lex`] and [`crate::Lexer::lex_with_diagnostics`] return.
+//! Closures taking the tokens and the source are filters too, which covers
+//! one-off rewrites such as macro expansion.
+//!
+//! [`crate::Lexer::stream`], [`crate::Lexer::lex_observed`] and
+//! [`crate::trace`] report tokens as they are lexed, and
+//! `Lexer::lex_source` and `Lexer::lex_reader`, with the `std` feature,
+//! never hold the whole source, so they bypass the pipeline.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt;
+
+use crate::{Keyword, Span, Token, Type};
+
+/// One stage of a [`Pipeline`].
+pub trait TokenFilter: Send + Sync {
+    /// Rewrites `tokens`, which were lexed from `source`.
+    fn apply(&self, tokens: Vec<Token>, source: &str) -> Vec<Token>;
+}
+
+impl<F> TokenFilter for F
+where
+    F: Fn(Vec<Token>, &str) -> Vec<Token> + Send + Sync,
+{
+    fn apply(&self, tokens: Vec<Token>, source: &str) -> Vec<Token> {
+        self(tokens, source)
+    }
+}
+
+/// Filters run one after another, each on the output of the last.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Arc<dyn TokenFilter>>,
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stages", &self.stages.len())
+            .finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Adds `filter` as the last stage.
+    pub fn push(&mut self, filter: impl TokenFilter + 'static) {
+        self.stages.push(Arc::new(filter));
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn run(&self, tokens: Vec<Token>, source: &str) -> Vec<Token> {
+        self.stages
+            .iter()
+            .fold(tokens, |tokens, stage| stage.apply(tokens, source))
+    }
+}
+
+/// Drops whitespace, comments and shebang lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripTrivia;
+
+impl TokenFilter for StripTrivia {
+    fn apply(&self, mut tokens: Vec<Token>, _source: &str) -> Vec<Token> {
+        tokens.retain(|token| !token.is_trivia());
+        tokens
+    }
+}
+
+/// Joins string literals separated only by trivia into one literal spanning
+/// all of them, as in C's `"a" "b"`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConcatStrings;
+
+impl TokenFilter for ConcatStrings {
+    fn apply(&self, tokens: Vec<Token>, _source: &str) -> Vec<Token> {
+        let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+        // Trivia since the last significant token, dropped if it turns out to
+        // separate two strings.
+        let mut trivia = Vec::new();
+        for token in tokens {
+            if token.is_trivia() {
+                trivia.push(token);
+                continue;
+            }
+            if let (
+                Type::String(s),
+                Some(Token {
+                    token_type: Type::String(last),
+                    span,
+                }),
+            ) = (&token.token_type, out.last_mut())
+            {
+                last.push_str(s);
+                span.end = token.span.end;
+                trivia.clear();
+                continue;
+            }
+            out.append(&mut trivia);
+            out.push(token);
+        }
+        out.append(&mut trivia);
+        out
+    }
+}
+
+/// Inserts a `;` at the end of every line whose last token can end a
+/// statement (an identifier, literal or closing bracket), unless the next
+/// token is already a `;`, as JavaScript and Go do. The inserted tokens have
+/// empty spans at the end of the token they follow, before any
+/// [`Type::Newline`] marking the same line end.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InsertSemicolons;
+
+fn ends_statement(token_type: &Type) -> bool {
+    matches!(
+        token_type,
+        Type::Identifier(_)
+            | Type::Symbol(_)
+            | Type::Number(_)
+            | Type::String(_)
+            | Type::Keyword(Keyword::True | Keyword::False | Keyword::Null)
+            | Type::RightParen
+            | Type::RightBracket
+            | Type::RightBrace
+    )
+}
+
+impl TokenFilter for InsertSemicolons {
+    fn apply(&self, tokens: Vec<Token>, source: &str) -> Vec<Token> {
+        let mut out = Vec::with_capacity(tokens.len());
+        for (i, token) in tokens.iter().enumerate() {
+            out.push(token.clone());
+            if token.is_trivia() || !ends_statement(&token.token_type) {
+                continue;
+            }
+            let insert = match tokens[i + 1..]
+                .iter()
+                .find(|t| !t.is_trivia() && t.token_type != Type::Newline)
+            {
+                Some(next) => {
+                    next.token_type != Type::Semicolon
+                        && source
+                            .get(token.span.end..next.span.start)
+                            .is_some_and(|between| between.contains('\n'))
+                }
+                None => true,
+            };
+            if insert {
+                let end = token.span.end;
+                out.push(Token::with_span(Type::Semicolon, Span::new(end, end)));
+            }
+        }
+        out
+    }
+}
+
+/// Inserts a [`Type::Newline`] after the last significant token of every
+/// line, so that line ends survive for languages where they end statements.
+/// The inserted tokens have empty spans at the end of the token they follow.
+/// Layout mode already marks line ends; see [`crate::LexerOptions::layout`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InsertNewlines;
+
+impl TokenFilter for InsertNewlines {
+    fn apply(&self, tokens: Vec<Token>, source: &str) -> Vec<Token> {
+        let mut out = Vec::with_capacity(tokens.len());
+        for (i, token) in tokens.iter().enumerate() {
+            out.push(token.clone());
+            if token.is_trivia() || token.token_type == Type::Newline {
+                continue;
+            }
+            let insert = match tokens[i + 1..].iter().find(|t| !t.is_trivia()) {
+                Some(next) => {
+                    next.token_type != Type::Newline
+                        && source
+                            .get(token.span.end..next.span.start)
+                            .is_some_and(|between| between.contains('\n'))
+                }
+                None => true,
+            };
+            if insert {
+                let end = token.span.end;
+                out.push(Token::with_span(Type::Newline, Span::new(end, end)));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        LexEvent, Lexer, Span, Token, Type, detokenize,
+        filter::{ConcatStrings, InsertNewlines, InsertSemicolons, Pipeline, StripTrivia},
+    };
+
+    #[test]
+    fn pipeline() {
+        let lexer = Lexer::builder()
+            .trivia(true)
+            .filter(ConcatStrings)
+            .filter(StripTrivia)
+            .filter(InsertSemicolons)
+            .filter(|tokens: Vec<Token>, _: &str| {
+                tokens
+                    .into_iter()
+                    .map(|token| match token.token_type {
+                        Type::Identifier(name) if name == "ANSWER" => {
+                            Token::with_span(Type::Number(42), token.span)
+                        }
+                        token_type => Token::with_span(token_type, token.span),
+                    })
+                    .collect()
+            })
+            .build();
+        let source = "x = \"a\" /* c */\n  \"b\"\nf(ANSWER);\ny = [1,\n 2]";
+        let tokens = lexer.lex(source).unwrap();
+        assert_eq!(
+            detokenize(&tokens),
+            "x = \"ab\" ; f ( 42 ) ; y = [ 1 , 2 ] ;"
+        );
+        assert_eq!(
+            &source[tokens[2].span.start..tokens[2].span.end],
+            "\"a\" /* c */\n  \"b\""
+        );
+        assert_eq!(tokens[3].span.start, tokens[3].span.end);
+        assert_eq!(
+            lexer.lex_with_diagnostics(source).tokens,
+            lexer.lex(source).unwrap()
+        );
+        assert!(
+            lexer
+                .stream(source)
+                .any(|event| matches!(event, LexEvent::Token(token) if token.is_trivia()))
+        );
+
+        let mut pipeline = Pipeline::new();
+        assert!(pipeline.is_empty());
+        pipeline.push(ConcatStrings);
+        assert_eq!(pipeline.len(), 1);
+        let tokens = Lexer::builder()
+            .trivia(true)
+            .build()
+            .lex("\"a\" b \"c\"")
+            .unwrap();
+        assert_eq!(detokenize(&pipeline.run(tokens, "")), "\"a\" b \"c\"");
+    }
+
+    #[test]
+    fn newlines() {
+        let source = "x = f(1,\n 2) /* a\n b */\n\n\"\"\"s\nt\"\"\" y\n";
+        let lexer = Lexer::builder()
+            .multiline_strings(true)
+            .filter(InsertNewlines)
+            .build();
+        let tokens = lexer.lex(source).unwrap();
+        assert_eq!(
+            detokenize(&tokens),
+            "x = f ( 1 , \n 2 ) \n \"\"\"s\nt\"\"\" y \n"
+        );
+        assert_eq!(tokens[6].span, Span::new(8, 8));
+        let semicolons = Lexer::builder()
+            .filter(InsertNewlines)
+            .filter(InsertSemicolons)
+            .build();
+        assert_eq!(
+            detokenize(&semicolons.lex("a\nb +\nc").unwrap()),
+            "a ; \n b + \n c ; \n"
+        );
+    }
+}
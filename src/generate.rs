@@ -0,0 +1,241 @@
+//! Deterministic generation of plausible source text, for benchmarking and
+//! stress-testing the lexer on inputs of a chosen size and token mix.
+
+use alloc::string::{String, ToString};
+
+/// The token mix [`gen_source`] aims for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Profile {
+    /// A bit of everything, roughly like hand-written code.
+    Mixed,
+    /// Mostly identifiers, stressing identifier scanning and keyword lookup.
+    Identifiers,
+    /// Long operator chains over short operands.
+    Operators,
+    /// Mostly string literals.
+    Strings,
+    /// Mostly (nested) block comments.
+    Comments,
+}
+
+impl Profile {
+    /// Relative weights of identifier, number, string and parenthesized
+    /// operands; the maximum operator chain length; and the chance out of 100
+    /// that a statement is a comment.
+    fn weights(self) -> ([u32; 4], u32, u32) {
+        match self {
+            Profile::Mixed => ([4, 3, 2, 1], 3, 10),
+            Profile::Identifiers => ([12, 1, 1, 0], 2, 2),
+            Profile::Operators => ([2, 4, 0, 2], 12, 2),
+            Profile::Strings => ([1, 1, 10, 0], 2, 2),
+            Profile::Comments => ([3, 2, 1, 0], 2, 70),
+        }
+    }
+}
+
+const WORDS: [&str; 16] = [
+    "alpha", "beta", "count", "delta", "index", "item", "left", "limit", "name", "node", "right",
+    "size", "total", "value", "width", "x",
+];
+
+const OPERATORS: [&str; 12] = [
+    "+", "-", "*", "/", "%", "==", "!=", "<", ">=", "&&", "||", "<<",
+];
+
+/// A SplitMix64 generator: tiny, fast, and identical on every platform.
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next() % n as u64) as u32
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u32) as usize]
+    }
+
+    fn weighted(&mut self, weights: &[u32]) -> usize {
+        let mut roll = self.below(weights.iter().sum());
+        for (i, &weight) in weights.iter().enumerate() {
+            if roll < weight {
+                return i;
+            }
+            roll -= weight;
+        }
+        weights.len() - 1
+    }
+}
+
+struct Generator {
+    rng: Rng,
+    profile: Profile,
+    out: String,
+}
+
+impl Generator {
+    fn identifier(&mut self) {
+        let word = self.rng.pick(&WORDS);
+        self.out.push_str(word);
+        if self.rng.below(3) == 0 {
+            self.out.push_str(&self.rng.below(100).to_string());
+        }
+    }
+
+    fn operand(&mut self, depth: u32) {
+        let (weights, _, _) = self.profile.weights();
+        let weights = match depth {
+            0 => [weights[0], weights[1], weights[2], 0],
+            _ => weights,
+        };
+        match self.rng.weighted(&weights) {
+            0 => self.identifier(),
+            1 => {
+                let n = self.rng.below(100_000);
+                self.out.push_str(&n.to_string());
+            }
+            2 => {
+                self.out.push('"');
+                for i in 0..1 + self.rng.below(4) {
+                    if i > 0 {
+                        self.out.push(' ');
+                    }
+                    let word = self.rng.pick(&WORDS);
+                    self.out.push_str(word);
+                }
+                self.out.push('"');
+            }
+            _ => {
+                self.out.push('(');
+                self.expression(depth - 1);
+                self.out.push(')');
+            }
+        }
+    }
+
+    fn expression(&mut self, depth: u32) {
+        let (_, chain, _) = self.profile.weights();
+        self.operand(depth);
+        for _ in 0..self.rng.below(chain + 1) {
+            self.out.push(' ');
+            let op = self.rng.pick(&OPERATORS);
+            self.out.push_str(op);
+            self.out.push(' ');
+            self.operand(depth);
+        }
+    }
+
+    fn comment(&mut self, depth: u32) {
+        self.out.push_str("/* ");
+        for _ in 0..1 + self.rng.below(8) {
+            let word = self.rng.pick(&WORDS);
+            self.out.push_str(word);
+            self.out.push(' ');
+        }
+        if depth > 0 && self.rng.below(4) == 0 {
+            self.comment(depth - 1);
+            self.out.push(' ');
+        }
+        self.out.push_str("*/");
+    }
+
+    fn statement(&mut self) {
+        let (_, _, comments) = self.profile.weights();
+        if self.rng.below(100) < comments {
+            self.comment(2);
+            self.out.push('\n');
+            return;
+        }
+        match self.rng.below(4) {
+            0 => {
+                self.out.push_str("if (");
+                self.expression(2);
+                self.out.push_str(") {\n    define ");
+                self.identifier();
+                self.out.push_str(" = ");
+                self.expression(2);
+                self.out.push_str(";\n}\n");
+            }
+            1 => {
+                self.expression(2);
+                self.out.push_str(";\n");
+            }
+            _ => {
+                self.out.push_str("define ");
+                self.identifier();
+                self.out.push_str(" = ");
+                self.expression(2);
+                self.out.push_str(";\n");
+            }
+        }
+    }
+}
+
+/// Generates at least `size` bytes of source that lexes without errors,
+/// stopping at the first statement boundary past `size`. The same `seed`,
+/// `size` and `profile` always produce the same text.
+pub fn gen_source(seed: u64, size: usize, profile: Profile) -> String {
+    let mut generator = Generator {
+        rng: Rng(seed),
+        profile,
+        out: String::with_capacity(size + 256),
+    };
+    while generator.out.len() < size {
+        generator.statement();
+    }
+    generator.out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        TokenKind,
+        generate::{Profile, gen_source},
+        try_lex,
+    };
+
+    #[test]
+    fn generated_source() {
+        assert_eq!(
+            gen_source(7, 1000, Profile::Mixed),
+            gen_source(7, 1000, Profile::Mixed)
+        );
+        assert_ne!(
+            gen_source(7, 1000, Profile::Mixed),
+            gen_source(8, 1000, Profile::Mixed)
+        );
+        assert_eq!(gen_source(1, 0, Profile::Mixed), "");
+        let count = |profile, kind| {
+            let source = gen_source(42, 20_000, profile);
+            assert!(source.len() >= 20_000);
+            try_lex(&source)
+                .unwrap()
+                .iter()
+                .filter(|t| t.kind() == kind)
+                .count()
+        };
+        for profile in [
+            Profile::Mixed,
+            Profile::Identifiers,
+            Profile::Operators,
+            Profile::Strings,
+            Profile::Comments,
+        ] {
+            assert_eq!(count(profile, TokenKind::None), 0);
+        }
+        assert!(
+            count(Profile::Strings, TokenKind::String) > count(Profile::Mixed, TokenKind::String)
+        );
+        assert!(
+            count(Profile::Identifiers, TokenKind::Identifier)
+                > count(Profile::Operators, TokenKind::Identifier)
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! A plain-text description of the lexical grammar, for keeping a lexer
+//! configuration in sync with tooling written in other languages.
+//!
+//! A grammar file lists one directive per line; blank lines and lines
+//! starting with `#` are ignored:
+//!
+//! ```text
+//! # identifiers are ascii or unicode (the default)
+//! identifiers ascii
+//! keyword define
+//! keyword if
+//! operator +
+//! operator <=>
+//! ```
+//!
+//! `keyword` and `operator` lines name every reserved word and every
+//! operator of the language, built-in ones included. Brackets and other
+//! punctuation are fixed and are not listed.
+
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{Lexer, LexerBuilder, LexerOptions, OPERATORS};
+
+/// Why [`Grammar::parse`] rejected its input, with the 1-based line number.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GrammarError {
+    /// The line does not start with a known directive.
+    UnknownDirective(usize),
+    /// The directive is missing its argument or has an invalid one.
+    InvalidValue(usize),
+}
+
+/// A difference between a [`Grammar`] and a lexer configuration.
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub enum Mismatch {
+    /// The grammar reserves a word the lexer does not.
+    MissingKeyword(String),
+    /// The lexer reserves a word the grammar does not.
+    ExtraKeyword(String),
+    /// The grammar has an operator the lexer does not recognize.
+    MissingOperator(String),
+    /// The lexer recognizes an operator the grammar does not have.
+    ExtraOperator(String),
+    /// The grammar and the lexer disagree on ASCII-only identifiers; carries
+    /// the grammar's setting.
+    Identifiers { ascii: bool },
+}
+
+/// A parsed grammar file, in the format described in the
+/// [module docs](self).
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Grammar {
+    pub keywords: BTreeSet<String>,
+    pub operators: BTreeSet<String>,
+    pub ascii_identifiers: bool,
+}
+
+impl Grammar {
+    pub fn parse(source: &str) -> Result<Grammar, GrammarError> {
+        let mut grammar = Grammar::default();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (directive, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                return Err(match directive {
+                    "keyword" | "operator" | "identifiers" => GrammarError::InvalidValue(i + 1),
+                    _ => GrammarError::UnknownDirective(i + 1),
+                });
+            }
+            match directive {
+                "keyword" => {
+                    grammar.keywords.insert(value.to_string());
+                }
+                "operator" => {
+                    grammar.operators.insert(value.to_string());
+                }
+                "identifiers" => {
+                    grammar.ascii_identifiers = match value {
+                        "ascii" => true,
+                        "unicode" => false,
+                        _ => return Err(GrammarError::InvalidValue(i + 1)),
+                    }
+                }
+                _ => return Err(GrammarError::UnknownDirective(i + 1)),
+            }
+        }
+        Ok(grammar)
+    }
+
+    /// Every way `options` differs from this grammar, sorted; empty when
+    /// they agree.
+    pub fn check(&self, options: &LexerOptions) -> Vec<Mismatch> {
+        let keywords = options
+            .keywords
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<String>>();
+        let operators = OPERATORS
+            .iter()
+            .map(|op| op.to_string())
+            .chain(options.operators.iter().cloned())
+            .collect::<BTreeSet<String>>();
+        let mut mismatches = Vec::new();
+        mismatches.extend(
+            self.keywords
+                .difference(&keywords)
+                .cloned()
+                .map(Mismatch::MissingKeyword),
+        );
+        mismatches.extend(
+            keywords
+                .difference(&self.keywords)
+                .cloned()
+                .map(Mismatch::ExtraKeyword),
+        );
+        mismatches.extend(
+            self.operators
+                .difference(&operators)
+                .cloned()
+                .map(Mismatch::MissingOperator),
+        );
+        mismatches.extend(
+            operators
+                .difference(&self.operators)
+                .cloned()
+                .map(Mismatch::ExtraOperator),
+        );
+        if self.ascii_identifiers != options.ascii_identifiers {
+            mismatches.push(Mismatch::Identifiers {
+                ascii: self.ascii_identifiers,
+            });
+        }
+        mismatches.sort();
+        mismatches
+    }
+}
+
+impl LexerBuilder {
+    /// Builds the lexer only if its configuration matches `grammar`
+    /// exactly, returning every mismatch otherwise.
+    pub fn build_conforming(self, grammar: &Grammar) -> Result<Lexer, Vec<Mismatch>> {
+        let lexer = self.build();
+        let mismatches = grammar.check(lexer.options());
+        match mismatches.is_empty() {
+            true => Ok(lexer),
+            false => Err(mismatches),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        KEYWORDS, Keyword, Lexer, OPERATORS,
+        grammar::{Grammar, GrammarError, Mismatch},
+    };
+
+    #[test]
+    fn conformance() {
+        let mut source = String::from("# test grammar\nidentifiers ascii\n\n");
+        for (word, _) in KEYWORDS {
+            source.push_str(&format!("keyword {}\n", word));
+        }
+        for op in OPERATORS {
+            source.push_str(&format!("operator {}\n", op));
+        }
+        source.push_str("operator <=>\nkeyword unless\n");
+        let grammar = Grammar::parse(&source).unwrap();
+        assert!(
+            Lexer::builder()
+                .ascii_identifiers(true)
+                .operator("<=>")
+                .keyword("unless", Keyword::Custom("unless".to_string()))
+                .build_conforming(&grammar)
+                .is_ok()
+        );
+        assert_eq!(
+            Lexer::builder()
+                .operator("**")
+                .remove_keyword("let")
+                .build_conforming(&grammar)
+                .unwrap_err(),
+            vec![
+                Mismatch::MissingKeyword("let".to_string()),
+                Mismatch::MissingKeyword("unless".to_string()),
+                Mismatch::MissingOperator("<=>".to_string()),
+                Mismatch::ExtraOperator("**".to_string()),
+                Mismatch::Identifiers { ascii: true },
+            ]
+        );
+        assert_eq!(
+            Grammar::parse("keyword if\ntoken x"),
+            Err(GrammarError::UnknownDirective(2))
+        );
+        assert_eq!(
+            Grammar::parse("operator"),
+            Err(GrammarError::InvalidValue(1))
+        );
+        assert_eq!(
+            Grammar::parse("identifiers latin1"),
+            Err(GrammarError::InvalidValue(1))
+        );
+    }
+}
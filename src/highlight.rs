@@ -0,0 +1,184 @@
+//! Syntax highlighting built on the lossless token stream, so everything
+//! between tokens (whitespace, comments, even input that failed to lex) is
+//! reproduced exactly.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{Lexer, Span, Token, TokenCategory, Type};
+
+/// How a stretch of source is highlighted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Style {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+    /// Input that failed to lex or that the lexer has no rule for.
+    Error,
+}
+
+impl Style {
+    fn of(token: &Token) -> Style {
+        match &token.token_type {
+            Type::Keyword(_) | Type::Directive(_) | Type::Decorator(_) => Style::Keyword,
+            Type::String(_) | Type::StringPart(_) | Type::StringStart | Type::StringEnd => {
+                Style::String
+            }
+            Type::Number(_) => Style::Number,
+            Type::Comment(_) | Type::Shebang(_) | Type::DocComment(_) => Style::Comment,
+            _ if token.category() == TokenCategory::Error => Style::Error,
+            _ => Style::Plain,
+        }
+    }
+
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Keyword => Some("\x1b[1m"),
+            Style::String => Some("\x1b[32m"),
+            Style::Number => Some("\x1b[36m"),
+            Style::Comment => Some("\x1b[2m"),
+            Style::Error => Some("\x1b[31m"),
+        }
+    }
+
+    fn class(self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Keyword => Some("kw"),
+            Style::String => Some("str"),
+            Style::Number => Some("num"),
+            Style::Comment => Some("comment"),
+            Style::Error => Some("err"),
+        }
+    }
+}
+
+/// Splits `source` into consecutive styled spans that cover all of it,
+/// lexing with `lexer`'s options plus trivia.
+pub fn segments(lexer: &Lexer, source: &str) -> Vec<(Span, Style)> {
+    let mut options = lexer.options().clone();
+    options.trivia = true;
+    let mut segments = Vec::new();
+    let mut end = 0;
+    for token in Lexer::new(options).lex_with_diagnostics(source).tokens {
+        if token.span.start > end {
+            segments.push((Span::new(end, token.span.start), Style::Error));
+        }
+        segments.push((token.span, Style::of(&token)));
+        end = token.span.end;
+    }
+    if end < source.len() {
+        segments.push((Span::new(end, source.len()), Style::Error));
+    }
+    segments
+}
+
+/// Re-emits `source` with ANSI escape codes: keywords bold, strings green,
+/// numbers cyan, comments dim and errors red.
+pub fn to_ansi(source: &str) -> String {
+    render_ansi(&Lexer::default(), source)
+}
+
+/// Like [`to_ansi`], lexing with `lexer`'s options.
+pub fn render_ansi(lexer: &Lexer, source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    for (span, style) in segments(lexer, source) {
+        let text = &source[span.start..span.end];
+        match style.ansi() {
+            Some(code) => {
+                out.push_str(code);
+                out.push_str(text);
+                out.push_str("\x1b[0m");
+            }
+            None => out.push_str(text),
+        }
+    }
+    out
+}
+
+/// Re-emits `source` as HTML, wrapping styled stretches in
+/// `<span class="...">` with the classes `kw`, `str`, `num`, `comment` and
+/// `err`. Text is escaped, so the result can be placed inside a `<pre>`.
+pub fn to_html(source: &str) -> String {
+    render_html(&Lexer::default(), source)
+}
+
+/// Like [`to_html`], lexing with `lexer`'s options.
+pub fn render_html(lexer: &Lexer, source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    for (span, style) in segments(lexer, source) {
+        let text = &source[span.start..span.end];
+        match style.class() {
+            Some(class) => {
+                out.push_str("<span class=\"");
+                out.push_str(class);
+                out.push_str("\">");
+                escape_html(text, &mut out);
+                out.push_str("</span>");
+            }
+            None => escape_html(text, &mut out),
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer, Span,
+        highlight::{Style, render_ansi, render_html, segments, to_ansi, to_html},
+    };
+
+    #[test]
+    fn ansi() {
+        let lexer = Lexer::default();
+        assert_eq!(
+            to_ansi("if x == 1 {\n\t\"s\" /* c */ $\n\"open"),
+            concat!(
+                "\x1b[1mif\x1b[0m x == \x1b[36m1\x1b[0m {\n\t\x1b[32m\"s\"\x1b[0m ",
+                "\x1b[2m/* c */\x1b[0m \x1b[31m$\x1b[0m\n\x1b[31m\"open\x1b[0m"
+            )
+        );
+        assert_eq!(
+            segments(&lexer, "x \"a\n1"),
+            vec![
+                (Span::new(0, 1), Style::Plain),
+                (Span::new(1, 2), Style::Plain),
+                (Span::new(2, 5), Style::Error),
+                (Span::new(5, 6), Style::Number),
+            ]
+        );
+        let lexer = Lexer::builder().remove_keyword("if").build();
+        assert_eq!(render_ansi(&lexer, "if"), "if");
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(
+            to_html("if a < b && \"<&>\" /* 'c' */ 1 $"),
+            concat!(
+                "<span class=\"kw\">if</span> a &lt; b &amp;&amp; ",
+                "<span class=\"str\">&quot;&lt;&amp;&gt;&quot;</span> ",
+                "<span class=\"comment\">/* &#39;c&#39; */</span> ",
+                "<span class=\"num\">1</span> <span class=\"err\">$</span>"
+            )
+        );
+        let lexer = Lexer::builder().remove_keyword("if").build();
+        assert_eq!(render_html(&lexer, "if"), "if");
+    }
+}
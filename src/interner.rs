@@ -0,0 +1,106 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::fmt;
+
+use crate::{LexError, Lexer, Token, Type};
+
+/// An identifier interned by an [`Interner`], which gives back its text.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Symbol(pub(crate) u32);
+
+impl Symbol {
+    /// The position of this symbol among those of its interner, counting
+    /// from 0 in the order they were interned, for use as a table index.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Stores each distinct identifier once, handing out a [`Symbol`] for it.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    symbols: BTreeMap<String, Symbol>,
+    names: Vec<String>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// The symbol of `name`, interning it if it is new.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.names.len()).expect("too many symbols"));
+        self.symbols.insert(String::from(name), symbol);
+        self.names.push(String::from(name));
+        symbol
+    }
+
+    /// The symbol of `name`, if it has been interned.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// The text of `symbol`, or `None` for a symbol of another interner.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.names.get(symbol.index()).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Lexer {
+    /// Like [`Lexer::lex`], with every [`Type::Identifier`] replaced by a
+    /// [`Type::Symbol`] interned in `interner`, so a name repeated across the
+    /// source is stored once. Lexing several sources with one interner gives
+    /// a name the same symbol in all of them.
+    pub fn lex_interned(&self, s: &str, interner: &mut Interner) -> Result<Vec<Token>, LexError> {
+        let mut tokens = self.lex(s)?;
+        for token in &mut tokens {
+            if let Type::Identifier(name) = &token.token_type {
+                token.token_type = Type::Symbol(interner.intern(name));
+            }
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Interner, Lexer, Symbol, TokenKind, Type};
+
+    #[test]
+    fn lex_interned() {
+        let mut interner = Interner::new();
+        let lexer = Lexer::default();
+        let tokens = lexer.lex_interned("x = y + x", &mut interner).unwrap();
+        assert_eq!(tokens[0].token_type, Type::Symbol(Symbol(0)));
+        assert_eq!(tokens[2].token_type, Type::Symbol(Symbol(1)));
+        assert_eq!(tokens[4].token_type, tokens[0].token_type);
+        assert_eq!(tokens[0].kind(), TokenKind::Identifier);
+        assert!(tokens[0].is_identifier());
+        assert_eq!(interner.len(), 2);
+
+        let more = lexer.lex_interned("define z = y", &mut interner).unwrap();
+        assert_eq!(more[3].token_type, Type::Symbol(Symbol(1)));
+        assert_eq!(interner.resolve(Symbol(2)), Some("z"));
+        assert_eq!(interner.resolve(Symbol(3)), None);
+        assert_eq!(interner.get("x"), Some(Symbol(0)));
+        assert_eq!(interner.get("w"), None);
+        assert!(lexer.lex_interned("\"open", &mut interner).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Significant indentation, as in Python: with [`LexerOptions::layout`] set,
+//! the lexed tokens are rewritten so that block structure shows up as tokens.
+//!
+//! Every line holding a significant token ends in a [`Type::Newline`]. A line
+//! indented past the line before it opens a block with a [`Type::Indent`],
+//! and a line indented less closes each block it leaves with a
+//! [`Type::Dedent`]; the blocks still open at the end of input are closed
+//! there. Line breaks inside brackets, and lines holding only trivia, do not
+//! count. The tokens are synthetic and have empty spans: a newline at the end
+//! of the token it follows, an indent or dedent at the start of the token it
+//! precedes.
+//!
+//! A line's indentation is compared character by character with the blocks
+//! it is in, so a line must start with the exact whitespace of its block:
+//! tabs and spaces are never converted into each other.
+//!
+//! [`LexerOptions::layout`]: crate::LexerOptions::layout
+
+use alloc::{vec, vec::Vec};
+
+use crate::{LexError, Span, Token, Type};
+
+/// Inserts the layout tokens into `tokens`, which were lexed from `source`,
+/// pushing an error for every line whose indentation does not fit.
+pub(crate) fn layout(tokens: Vec<Token>, source: &str, errors: &mut Vec<LexError>) -> Vec<Token> {
+    let mut out = Vec::with_capacity(tokens.len());
+    // The indentation of the blocks open, innermost last.
+    let mut blocks: Vec<&str> = vec![""];
+    let mut depth = 0usize;
+    // Where the last significant token ended, once there is one.
+    let mut last_end = None;
+    let mut pending = Vec::new();
+    for token in tokens {
+        if token.is_trivia() {
+            pending.push(token);
+            continue;
+        }
+        let start = token.span.start;
+        match last_end {
+            Some(end) if depth == 0 && source[end..start].contains('\n') => {
+                out.push(synthetic(Type::Newline, end));
+                out.append(&mut pending);
+                let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+                let line = &source[line_start..];
+                let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+                indent_to(indent, start, &mut blocks, &mut out, errors);
+            }
+            _ => out.append(&mut pending),
+        }
+        match token.token_type {
+            Type::LeftParen | Type::LeftBracket | Type::LeftBrace => depth += 1,
+            Type::RightParen | Type::RightBracket | Type::RightBrace => {
+                depth = depth.saturating_sub(1)
+            }
+            _ => (),
+        }
+        last_end = Some(token.span.end);
+        out.push(token);
+    }
+    if let Some(end) = last_end {
+        out.push(synthetic(Type::Newline, end));
+    }
+    out.append(&mut pending);
+    for _ in 1..blocks.len() {
+        out.push(synthetic(Type::Dedent, source.len()));
+    }
+    out
+}
+
+/// Opens or closes blocks for a line indented by `indent` whose first token
+/// starts at `at`.
+fn indent_to<'a>(
+    indent: &'a str,
+    at: usize,
+    blocks: &mut Vec<&'a str>,
+    out: &mut Vec<Token>,
+    errors: &mut Vec<LexError>,
+) {
+    let current = blocks[blocks.len() - 1];
+    if indent == current {
+        return;
+    }
+    if indent.starts_with(current) {
+        blocks.push(indent);
+        out.push(synthetic(Type::Indent, at));
+        return;
+    }
+    if !current.starts_with(indent) {
+        errors.push(LexError::MixedIndentation(at));
+        return;
+    }
+    while blocks[blocks.len() - 1].len() > indent.len() {
+        blocks.pop();
+        out.push(synthetic(Type::Dedent, at));
+    }
+    if blocks[blocks.len() - 1] != indent {
+        errors.push(LexError::UnalignedDedent(at));
+    }
+}
+
+fn synthetic(token_type: Type, at: usize) -> Token {
+    Token::with_span(token_type, Span::new(at, at))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LexError, Lexer, Span, Token, Type};
+
+    fn types(tokens: &[Token]) -> Vec<Type> {
+        tokens.iter().map(|t| t.token_type.clone()).collect()
+    }
+
+    #[test]
+    fn layout() {
+        let lexer = Lexer::builder().layout(true).build();
+        let source = "if x\n  f(a,\nb)\n\n  /* c */\n  if y\n    g\nh\n";
+        let tokens = lexer.lex(source).unwrap();
+        let id = |s: &str| Type::Identifier(s.to_string());
+        assert_eq!(
+            types(&tokens)
+                .into_iter()
+                .filter(|t| !matches!(t, Type::LeftParen | Type::RightParen | Type::Comma))
+                .collect::<Vec<Type>>(),
+            vec![
+                Type::Keyword(crate::Keyword::If),
+                id("x"),
+                Type::Newline,
+                Type::Indent,
+                id("f"),
+                id("a"),
+                id("b"),
+                Type::Newline,
+                Type::Keyword(crate::Keyword::If),
+                id("y"),
+                Type::Newline,
+                Type::Indent,
+                id("g"),
+                Type::Newline,
+                Type::Dedent,
+                Type::Dedent,
+                id("h"),
+                Type::Newline,
+            ]
+        );
+        assert_eq!(tokens[2].span, Span::new(4, 4));
+        assert_eq!(tokens[3].span, Span::new(7, 7));
+        assert!(Lexer::default().lex(source).unwrap().len() < tokens.len());
+
+        let tokens = lexer.lex("a\n\tb").unwrap();
+        assert_eq!(tokens.last().unwrap().token_type, Type::Dedent);
+        assert_eq!(tokens.last().unwrap().span, Span::new(4, 4));
+    }
+
+    #[test]
+    fn inconsistent_indentation() {
+        let lexer = Lexer::builder().layout(true).build();
+        assert_eq!(
+            lexer.lex_all_errors("a\n\tb\n  c\n").1,
+            vec![LexError::MixedIndentation(7)]
+        );
+        assert_eq!(
+            lexer.lex_all_errors("a\n    b\n  c\nd").1,
+            vec![LexError::UnalignedDedent(10)]
+        );
+        let trivia = Lexer::builder().layout(true).trivia(true).build();
+        let source = "a /* x\n */\n  b /* c */\n";
+        let tokens: Vec<Token> = trivia
+            .lex(source)
+            .unwrap()
+            .into_iter()
+            .filter(|t| !t.is_trivia())
+            .collect();
+        assert_eq!(tokens, lexer.lex(source).unwrap());
+        assert_eq!(
+            types(&tokens),
+            vec![
+                Type::Identifier("a".to_string()),
+                Type::Newline,
+                Type::Indent,
+                Type::Identifier("b".to_string()),
+                Type::Newline,
+                Type::Dedent,
+            ]
+        );
+    }
+}
@@ -1,31 +1,199 @@
-use std::{fmt, iter::Peekable, str::Chars};
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-const KEYWORDS: [(&'static str, Keyword); 5] = [
+extern crate alloc;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    fmt, format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+pub mod analyze;
+mod bytes;
+mod compact;
+pub mod core;
+pub mod csv;
+pub mod diagnostics;
+pub mod diff;
+pub mod eval;
+pub mod export;
+pub mod filter;
+pub mod generate;
+pub mod grammar;
+pub mod highlight;
+mod interner;
+mod layout;
+mod line_index;
+pub mod lint;
+mod macros;
+mod metadata;
+pub mod mode;
+#[cfg(feature = "std")]
+mod parallel;
+pub mod parser;
+pub mod pragma;
+#[cfg(feature = "std")]
+mod reader;
+mod relex;
+#[cfg(feature = "std")]
+pub mod repl;
+pub mod repro;
+pub mod semantic;
+mod source_file;
+pub mod sourcemap;
+mod token_stream;
+pub mod trace;
+mod viewport;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use compact::CompactToken;
+pub use interner::{Interner, Symbol};
+pub use line_index::{LineCol, LineIndex};
+#[doc(hidden)]
+pub use macros::__token_vec;
+pub use metadata::TokenMap;
+#[cfg(feature = "std")]
+pub use reader::{CharSource, ReadError};
+pub use relex::TextEdit;
+pub use source_file::SourceFile;
+pub use token_stream::{Checkpoint, TokenStream, Unexpected};
+use trace::Rule;
+
+use crate::{
+    core::Cursor,
+    filter::{Pipeline, TokenFilter},
+    mode::{Mode, ModeStack, Modes, Transition},
+};
+
+const KEYWORDS: [(&str, Keyword); 14] = [
     ("define", Keyword::Define),
     ("true", Keyword::True),
     ("false", Keyword::False),
     ("if", Keyword::If),
     ("null", Keyword::Null),
+    ("else", Keyword::Else),
+    ("while", Keyword::While),
+    ("for", Keyword::For),
+    ("return", Keyword::Return),
+    ("fn", Keyword::Function),
+    ("function", Keyword::Function),
+    ("let", Keyword::Let),
+    ("break", Keyword::Break),
+    ("continue", Keyword::Continue),
 ];
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+const OPERATORS: [Operator; 22] = [
+    Operator::Plus,
+    Operator::Minus,
+    Operator::Star,
+    Operator::Slash,
+    Operator::Equals,
+    Operator::DoubleEquals,
+    Operator::NotEquals,
+    Operator::Bang,
+    Operator::Mod,
+    Operator::Greater,
+    Operator::Less,
+    Operator::GreaterEqual,
+    Operator::LessEqual,
+    Operator::And,
+    Operator::Or,
+    Operator::BitAnd,
+    Operator::BitOr,
+    Operator::BitXor,
+    Operator::BitNot,
+    Operator::Shl,
+    Operator::Shr,
+    Operator::Coalesce,
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Keyword {
     Define,
     True,
     False,
-    None,
     If,
     Null,
+    Else,
+    While,
+    For,
+    Return,
+    Function,
+    Let,
+    Break,
+    Continue,
+    /// A reserved word registered at runtime through [`LexerBuilder::keyword`],
+    /// carrying its spelling.
+    Custom(String),
 }
 
-impl Keyword {
-    pub fn from_str(s: String) -> Keyword {
-        for p in KEYWORDS {
-            if s == p.0 {
-                return p.1;
-            }
-        }
-        return Keyword::None;
+/// Why a word failed to parse as a [`Keyword`]: it is not a built-in one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UnknownKeyword;
+
+impl fmt::Display for UnknownKeyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("not a built-in keyword")
+    }
+}
+
+impl ::core::error::Error for UnknownKeyword {}
+
+/// Looks up a built-in keyword, as reserved by the default
+/// [`LexerOptions::keywords`] without any words added or removed.
+impl ::core::str::FromStr for Keyword {
+    type Err = UnknownKeyword;
+
+    fn from_str(s: &str) -> Result<Keyword, UnknownKeyword> {
+        Ok(match s {
+            "define" => Keyword::Define,
+            "true" => Keyword::True,
+            "false" => Keyword::False,
+            "if" => Keyword::If,
+            "null" => Keyword::Null,
+            "else" => Keyword::Else,
+            "while" => Keyword::While,
+            "for" => Keyword::For,
+            "return" => Keyword::Return,
+            "fn" | "function" => Keyword::Function,
+            "let" => Keyword::Let,
+            "break" => Keyword::Break,
+            "continue" => Keyword::Continue,
+            _ => return Err(UnknownKeyword),
+        })
+    }
+}
+
+impl TryFrom<&str> for Keyword {
+    type Error = UnknownKeyword;
+
+    fn try_from(s: &str) -> Result<Keyword, UnknownKeyword> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Keyword::Define => "define",
+            Keyword::True => "true",
+            Keyword::False => "false",
+            Keyword::If => "if",
+            Keyword::Null => "null",
+            Keyword::Else => "else",
+            Keyword::While => "while",
+            Keyword::For => "for",
+            Keyword::Return => "return",
+            Keyword::Function => "fn",
+            Keyword::Let => "let",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::Custom(word) => word,
+        })
     }
 }
 
@@ -46,15 +214,309 @@ pub enum Operator {
     LessEqual,
     And,
     Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Shl,
+    Shr,
+    /// `??`, which yields its left operand unless that is null.
+    Coalesce,
+    /// An operator registered at runtime through [`LexerBuilder::operator`],
+    /// carrying its spelling.
+    Custom(String),
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Equals => "=",
+            Operator::DoubleEquals => "==",
+            Operator::NotEquals => "!=",
+            Operator::Bang => "!",
+            Operator::Mod => "%",
+            Operator::Greater => ">",
+            Operator::Less => "<",
+            Operator::GreaterEqual => ">=",
+            Operator::LessEqual => "<=",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::BitAnd => "&",
+            Operator::BitOr => "|",
+            Operator::BitXor => "^",
+            Operator::BitNot => "~",
+            Operator::Shl => "<<",
+            Operator::Shr => ">>",
+            Operator::Coalesce => "??",
+            Operator::Custom(op) => op.as_str(),
+        })
+    }
+}
+
+/// How a chain of binary operators of the same precedence groups.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a = b = c` is `a = (b = c)`.
+    Right,
+}
+
+impl Operator {
+    /// How tightly the operator binds its operands; higher binds tighter.
+    /// From loosest to tightest:
+    ///
+    /// | Precedence | Operators |
+    /// |-----------:|-----------|
+    /// | 1 | `=` |
+    /// | 2 | custom operators |
+    /// | 3 | `??` |
+    /// | 4 | `\|\|` |
+    /// | 5 | `&&` |
+    /// | 6 | `\|` |
+    /// | 7 | `^` |
+    /// | 8 | `&` |
+    /// | 9 | `==` `!=` |
+    /// | 10 | `<` `>` `<=` `>=` |
+    /// | 11 | `<<` `>>` |
+    /// | 12 | `+` `-` |
+    /// | 13 | `*` `/` `%` |
+    /// | 14 | `!` `~` |
+    ///
+    /// `!` and `~` are prefix operators, binding tighter than any binary
+    /// one; so does `-` used as a prefix. [`operator_table`] lists the
+    /// built-in operators with their precedence and associativity.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operator::Equals => 1,
+            Operator::Custom(_) => 2,
+            Operator::Coalesce => 3,
+            Operator::Or => 4,
+            Operator::And => 5,
+            Operator::BitOr => 6,
+            Operator::BitXor => 7,
+            Operator::BitAnd => 8,
+            Operator::DoubleEquals | Operator::NotEquals => 9,
+            Operator::Less | Operator::Greater | Operator::LessEqual | Operator::GreaterEqual => 10,
+            Operator::Shl | Operator::Shr => 11,
+            Operator::Plus | Operator::Minus => 12,
+            Operator::Star | Operator::Slash | Operator::Mod => 13,
+            Operator::Bang | Operator::BitNot => 14,
+        }
+    }
+
+    /// How a chain of the operator groups: assignment and the prefix
+    /// operators to the right, everything else to the left.
+    pub fn associativity(&self) -> Assoc {
+        match self {
+            Operator::Equals | Operator::Bang | Operator::BitNot => Assoc::Right,
+            _ => Assoc::Left,
+        }
+    }
+}
+
+/// Every built-in operator with its [`Operator::precedence`] and
+/// [`Operator::associativity`], loosest first, for parsers to build their
+/// own tables from.
+pub fn operator_table() -> Vec<(Operator, u8, Assoc)> {
+    let mut table: Vec<(Operator, u8, Assoc)> = OPERATORS
+        .into_iter()
+        .map(|op| {
+            let (precedence, assoc) = (op.precedence(), op.associativity());
+            (op, precedence, assoc)
+        })
+        .collect();
+    table.sort_by_key(|&(_, precedence, _)| precedence);
+    table
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Type {
     String(String),
+    /// An integer literal. A value too large for an `i32` wraps around and
+    /// is reported as [`LexError::NumberTooLarge`].
     Number(i32),
     Keyword(Keyword),
     Operator(Operator),
     Identifier(String),
+    /// An identifier interned by [`Lexer::lex_interned`]; its text is in the
+    /// [`Interner`]. Its kind is [`TokenKind::Identifier`].
+    Symbol(Symbol),
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Dot,
+    Comma,
+    Semicolon,
+    LeftBracket,
+    RightBracket,
+    Colon,
+    DoubleColon,
+    Question,
+    /// `?.`, optional chaining.
+    QuestionDot,
+    Arrow,
+    FatArrow,
+    /// A `name:` label starting a line, without its colon. Only produced
+    /// when [`LexerOptions::labels`] is set.
+    Label(String),
+    /// A `.name` directive, without its dot. Only produced when
+    /// [`LexerOptions::directives`] is set.
+    Directive(String),
+    /// A `@name` decorator, without its `@`. Only produced when
+    /// [`LexerOptions::decorators`] is set.
+    Decorator(String),
+    /// A run of whitespace, newlines included. Only produced by
+    /// [`lex_lossless`].
+    Whitespace(String),
+    /// A block comment, or with [`LexerOptions::line_comments`] a `//`
+    /// comment (with [`LexerOptions::doc_comments`], a `////` one) without
+    /// its newline, delimiters included. Only produced by
+    /// [`lex_lossless`].
+    Comment(String),
+    /// A leading `#!` line, without its newline. Only produced by
+    /// [`lex_lossless`].
+    Shebang(String),
+    /// A `///` line or `/** */` block, with the markers stripped; see
+    /// [`LexerOptions::doc_comments`]. Counts as trivia.
+    DocComment(String),
+    /// A literal piece of a string containing interpolations, without quotes.
+    /// Only produced when [`LexerOptions::interpolation`] is set; such a
+    /// string lexes as a [`Type::StringStart`] and a part, then an
+    /// [`Type::InterpolationStart`], the tokens of the expression, an
+    /// [`Type::InterpolationEnd`] and another part for every interpolation,
+    /// and a [`Type::StringEnd`], so parts may be empty.
+    StringPart(String),
+    /// The `"` opening a string containing interpolations.
+    StringStart,
+    /// The `"` closing a string containing interpolations.
+    StringEnd,
+    /// The `${` opening an interpolation.
+    InterpolationStart,
+    /// The `}` closing an interpolation.
+    InterpolationEnd,
+    /// The `#[` opening an attribute. Only produced when
+    /// [`LexerOptions::attributes`] is set; the attribute's contents lex as
+    /// ordinary tokens, up to the [`Type::AttributeEnd`] matching it.
+    AttributeStart,
+    /// The `]` closing an attribute.
+    AttributeEnd,
+    /// The end of a line, only produced by [`LexerOptions::layout`] and
+    /// [`filter::InsertNewlines`]. Like the other layout tokens it is
+    /// synthetic, with an empty span.
+    Newline,
+    /// The start of a more indented block; see [`LexerOptions::layout`].
+    Indent,
+    /// The end of an indented block; see [`LexerOptions::layout`].
+    Dedent,
+    /// The end of input, with an empty span there. Only produced when
+    /// [`LexerOptions::eof`] is set.
+    Eof,
+    /// A character no rule matches, such as `$`. Only produced when
+    /// [`LexerOptions::unknown_chars`] is [`UnknownCharRule::Token`].
+    Unknown(char),
+    /// A character no rule matches, when unknown characters are otherwise
+    /// dropped. Only produced when [`LexerOptions::trivia`] is set, so that
+    /// the tokens cover the input.
+    None,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::String(s) if s.contains(['\n', '"']) => write!(f, "\"\"\"{}\"\"\"", s),
+            Type::String(s) => write!(f, "\"{}\"", s),
+            Type::Number(n) => write!(f, "{}", n),
+            Type::Keyword(k) => write!(f, "{}", k),
+            Type::Operator(o) => write!(f, "{}", o),
+            Type::Identifier(s) | Type::Whitespace(s) | Type::Comment(s) | Type::Shebang(s) => {
+                f.write_str(s)
+            }
+            Type::Symbol(symbol) => write!(f, "{}", symbol),
+            Type::DocComment(s) if s.contains('\n') => write!(f, "/**{}*/", s),
+            Type::DocComment(s) => write!(f, "/// {}", s),
+            Type::LeftParen => f.write_str("("),
+            Type::RightParen => f.write_str(")"),
+            Type::LeftBrace => f.write_str("{"),
+            Type::RightBrace => f.write_str("}"),
+            Type::Dot => f.write_str("."),
+            Type::Comma => f.write_str(","),
+            Type::Semicolon => f.write_str(";"),
+            Type::LeftBracket => f.write_str("["),
+            Type::RightBracket => f.write_str("]"),
+            Type::Colon => f.write_str(":"),
+            Type::DoubleColon => f.write_str("::"),
+            Type::Question => f.write_str("?"),
+            Type::QuestionDot => f.write_str("?."),
+            Type::Arrow => f.write_str("->"),
+            Type::FatArrow => f.write_str("=>"),
+            Type::Label(s) => write!(f, "{}:", s),
+            Type::Directive(s) => write!(f, ".{}", s),
+            Type::Decorator(s) => write!(f, "@{}", s),
+            Type::StringPart(s) => f.write_str(s),
+            Type::StringStart | Type::StringEnd => f.write_str("\""),
+            Type::InterpolationStart => f.write_str("${"),
+            Type::InterpolationEnd => f.write_str("}"),
+            Type::AttributeStart => f.write_str("#["),
+            Type::AttributeEnd => f.write_str("]"),
+            Type::Newline => f.write_str("\n"),
+            Type::Unknown(c) => write!(f, "{}", c),
+            Type::Indent | Type::Dedent | Type::Eof => Ok(()),
+            Type::None => Ok(()),
+        }
+    }
+}
+
+/// The fieldless discriminant of a [`Type`], flattened so that every operator
+/// and keyword gets its own kind.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[repr(u8)]
+pub enum TokenKind {
+    String,
+    Number,
+    Identifier,
+    Define,
+    True,
+    False,
+    If,
+    Null,
+    Else,
+    While,
+    For,
+    Return,
+    Function,
+    Let,
+    Break,
+    Continue,
+    CustomKeyword,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Equals,
+    DoubleEquals,
+    NotEquals,
+    Bang,
+    Mod,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Shl,
+    Shr,
+    Coalesce,
+    CustomOperator,
     LeftParen,
     RightParen,
     LeftBrace,
@@ -62,282 +524,3434 @@ pub enum Type {
     Dot,
     Comma,
     Semicolon,
+    LeftBracket,
+    RightBracket,
+    Colon,
+    DoubleColon,
+    Question,
+    QuestionDot,
+    Arrow,
+    FatArrow,
+    Label,
+    Directive,
+    Decorator,
+    Whitespace,
+    Comment,
+    Shebang,
+    DocComment,
+    StringPart,
+    StringStart,
+    StringEnd,
+    InterpolationStart,
+    InterpolationEnd,
+    AttributeStart,
+    AttributeEnd,
+    Newline,
+    Indent,
+    Dedent,
+    Eof,
+    Unknown,
     None,
 }
 
+impl Type {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Type::String(_) => TokenKind::String,
+            Type::Number(_) => TokenKind::Number,
+            Type::Identifier(_) | Type::Symbol(_) => TokenKind::Identifier,
+            Type::Keyword(k) => match k {
+                Keyword::Define => TokenKind::Define,
+                Keyword::True => TokenKind::True,
+                Keyword::False => TokenKind::False,
+                Keyword::If => TokenKind::If,
+                Keyword::Null => TokenKind::Null,
+                Keyword::Else => TokenKind::Else,
+                Keyword::While => TokenKind::While,
+                Keyword::For => TokenKind::For,
+                Keyword::Return => TokenKind::Return,
+                Keyword::Function => TokenKind::Function,
+                Keyword::Let => TokenKind::Let,
+                Keyword::Break => TokenKind::Break,
+                Keyword::Continue => TokenKind::Continue,
+                Keyword::Custom(_) => TokenKind::CustomKeyword,
+            },
+            Type::Operator(o) => match o {
+                Operator::Plus => TokenKind::Plus,
+                Operator::Minus => TokenKind::Minus,
+                Operator::Star => TokenKind::Star,
+                Operator::Slash => TokenKind::Slash,
+                Operator::Equals => TokenKind::Equals,
+                Operator::DoubleEquals => TokenKind::DoubleEquals,
+                Operator::NotEquals => TokenKind::NotEquals,
+                Operator::Bang => TokenKind::Bang,
+                Operator::Mod => TokenKind::Mod,
+                Operator::Greater => TokenKind::Greater,
+                Operator::Less => TokenKind::Less,
+                Operator::GreaterEqual => TokenKind::GreaterEqual,
+                Operator::LessEqual => TokenKind::LessEqual,
+                Operator::And => TokenKind::And,
+                Operator::Or => TokenKind::Or,
+                Operator::BitAnd => TokenKind::BitAnd,
+                Operator::BitOr => TokenKind::BitOr,
+                Operator::BitXor => TokenKind::BitXor,
+                Operator::BitNot => TokenKind::BitNot,
+                Operator::Shl => TokenKind::Shl,
+                Operator::Shr => TokenKind::Shr,
+                Operator::Coalesce => TokenKind::Coalesce,
+                Operator::Custom(_) => TokenKind::CustomOperator,
+            },
+            Type::LeftParen => TokenKind::LeftParen,
+            Type::RightParen => TokenKind::RightParen,
+            Type::LeftBrace => TokenKind::LeftBrace,
+            Type::RightBrace => TokenKind::RightBrace,
+            Type::Dot => TokenKind::Dot,
+            Type::Comma => TokenKind::Comma,
+            Type::Semicolon => TokenKind::Semicolon,
+            Type::LeftBracket => TokenKind::LeftBracket,
+            Type::RightBracket => TokenKind::RightBracket,
+            Type::Colon => TokenKind::Colon,
+            Type::DoubleColon => TokenKind::DoubleColon,
+            Type::Question => TokenKind::Question,
+            Type::QuestionDot => TokenKind::QuestionDot,
+            Type::Arrow => TokenKind::Arrow,
+            Type::FatArrow => TokenKind::FatArrow,
+            Type::Label(_) => TokenKind::Label,
+            Type::Directive(_) => TokenKind::Directive,
+            Type::Decorator(_) => TokenKind::Decorator,
+            Type::Whitespace(_) => TokenKind::Whitespace,
+            Type::Comment(_) => TokenKind::Comment,
+            Type::Shebang(_) => TokenKind::Shebang,
+            Type::DocComment(_) => TokenKind::DocComment,
+            Type::StringPart(_) => TokenKind::StringPart,
+            Type::StringStart => TokenKind::StringStart,
+            Type::StringEnd => TokenKind::StringEnd,
+            Type::InterpolationStart => TokenKind::InterpolationStart,
+            Type::InterpolationEnd => TokenKind::InterpolationEnd,
+            Type::AttributeStart => TokenKind::AttributeStart,
+            Type::AttributeEnd => TokenKind::AttributeEnd,
+            Type::Newline => TokenKind::Newline,
+            Type::Indent => TokenKind::Indent,
+            Type::Dedent => TokenKind::Dedent,
+            Type::Eof => TokenKind::Eof,
+            Type::Unknown(_) => TokenKind::Unknown,
+            Type::None => TokenKind::None,
+        }
+    }
+
+    /// The coarse class of the token type, for tools that treat, say, all
+    /// literals alike.
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            Type::String(_)
+            | Type::Number(_)
+            | Type::StringPart(_)
+            | Type::StringStart
+            | Type::StringEnd => TokenCategory::Literal,
+            Type::Keyword(_) | Type::Directive(_) | Type::Decorator(_) => TokenCategory::Keyword,
+            Type::Operator(_) => TokenCategory::Operator,
+            Type::Identifier(_) | Type::Symbol(_) | Type::Label(_) => TokenCategory::Identifier,
+            Type::Whitespace(_) | Type::Comment(_) | Type::Shebang(_) | Type::DocComment(_) => {
+                TokenCategory::Trivia
+            }
+            Type::Unknown(_) | Type::None => TokenCategory::Error,
+            Type::LeftParen
+            | Type::RightParen
+            | Type::LeftBrace
+            | Type::RightBrace
+            | Type::Dot
+            | Type::Comma
+            | Type::Semicolon
+            | Type::LeftBracket
+            | Type::RightBracket
+            | Type::Colon
+            | Type::DoubleColon
+            | Type::Question
+            | Type::QuestionDot
+            | Type::Arrow
+            | Type::FatArrow
+            | Type::InterpolationStart
+            | Type::InterpolationEnd
+            | Type::AttributeStart
+            | Type::AttributeEnd
+            | Type::Newline
+            | Type::Indent
+            | Type::Dedent
+            | Type::Eof => TokenCategory::Punctuation,
+        }
+    }
+}
+
+/// A coarse class of [`Type`]s; see [`Type::category`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum TokenCategory {
+    /// Strings, numbers and the parts of interpolated strings. `true`,
+    /// `false` and `null` are keywords.
+    Literal,
+    /// Keywords, including custom ones, directives and decorators.
+    Keyword,
+    Operator,
+    /// Brackets, separators and arrows, along with the synthetic layout and
+    /// end of input tokens.
+    Punctuation,
+    /// Identifiers, interned symbols and labels.
+    Identifier,
+    /// Whitespace, comments, doc comments and the shebang line.
+    Trivia,
+    /// A character no rule matches.
+    Error,
+}
+
+/// A set of [`TokenKind`]s stored as a bitset, for cheap FIRST/FOLLOW and
+/// synchronization-point checks in recursive-descent parsers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct TokenKindSet(u128);
+
+// `TokenKind::None` is kept as the last variant, so this bounds every kind.
+const _: () = assert!((TokenKind::None as u8) < 128);
+
+impl TokenKindSet {
+    pub const EMPTY: TokenKindSet = TokenKindSet(0);
+
+    pub const fn new(kinds: &[TokenKind]) -> Self {
+        let mut set = TokenKindSet::EMPTY;
+        let mut i = 0;
+        while i < kinds.len() {
+            set = set.with(kinds[i]);
+            i += 1;
+        }
+        set
+    }
+
+    pub const fn with(self, kind: TokenKind) -> Self {
+        TokenKindSet(self.0 | 1 << kind as u8)
+    }
+
+    pub const fn without(self, kind: TokenKind) -> Self {
+        TokenKindSet(self.0 & !(1 << kind as u8))
+    }
+
+    pub const fn union(self, other: TokenKindSet) -> Self {
+        TokenKindSet(self.0 | other.0)
+    }
+
+    pub const fn contains(self, kind: TokenKind) -> bool {
+        self.0 & 1 << kind as u8 != 0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+}
+
+/// A half-open range of byte offsets into the lexed source.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Token {
     pub token_type: Type,
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(token_type: Type) -> Self {
-        return Token { token_type };
+        Token {
+            token_type,
+            span: Span::default(),
+        }
     }
 
-    pub fn none() -> Self {
-        return Token {
-            token_type: Type::None,
-        };
+    pub fn with_span(token_type: Type, span: Span) -> Self {
+        Token { token_type, span }
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.token_type.kind()
+    }
+
+    pub fn category(&self) -> TokenCategory {
+        self.token_type.category()
+    }
+
+    pub fn is_keyword(&self, keyword: Keyword) -> bool {
+        self.token_type == Type::Keyword(keyword)
+    }
+
+    pub fn is_operator(&self, operator: Operator) -> bool {
+        self.token_type == Type::Operator(operator)
+    }
+
+    pub fn is_identifier(&self) -> bool {
+        matches!(self.token_type, Type::Identifier(_) | Type::Symbol(_))
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.token_type == Type::Eof
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.category() == TokenCategory::Trivia
+    }
+
+    /// Bytes this token occupies: its inline size plus the heap capacity of
+    /// any string payload.
+    pub fn memory_footprint(&self) -> usize {
+        size_of::<Token>()
+            + match &self.token_type {
+                Type::String(s)
+                | Type::Identifier(s)
+                | Type::Whitespace(s)
+                | Type::Comment(s)
+                | Type::Shebang(s)
+                | Type::DocComment(s)
+                | Type::StringPart(s)
+                | Type::Label(s)
+                | Type::Directive(s)
+                | Type::Decorator(s)
+                | Type::Keyword(Keyword::Custom(s))
+                | Type::Operator(Operator::Custom(s)) => s.capacity(),
+                _ => 0,
+            }
     }
 }
 
-fn lex_string(chars: &mut Peekable<Chars>) -> Result<Token, &'static str> {
-    let mut accumulator: String = String::new();
-    let mut error = false;
-    loop {
-        match chars.next() {
-            Some(c) => match c {
-                '"' => break,
-                '\n' => {
-                    error = true;
-                    break;
-                }
-                _ => accumulator.push(c),
-            },
-            None => {
-                error = true;
-                break;
+// Every token pays for these sizes, so large files feel any growth. Raise
+// the bounds deliberately when a new payload is worth it.
+const _: () = assert!(size_of::<Type>() <= 32);
+const _: () = assert!(size_of::<Token>() <= 48);
+
+/// Total bytes held by a token stream, counting each token's inline size and
+/// heap payload but not any spare capacity of the slice's own allocation.
+pub fn memory_footprint(tokens: &[Token]) -> usize {
+    tokens.iter().map(Token::memory_footprint).sum()
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.token_type)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexError {
+    /// A string literal ran into a newline or the end of input. Carries the
+    /// byte offset of the opening `"`.
+    UnterminatedString(usize),
+    /// A `/*` block comment (possibly nested) was never closed. Carries the
+    /// byte offset of the outermost opening `/*`.
+    UnterminatedComment(usize),
+    /// With [`LexerOptions::layout`], a line is indented with whitespace
+    /// that neither continues nor matches its block's, such as tabs where
+    /// the block uses spaces. Carries the byte offset of the line's first
+    /// token.
+    MixedIndentation(usize),
+    /// With [`LexerOptions::layout`], a line is indented less than the line
+    /// before it, but not as much as any block it returns to. Carries the
+    /// byte offset of the line's first token.
+    UnalignedDedent(usize),
+    /// Input given to [`Lexer::lex_bytes`] holds bytes that are not UTF-8.
+    /// Carries the byte offset of the first of them.
+    InvalidUtf8(usize),
+    /// Interpolated strings and attributes are nested inside each other more
+    /// than [`MAX_NESTING`] deep. Carries the byte offset of the innermost
+    /// string's opening `"` or attribute's `#`.
+    NestingTooDeep(usize),
+    /// Input went past one of the limits set in [`LexerOptions`]. Carries
+    /// the byte offset of the token, string or comment that went past it, or
+    /// of the first token past [`LexerOptions::max_tokens`].
+    LimitExceeded(Limit, usize),
+    /// A `#[` attribute was never closed. Carries the byte offset of its
+    /// `#`.
+    UnterminatedAttribute(usize),
+    /// A character no rule matches, reported when
+    /// [`LexerOptions::unknown_chars`] is [`UnknownCharRule::Error`].
+    /// Carries the character and its byte offset.
+    UnknownCharacter(char, usize),
+    /// An integer literal does not fit in an `i32`. Carries the span of the
+    /// whole literal, which is still lexed, its value wrapped around.
+    NumberTooLarge(Span),
+}
+
+/// A limit on hostile input; see [`LexError::LimitExceeded`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    /// [`LexerOptions::max_token_len`].
+    TokenLength,
+    /// [`LexerOptions::max_string_len`].
+    StringLength,
+    /// [`LexerOptions::max_nesting`].
+    Nesting,
+    /// [`LexerOptions::max_tokens`].
+    Tokens,
+}
+
+/// How deeply interpolated strings and attributes may nest, so that the lexer's recursion
+/// into them stays well within the stack of any thread.
+pub const MAX_NESTING: usize = 64;
+
+impl LexError {
+    /// A short, stable identifier of the kind of error, such as `L001`, for
+    /// tools to match on rather than the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnterminatedString(_) => "L001",
+            LexError::UnterminatedComment(_) => "L002",
+            LexError::MixedIndentation(_) => "L003",
+            LexError::UnalignedDedent(_) => "L004",
+            LexError::InvalidUtf8(_) => "L005",
+            LexError::NestingTooDeep(_) => "L006",
+            LexError::LimitExceeded(..) => "L007",
+            LexError::UnterminatedAttribute(_) => "L008",
+            LexError::UnknownCharacter(..) => "L009",
+            LexError::NumberTooLarge(_) => "L010",
+        }
+    }
+
+    /// The source the error points at: the opening delimiter of an
+    /// unterminated literal, or the first character of a badly indented line.
+    pub fn span(&self) -> Span {
+        match *self {
+            LexError::UnterminatedComment(offset) | LexError::UnterminatedAttribute(offset) => {
+                Span::new(offset, offset + 2)
             }
-        };
+            LexError::UnknownCharacter(c, offset) => Span::new(offset, offset + c.len_utf8()),
+            LexError::NumberTooLarge(span) => span,
+            LexError::UnterminatedString(offset)
+            | LexError::MixedIndentation(offset)
+            | LexError::UnalignedDedent(offset)
+            | LexError::InvalidUtf8(offset)
+            | LexError::NestingTooDeep(offset)
+            | LexError::LimitExceeded(_, offset) => Span::new(offset, offset + 1),
+        }
     }
-    if error {
-        return Err("Non-terminated String");
-    } else {
-        Ok(Token {
-            token_type: Type::String(accumulator),
-        })
+
+    pub(crate) fn message(&self) -> &'static str {
+        match self {
+            LexError::UnterminatedString(_) => "unterminated string",
+            LexError::UnterminatedComment(_) => "unterminated block comment",
+            LexError::MixedIndentation(_) => "indentation mixes tabs and spaces inconsistently",
+            LexError::UnalignedDedent(_) => "unindent does not match any outer indentation level",
+            LexError::InvalidUtf8(_) => "invalid UTF-8",
+            LexError::NestingTooDeep(_) => "interpolations or attributes are nested too deeply",
+            LexError::LimitExceeded(Limit::TokenLength, _) => "token is too long",
+            LexError::LimitExceeded(Limit::StringLength, _) => "string literal is too long",
+            LexError::LimitExceeded(Limit::Nesting, _) => "nested too deeply",
+            LexError::LimitExceeded(Limit::Tokens, _) => "too many tokens",
+            LexError::UnterminatedAttribute(_) => "unterminated attribute",
+            LexError::UnknownCharacter(..) => "unknown character",
+            LexError::NumberTooLarge(_) => "number is too large",
+        }
     }
 }
 
-fn lex_number(chars: &mut Peekable<Chars>) -> Token {
-    let mut accumulator: i32 = 0;
-    while let Some(c) = chars.next_if(|&c| c.is_numeric()) {
-        accumulator = accumulator * 10 + c.to_digit(10).unwrap() as i32;
+/// Writes the message, the span as byte offsets and the code, as in
+/// `unterminated string at 4..5 [L001]`.
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{} at {}..{} [{}]",
+            self.message(),
+            span.start,
+            span.end,
+            self.code()
+        )
     }
-    return Token::new(Type::Number(accumulator));
 }
 
-fn lex_alphanumeric(chars: &mut Peekable<Chars>) -> Token {
-    let mut accumulator: String = String::new();
-    while let Some(c) = chars.next_if(|&c| c.is_alphanumeric()) {
-        accumulator.push(c);
+impl ::core::error::Error for LexError {}
+
+/// Something suspicious that still lexed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexWarning {
+    /// A string, comment or identifier exceeded
+    /// [`LexerOptions::long_token_warning`].
+    LongToken(Span),
+    /// A whitespace character other than space, tab, `\n` or `\r`, reported
+    /// when [`LexerOptions::unusual_whitespace_warning`] is set.
+    UnusualWhitespace(Span),
+    /// A string literal contains a grapheme cluster made of several
+    /// characters (such as `e` plus a combining accent), so its character
+    /// count differs from what a reader sees. Points at the first such
+    /// cluster; reported when [`LexerOptions::combining_warning`] is set.
+    CombiningSequence(Span),
+    /// A keyword directly followed by `(`, as if it were a function name,
+    /// such as `define(x)`. Reported for the keywords in
+    /// [`LexerOptions::keyword_call_warning`].
+    KeywordCall(Keyword, Span),
+}
+
+/// Everything produced by [`Lexer::lex_with_diagnostics`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LexOutput {
+    pub tokens: Vec<Token>,
+    pub errors: Vec<LexError>,
+    pub warnings: Vec<LexWarning>,
+}
+
+fn lex_string(chars: &mut Cursor, max_len: Option<usize>) -> Result<Token, LexError> {
+    let start = chars.offset;
+    let body = start + 1;
+    // The delimiters are ASCII, and no byte of a multi-byte character is.
+    let end = chars.source.as_bytes()[body..]
+        .iter()
+        .position(|&b| b == b'"' || b == b'\n')
+        .map(|len| body + len);
+    chars.offset = end.map_or(chars.source.len(), |end| end + 1);
+    if exceeds(max_len, end.unwrap_or(chars.source.len()) - body) {
+        return Err(LexError::LimitExceeded(Limit::StringLength, start));
     }
-    Token::new(
-        match KEYWORDS
-            .map(|k| k.0)
-            .contains(&(&accumulator).clone().as_str())
-        {
-            true => match Keyword::from_str(accumulator) {
-                Keyword::None => Type::None,
-                keyword => Type::Keyword(keyword),
-            },
-            false => Type::Identifier(accumulator),
-        },
-    )
-}
-
-fn lex_operator(chars: &mut Peekable<Chars>) -> Token {
-    match chars.next().unwrap() {
-        '+' => Token::new(Type::Operator(Operator::Plus)),
-        '-' => Token::new(Type::Operator(Operator::Minus)),
-        '*' => Token::new(Type::Operator(Operator::Star)),
-        '/' => Token::new(Type::Operator(Operator::Slash)),
-        '=' => match chars.peek() {
-            Some(c) => match c {
-                '=' => {
-                    chars.next();
-                    return Token::new(Type::Operator(Operator::DoubleEquals));
-                }
-                _ => Token::new(Type::Operator(Operator::Equals)),
-            },
-            None => Token::none(), // TODO: produce errors
-        },
-        '!' => match chars.peek() {
-            Some(c) => match c {
-                '=' => {
-                    chars.next();
-                    return Token::new(Type::Operator(Operator::NotEquals));
-                }
-                _ => Token::new(Type::Operator(Operator::Bang)),
-            },
-            None => Token::none(), // TODO: produce errors
-        },
-        '%' => Token::new(Type::Operator(Operator::Mod)),
-        '>' => match chars.peek() {
-            Some(c) => match c {
-                '=' => {
-                    chars.next();
-                    return Token::new(Type::Operator(Operator::GreaterEqual));
-                }
-                _ => Token::new(Type::Operator(Operator::Greater)),
-            },
-            None => Token::none(), // TODO: produce errors
-        },
-        '<' => match chars.peek() {
-            Some(c) => match c {
-                '=' => {
-                    chars.next();
-                    Token::new(Type::Operator(Operator::LessEqual))
-                }
-                _ => Token::new(Type::Operator(Operator::Less)),
-            },
-            None => Token::none(), // TODO: produce errors
-        },
-        '&' => match chars.peek() {
-            Some(c) => match c {
-                '&' => {
-                    chars.next();
-                    return Token::new(Type::Operator(Operator::And));
-                }
-                _ => Token::none(), // TODO: produce errors
-            },
-            None => Token::none(), // TODO: produce errors
-        },
-        '|' => match chars.peek() {
-            Some(c) => match c {
-                '|' => {
-                    chars.next();
-                    return Token::new(Type::Operator(Operator::Or));
-                }
-                _ => Token::none(), // TODO: produce errors
-            },
-            None => Token::none(), // TODO: produce errors
-        },
-        _ => Token::none(), // TODO: produce errors
+    match end {
+        Some(end) if chars.source.as_bytes()[end] == b'"' => Ok(Token::new(Type::String(
+            chars.source[body..end].to_string(),
+        ))),
+        _ => Err(LexError::UnterminatedString(start)),
     }
 }
 
-fn lex_helper(mut chars: Peekable<Chars>) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    while let Some(c) = chars.peek() {
-        match c {
-            '"' => {
-                chars.next();
-                match lex_string(&mut chars) {
-                    Ok(t) => tokens.push(t),
-                    Err(_) => (), // TODO: produce errors
-                }
-            }
-            '0'..='9' => tokens.push(lex_number(&mut chars)),
-            '(' => {
-                chars.next();
-                tokens.push(Token::new(Type::LeftParen));
-            }
-            ')' => {
-                chars.next();
-                tokens.push(Token::new(Type::RightParen));
-            }
-            '{' => {
+/// Lexes a `"""` string literal, which may span lines and ends at the next
+/// `"""`. With [`LexerOptions::dedent_strings`], a line break right after
+/// the opening quotes is dropped and the indentation common to the lines is
+/// stripped.
+fn lex_multiline_string(chars: &mut Cursor, options: &LexerOptions) -> Result<Token, LexError> {
+    let start = chars.offset;
+    chars.offset += 3;
+    let end = chars.rest().find(r#"""""#);
+    if exceeds(options.max_string_len, end.unwrap_or(chars.rest().len())) {
+        chars.offset = end.map_or(chars.source.len(), |end| chars.offset + end + 3);
+        return Err(LexError::LimitExceeded(Limit::StringLength, start));
+    }
+    let text = match end {
+        Some(end) => &chars.rest()[..end],
+        None => {
+            chars.offset = chars.source.len();
+            return Err(LexError::UnterminatedString(start));
+        }
+    };
+    chars.offset += text.len() + 3;
+    match options.dedent_strings {
+        true => Ok(Token::new(Type::String(dedent_lines(text)))),
+        false => Ok(Token::new(Type::String(text.to_string()))),
+    }
+}
+
+/// Strips the indentation common to the lines of `text` that are not blank,
+/// and the line that closes the literal, after dropping a leading line break.
+fn dedent_lines(text: &str) -> String {
+    let text = text
+        .strip_prefix('\n')
+        .or_else(|| text.strip_prefix("\r\n"))
+        .unwrap_or(text);
+    let lines: Vec<&str> = text.split('\n').collect();
+    let indent_of = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    let last = lines.len() - 1;
+    let indent = lines
+        .iter()
+        .enumerate()
+        .filter(|&(i, line)| i == last || !line.trim().is_empty())
+        .map(|(_, line)| indent_of(line))
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| &line[indent.min(indent_of(line))..])
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Lexes a string literal in which `${...}` interpolates an expression, with
+/// the interpolation's tokens lexed by [`lex_step`]. A literal without
+/// interpolations is a plain [`Type::String`].
+fn lex_interpolated(
+    chars: &mut Cursor,
+    options: &LexerOptions,
+    out: &mut LexOutput,
+) -> Result<(), LexError> {
+    let start = chars.offset;
+    check_nesting(chars, options)?;
+    chars.next();
+    let mut part_start = chars.offset;
+    let mut part = String::new();
+    let mut parts = Vec::new();
+    loop {
+        match chars.peek() {
+            Some('"') => {
                 chars.next();
-                tokens.push(Token::new(Type::LeftBrace));
+                break;
             }
-            '}' => {
+            Some('\n') | None => return Err(LexError::UnterminatedString(start)),
+            Some('$') if chars.peek_second() == Some('{') => {
+                if parts.is_empty() {
+                    parts.push(Token::with_span(
+                        Type::StringStart,
+                        Span::new(start, start + 1),
+                    ));
+                }
+                let part = ::core::mem::take(&mut part);
+                parts.push(Token::with_span(
+                    Type::StringPart(part),
+                    Span::new(part_start, chars.offset),
+                ));
+                let open = chars.offset;
+                chars.offset += 2;
+                parts.push(Token::with_span(
+                    Type::InterpolationStart,
+                    Span::new(open, chars.offset),
+                ));
+                let mut depth = 0;
+                loop {
+                    match chars.peek() {
+                        Some('}') if depth == 0 => break,
+                        Some('\n') | None => return Err(LexError::UnterminatedString(start)),
+                        _ => (),
+                    }
+                    let count = out.tokens.len();
+                    chars.nesting += 1;
+                    lex_step(chars, options, out);
+                    chars.nesting -= 1;
+                    match out.tokens[count..].first().map(|t| &t.token_type) {
+                        Some(Type::LeftBrace) => depth += 1,
+                        Some(Type::RightBrace) => depth -= 1,
+                        _ => (),
+                    }
+                    parts.append(&mut out.tokens);
+                }
                 chars.next();
-                tokens.push(Token::new(Type::RightBrace));
+                parts.push(Token::with_span(
+                    Type::InterpolationEnd,
+                    Span::new(chars.offset - 1, chars.offset),
+                ));
+                part_start = chars.offset;
             }
-            '.' => {
-                chars.next();
-                tokens.push(Token::new(Type::Dot));
+            Some(c)
+                if exceeds(
+                    options.max_string_len,
+                    chars.offset + c.len_utf8() - start - 1,
+                ) =>
+            {
+                skip_line(chars, start);
+                return Err(LexError::LimitExceeded(Limit::StringLength, start));
             }
-            ',' => {
+            Some(c) => {
                 chars.next();
-                tokens.push(Token::new(Type::Comma));
+                part.push(c);
             }
-            '+' | '-' | '*' | '/' | '=' | '!' | '%' | '>' | '<' | '&' | '|' => {
-                tokens.push(lex_operator(&mut chars))
+        }
+    }
+    match parts.is_empty() {
+        true => out.tokens.push(Token::new(Type::String(part))),
+        false => {
+            let end = chars.offset - 1;
+            parts.push(Token::with_span(
+                Type::StringPart(part),
+                Span::new(part_start, end),
+            ));
+            parts.push(Token::with_span(
+                Type::StringEnd,
+                Span::new(end, chars.offset),
+            ));
+            out.tokens.append(&mut parts);
+        }
+    }
+    Ok(())
+}
+
+/// Fails, skipping the rest of the line, if a construct starting at `chars`
+/// would nest too deeply inside the ones around it.
+fn check_nesting(chars: &mut Cursor, options: &LexerOptions) -> Result<(), LexError> {
+    let start = chars.offset;
+    let error = match chars.nesting {
+        nesting if nesting >= MAX_NESTING => LexError::NestingTooDeep(start),
+        nesting if options.max_nesting.is_some_and(|max| nesting >= max) => {
+            LexError::LimitExceeded(Limit::Nesting, start)
+        }
+        _ => return Ok(()),
+    };
+    skip_line(chars, start);
+    Err(error)
+}
+
+/// Moves `chars` to the end of the line `start` is on.
+fn skip_line(chars: &mut Cursor, start: usize) {
+    chars.offset = chars.source[start..]
+        .find('\n')
+        .map_or(chars.source.len(), |newline| start + newline);
+}
+
+/// Lexes a `#[...]` attribute, with its contents lexed by [`lex_step`] up to
+/// the `]` matching the opening bracket. Attributes may span lines.
+fn lex_attribute(
+    chars: &mut Cursor,
+    options: &LexerOptions,
+    out: &mut LexOutput,
+) -> Result<(), LexError> {
+    let start = chars.offset;
+    check_nesting(chars, options)?;
+    chars.offset += 2;
+    out.tokens.push(Token::with_span(
+        Type::AttributeStart,
+        Span::new(start, chars.offset),
+    ));
+    let mut depth = 0;
+    loop {
+        match chars.peek() {
+            Some(']') if depth == 0 => break,
+            None => return Err(LexError::UnterminatedAttribute(start)),
+            _ => (),
+        }
+        let count = out.tokens.len();
+        chars.nesting += 1;
+        lex_step(chars, options, out);
+        chars.nesting -= 1;
+        match out.tokens[count..].first().map(|t| &t.token_type) {
+            Some(Type::LeftBracket) => depth += 1,
+            Some(Type::RightBracket) => depth -= 1,
+            _ => (),
+        }
+    }
+    chars.next();
+    out.tokens.push(Token::with_span(
+        Type::AttributeEnd,
+        Span::new(chars.offset - 1, chars.offset),
+    ));
+    Ok(())
+}
+
+/// Skips a block comment, nested comments included. With `max_depth`, the
+/// whole comment is still skipped when it nests too deeply, and the error
+/// points at the `/*` that opens one comment too many.
+fn skip_block_comment(chars: &mut Cursor, max_depth: Option<usize>) -> Result<(), LexError> {
+    let start = chars.offset;
+    let bytes = chars.source.as_bytes();
+    let mut at = start + 2;
+    let mut depth = 1;
+    let mut too_deep = exceeds(max_depth, depth).then_some(start);
+    while depth > 0 {
+        match (bytes.get(at), bytes.get(at + 1)) {
+            (Some(b'/'), Some(b'*')) => {
+                at += 2;
+                depth += 1;
+                if too_deep.is_none() && exceeds(max_depth, depth) {
+                    too_deep = Some(at - 2);
+                }
             }
-            ';' => {
-                chars.next();
-                tokens.push(Token::new(Type::Semicolon));
+            (Some(b'*'), Some(b'/')) => {
+                at += 2;
+                depth -= 1;
             }
-            _ if c.is_alphanumeric() => tokens.push(lex_alphanumeric(&mut chars)),
-            _ => {
-                chars.next();
+            (Some(_), _) => at += 1,
+            (None, _) => {
+                chars.offset = bytes.len();
+                return Err(match too_deep {
+                    Some(at) => LexError::LimitExceeded(Limit::Nesting, at),
+                    None => LexError::UnterminatedComment(start),
+                });
             }
-        };
+        }
+    }
+    chars.offset = at;
+    match too_deep {
+        Some(at) => Err(LexError::LimitExceeded(Limit::Nesting, at)),
+        None => Ok(()),
     }
-    tokens
 }
 
-pub fn lex(s: String) -> Vec<Token> {
-    return lex_helper(s.chars().peekable());
+/// Whether `rest` starts with a doc comment: `///` but not `////`, or `/**`
+/// but not the plain block comments `/**/` and `/***`, as in Rust.
+fn starts_doc_comment(rest: &str) -> bool {
+    (rest.starts_with("///") && !rest.starts_with("////"))
+        || (rest.starts_with("/**") && !rest.starts_with("/**/") && !rest.starts_with("/***"))
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{Keyword, Operator, Token, Type, lex};
+/// Lexes a doc comment, stripping `///` and one space after it, or `/**`,
+/// `*/` and the leading whitespace, `*` and one space of each further line.
+fn lex_doc_comment(chars: &mut Cursor, max_depth: Option<usize>) -> Result<Token, LexError> {
+    let start = chars.offset;
+    if chars.eat("///") {
+        let text = chars.eat_while(|c| c != '\n');
+        let text = text.strip_prefix(' ').unwrap_or(text);
+        return Ok(Token::new(Type::DocComment(text.to_string())));
+    }
+    skip_block_comment(chars, max_depth)?;
+    let body = &chars.source[start + 3..chars.offset - 2];
+    let mut lines = body.split('\n');
+    let mut text = String::from(lines.next().unwrap_or_default());
+    for line in lines {
+        let line = line.trim_start();
+        let line = line.strip_prefix('*').unwrap_or(line);
+        text.push('\n');
+        text.push_str(line.strip_prefix(' ').unwrap_or(line));
+    }
+    Ok(Token::new(Type::DocComment(text)))
+}
 
-    #[test]
-    fn test() {
-        assert_eq!(
-            lex("\"meow\"".to_string()),
-            vec![Token::new(Type::String("meow".to_string()))]
-        );
-        assert_eq!(
-            lex("\"meow meow\"".to_string()),
-            vec![Token::new(Type::String("meow meow".to_string()))]
-        );
-        assert_eq!(lex("311".to_string()), vec![Token::new(Type::Number(311))]);
-        assert_eq!(
-            lex("ident".to_string()),
-            vec![Token::new(Type::Identifier("ident".to_string()))]
-        );
-        assert_eq!(
-            lex("empty()".to_string()),
-            vec![
-                Token::new(Type::Identifier("empty".to_string())),
-                Token::new(Type::LeftParen),
-                Token::new(Type::RightParen)
-            ]
-        );
-        assert_eq!(
-            lex("1 + 1 == 5".to_string()),
-            vec![
-                Token::new(Type::Number(1)),
-                Token::new(Type::Operator(Operator::Plus)),
-                Token::new(Type::Number(1)),
-                Token::new(Type::Operator(Operator::DoubleEquals)),
-                Token::new(Type::Number(5))
-            ]
-        );
-        assert_eq!(
-            lex("define x = 5".to_string()),
-            vec![
-                Token::new(Type::Keyword(Keyword::Define)),
-                Token::new(Type::Identifier("x".to_string())),
-                Token::new(Type::Operator(Operator::Equals)),
-                Token::new(Type::Number(5))
-            ]
-        );
+/// Lexes the digits of a number that starts at `start`, reporting one too
+/// large for an `i32`.
+fn lex_number(
+    chars: &mut Cursor,
+    start: usize,
+    radix: u32,
+    options: &LexerOptions,
+    errors: &mut Vec<LexError>,
+) -> Token {
+    let mut accumulator: i32 = 0;
+    let mut overflowed = false;
+    while let Some(c) =
+        chars.next_if(|&c| c.is_digit(radix) || options.digit_separators.contains(&c))
+    {
+        if let Some(digit) = c.to_digit(radix) {
+            accumulator = match accumulator
+                .checked_mul(radix as i32)
+                .and_then(|n| n.checked_add(digit as i32))
+            {
+                Some(n) => n,
+                None => {
+                    overflowed = true;
+                    accumulator
+                        .wrapping_mul(radix as i32)
+                        .wrapping_add(digit as i32)
+                }
+            };
+        }
+    }
+    if overflowed {
+        errors.push(LexError::NumberTooLarge(Span::new(start, chars.offset)));
+    }
+    Token::new(Type::Number(accumulator))
+}
+
+fn lex_prefixed_number(
+    chars: &mut Cursor,
+    options: &LexerOptions,
+    errors: &mut Vec<LexError>,
+) -> Option<Token> {
+    let rest = &chars.source[chars.offset..];
+    let (prefix, radix) = options
+        .radix_prefixes
+        .iter()
+        .filter(|(prefix, radix)| {
+            !prefix.is_empty()
+                && rest.starts_with(prefix.as_str())
+                && rest[prefix.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_digit(*radix))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())?;
+    let start = chars.offset;
+    chars.offset += prefix.len();
+    Some(lex_number(chars, start, *radix, options, errors))
+}
+
+fn is_identifier_start(c: char, options: &LexerOptions) -> bool {
+    match options.ascii_identifiers {
+        true => c.is_ascii_alphabetic() || c == '_',
+        false => c == '_' || unicode_ident::is_xid_start(c),
+    }
+}
+
+fn is_identifier_continue(c: char, options: &LexerOptions) -> bool {
+    match options.ascii_identifiers {
+        true => c.is_ascii_alphanumeric() || c == '_',
+        false => unicode_ident::is_xid_continue(c),
+    }
+}
+
+fn lex_identifier(chars: &mut Cursor, options: &LexerOptions) -> Token {
+    let start = chars.offset;
+    // ASCII letters, digits and `_` continue an identifier under either
+    // rule, so only other characters need decoding.
+    loop {
+        chars.eat_bytes_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if chars.peek_byte().is_none_or(|b| b.is_ascii())
+            || chars
+                .next_if(|&c| is_identifier_continue(c, options))
+                .is_none()
+        {
+            break;
+        }
+    }
+    let word = &chars.source[start..chars.offset];
+    let keyword = match options.keywords.get(word) {
+        None if options.case_insensitive_keywords => {
+            let lowered = word.to_lowercase();
+            options
+                .keywords
+                .iter()
+                .find(|(word, _)| word.to_lowercase() == lowered)
+                .map(|(_, keyword)| keyword)
+        }
+        keyword => keyword,
+    };
+    Token::new(match keyword {
+        Some(keyword) => Type::Keyword(keyword.clone()),
+        None => Type::Identifier(word.to_string()),
+    })
+}
+
+/// The built-in operator starting with `first`, followed by `second`, and
+/// its length in bytes.
+fn operator_at(first: char, second: Option<char>) -> Option<(Type, usize)> {
+    let (operator, len) = match (first, second) {
+        ('-', Some('>')) => return Some((Type::Arrow, 2)),
+        ('=', Some('>')) => return Some((Type::FatArrow, 2)),
+        ('=', Some('=')) => (Operator::DoubleEquals, 2),
+        ('!', Some('=')) => (Operator::NotEquals, 2),
+        ('>', Some('=')) => (Operator::GreaterEqual, 2),
+        ('>', Some('>')) => (Operator::Shr, 2),
+        ('<', Some('=')) => (Operator::LessEqual, 2),
+        ('<', Some('<')) => (Operator::Shl, 2),
+        ('&', Some('&')) => (Operator::And, 2),
+        ('|', Some('|')) => (Operator::Or, 2),
+        ('+', _) => (Operator::Plus, 1),
+        ('-', _) => (Operator::Minus, 1),
+        ('*', _) => (Operator::Star, 1),
+        ('/', _) => (Operator::Slash, 1),
+        ('=', _) => (Operator::Equals, 1),
+        ('!', _) => (Operator::Bang, 1),
+        ('%', _) => (Operator::Mod, 1),
+        ('>', _) => (Operator::Greater, 1),
+        ('<', _) => (Operator::Less, 1),
+        ('&', _) => (Operator::BitAnd, 1),
+        ('|', _) => (Operator::BitOr, 1),
+        ('^', _) => (Operator::BitXor, 1),
+        ('~', _) => (Operator::BitNot, 1),
+        _ => return None,
+    };
+    Some((Type::Operator(operator), len))
+}
+
+/// Lexes the built-in operator at the cursor, if there is one.
+fn lex_operator(chars: &mut Cursor) -> Option<Token> {
+    let (token_type, len) = operator_at(chars.peek()?, chars.peek_second())?;
+    chars.offset += len;
+    Some(Token::new(token_type))
+}
+
+fn is_operator_start(c: char) -> bool {
+    operator_at(c, None).is_some()
+}
+
+fn lex_custom_operator(chars: &mut Cursor, options: &LexerOptions) -> Option<Token> {
+    let rest = &chars.source[chars.offset..];
+    let op = options
+        .operators
+        .iter()
+        .filter(|op| !op.is_empty() && rest.starts_with(op.as_str()))
+        .max_by_key(|op| op.len())?;
+    // Maximal munch: a longer built-in operator beats a shorter custom one.
+    if let Some((_, len)) = operator_at(chars.peek()?, chars.peek_second())
+        && len > op.len()
+    {
+        return None;
+    }
+    chars.offset += op.len();
+    Some(Token::new(Type::Operator(Operator::Custom(op.clone()))))
+}
+
+fn lex_preamble(chars: &mut Cursor, options: &LexerOptions, tokens: &mut Vec<Token>) {
+    if chars.offset > 0 {
+        return;
+    }
+    if chars.next_if(|&c| c == '\u{feff}').is_some() && options.trivia {
+        tokens.push(Token::with_span(
+            Type::Whitespace("\u{feff}".to_string()),
+            Span::new(0, chars.offset),
+        ));
+    }
+    let start = chars.offset;
+    if options.shebang && chars.source[start..].starts_with("#!") {
+        while chars.next_if(|&c| c != '\n').is_some() {}
+        if options.trivia {
+            tokens.push(Token::with_span(
+                Type::Shebang(chars.source[start..chars.offset].to_string()),
+                Span::new(start, chars.offset),
+            ));
+        }
+    }
+}
+
+fn is_whitespace(c: char, options: &LexerOptions) -> bool {
+    match &options.whitespace {
+        WhitespaceRule::Unicode => c.is_whitespace(),
+        WhitespaceRule::Only(set) => set.contains(&c),
+    }
+}
+
+/// Whether only whitespace precedes `offset` on its line, for rules that
+/// depend on line position.
+fn first_on_line(chars: &Cursor, offset: usize, options: &LexerOptions) -> bool {
+    chars.source[..offset]
+        .rsplit('\n')
+        .next()
+        .is_some_and(|line| line.chars().all(|c| is_whitespace(c, options)))
+}
+
+fn check_length(span: Span, options: &LexerOptions, warnings: &mut Vec<LexWarning>) {
+    if let Some(limit) = options.long_token_warning
+        && span.end - span.start > limit
+    {
+        warnings.push(LexWarning::LongToken(span));
+    }
+}
+
+fn check_graphemes(
+    start: usize,
+    text: &str,
+    options: &LexerOptions,
+    warnings: &mut Vec<LexWarning>,
+) {
+    if !options.combining_warning {
+        return;
+    }
+    let cluster = text
+        .grapheme_indices(true)
+        .find(|(_, g)| g.chars().nth(1).is_some());
+    if let Some((offset, g)) = cluster {
+        let start = start + offset;
+        warnings.push(LexWarning::CombiningSequence(Span::new(
+            start,
+            start + g.len(),
+        )));
+    }
+}
+
+/// Lexes the next token (or skipped run of input) starting at the cursor,
+/// appending whatever it produces to `out`. Returns the rule that matched,
+/// or `None` at the end of input.
+fn lex_step(chars: &mut Cursor, options: &LexerOptions, out: &mut LexOutput) -> Option<Rule> {
+    let trivia = options.trivia;
+    let LexOutput {
+        tokens,
+        errors,
+        warnings,
+    } = out;
+    let c = chars.peek()?;
+    let start = chars.offset;
+    let count = tokens.len();
+    let error_count = errors.len();
+    let custom = lex_custom_operator(chars, options);
+    let number = match custom {
+        Some(_) => None,
+        None => lex_prefixed_number(chars, options, errors),
+    };
+    let rule = match c {
+        _ if custom.is_some() => {
+            tokens.extend(custom);
+            Rule::CustomOperator
+        }
+        _ if number.is_some() => {
+            tokens.extend(number);
+            Rule::Number
+        }
+        '"' if options.multiline_strings && chars.rest().starts_with(r#"""""#) => {
+            match lex_multiline_string(chars, options) {
+                Ok(t) => {
+                    tokens.push(t);
+                    check_graphemes(start, &chars.source[start..chars.offset], options, warnings);
+                }
+                Err(e) => errors.push(e),
+            }
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::String
+        }
+        '"' if options.interpolation => {
+            let mut nested = LexOutput::default();
+            match lex_interpolated(chars, options, &mut nested) {
+                Ok(()) => {
+                    tokens.append(&mut nested.tokens);
+                    check_graphemes(start, &chars.source[start..chars.offset], options, warnings);
+                }
+                Err(e) => nested.errors.push(e),
+            }
+            errors.append(&mut nested.errors);
+            warnings.append(&mut nested.warnings);
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::String
+        }
+        '"' => {
+            match lex_string(chars, options.max_string_len) {
+                Ok(t) => {
+                    tokens.push(t);
+                    check_graphemes(start, &chars.source[start..chars.offset], options, warnings);
+                }
+                Err(e) => errors.push(e),
+            }
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::String
+        }
+        '0'..='9' => {
+            tokens.push(lex_number(chars, start, 10, options, errors));
+            Rule::Number
+        }
+        '(' => {
+            chars.next();
+            tokens.push(Token::new(Type::LeftParen));
+            Rule::Punctuation
+        }
+        ')' => {
+            chars.next();
+            tokens.push(Token::new(Type::RightParen));
+            Rule::Punctuation
+        }
+        '{' => {
+            chars.next();
+            tokens.push(Token::new(Type::LeftBrace));
+            Rule::Punctuation
+        }
+        '}' => {
+            chars.next();
+            tokens.push(Token::new(Type::RightBrace));
+            Rule::Punctuation
+        }
+        '.' => {
+            chars.next();
+            match chars.peek() {
+                Some(c) if options.directives && is_identifier_start(c, options) => {
+                    while chars
+                        .next_if(|&c| is_identifier_continue(c, options))
+                        .is_some()
+                    {}
+                    tokens.push(Token::new(Type::Directive(
+                        chars.source[start + 1..chars.offset].to_string(),
+                    )));
+                    Rule::Identifier
+                }
+                _ => {
+                    tokens.push(Token::new(Type::Dot));
+                    Rule::Punctuation
+                }
+            }
+        }
+        '#' if options.attributes && chars.peek_second() == Some('[') => {
+            let mut nested = LexOutput::default();
+            match lex_attribute(chars, options, &mut nested) {
+                Ok(()) => tokens.append(&mut nested.tokens),
+                Err(e) => nested.errors.push(e),
+            }
+            errors.append(&mut nested.errors);
+            warnings.append(&mut nested.warnings);
+            Rule::Attribute
+        }
+        '@' if options.decorators
+            && chars
+                .peek_second()
+                .is_some_and(|c| is_identifier_start(c, options)) =>
+        {
+            chars.next();
+            while chars
+                .next_if(|&c| is_identifier_continue(c, options))
+                .is_some()
+            {}
+            tokens.push(Token::new(Type::Decorator(
+                chars.source[start + 1..chars.offset].to_string(),
+            )));
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::Identifier
+        }
+        ',' => {
+            chars.next();
+            tokens.push(Token::new(Type::Comma));
+            Rule::Punctuation
+        }
+        '[' => {
+            chars.next();
+            tokens.push(Token::new(Type::LeftBracket));
+            Rule::Punctuation
+        }
+        ']' => {
+            chars.next();
+            tokens.push(Token::new(Type::RightBracket));
+            Rule::Punctuation
+        }
+        ':' => {
+            chars.next();
+            match chars.next_if(|&c| c == ':') {
+                Some(_) => tokens.push(Token::new(Type::DoubleColon)),
+                None => tokens.push(Token::new(Type::Colon)),
+            }
+            Rule::Punctuation
+        }
+        '?' => {
+            chars.next();
+            match chars.peek() {
+                Some('?') => {
+                    chars.next();
+                    tokens.push(Token::new(Type::Operator(Operator::Coalesce)));
+                    Rule::Operator
+                }
+                Some('.') => {
+                    chars.next();
+                    tokens.push(Token::new(Type::QuestionDot));
+                    Rule::Punctuation
+                }
+                _ => {
+                    tokens.push(Token::new(Type::Question));
+                    Rule::Punctuation
+                }
+            }
+        }
+        '/' if options.doc_comments && starts_doc_comment(chars.rest()) => {
+            match lex_doc_comment(chars, options.max_nesting) {
+                Ok(t) => tokens.push(t),
+                Err(e) => errors.push(e),
+            }
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::BlockComment
+        }
+        '/' if (options.line_comments && chars.peek_second() == Some('/'))
+            || (options.doc_comments && chars.rest().starts_with("////")) =>
+        {
+            let text = chars.eat_while(|c| c != '\n');
+            if trivia {
+                tokens.push(Token::new(Type::Comment(text.to_string())));
+            }
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::LineComment
+        }
+        '/' if chars.peek_second() == Some('*') => {
+            match skip_block_comment(chars, options.max_nesting) {
+                Ok(()) if trivia => tokens.push(Token::new(Type::Comment(
+                    chars.source[start..chars.offset].to_string(),
+                ))),
+                Ok(()) => (),
+                Err(e) => errors.push(e),
+            }
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::BlockComment
+        }
+        _ if is_operator_start(c) => {
+            tokens.extend(lex_operator(chars));
+            Rule::Operator
+        }
+        ';' => {
+            chars.next();
+            tokens.push(Token::new(Type::Semicolon));
+            Rule::Punctuation
+        }
+        _ if is_identifier_start(c, options) => {
+            let token = lex_identifier(chars, options);
+            let rest = &chars.source[chars.offset..];
+            if let Type::Keyword(keyword) = &token.token_type
+                && rest.starts_with('(')
+                && options.keyword_call_warning.contains(keyword)
+            {
+                warnings.push(LexWarning::KeywordCall(
+                    keyword.clone(),
+                    Span::new(start, chars.offset),
+                ));
+            }
+            match options.labels
+                && rest.starts_with(':')
+                && !rest.starts_with("::")
+                && first_on_line(chars, start, options)
+            {
+                true => {
+                    let name = chars.source[start..chars.offset].to_string();
+                    chars.next();
+                    tokens.push(Token::new(Type::Label(name)));
+                }
+                false => tokens.push(token),
+            }
+            check_length(Span::new(start, chars.offset), options, warnings);
+            Rule::Identifier
+        }
+        _ if is_whitespace(c, options) => {
+            if let WhitespaceRule::Unicode = options.whitespace {
+                chars.eat_bytes_while(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'));
+            }
+            while let Some(w) = chars.next_if(|&c| is_whitespace(c, options)) {
+                if options.unusual_whitespace_warning && !matches!(w, ' ' | '\t' | '\n' | '\r') {
+                    warnings.push(LexWarning::UnusualWhitespace(Span::new(
+                        chars.offset - w.len_utf8(),
+                        chars.offset,
+                    )));
+                }
+            }
+            if trivia {
+                tokens.push(Token::new(Type::Whitespace(
+                    chars.source[start..chars.offset].to_string(),
+                )));
+            }
+            Rule::Whitespace
+        }
+        _ => {
+            chars.next();
+            match options.unknown_chars {
+                UnknownCharRule::Token => tokens.push(Token::new(Type::Unknown(c))),
+                UnknownCharRule::Error => errors.push(LexError::UnknownCharacter(c, start)),
+                UnknownCharRule::Skip => (),
+            }
+            if trivia && options.unknown_chars != UnknownCharRule::Token {
+                tokens.push(Token::new(Type::None));
+            }
+            Rule::Unknown
+        }
+    };
+    // Interpolated strings produce several tokens, spanned as they are lexed.
+    if tokens.len() == count + 1 {
+        tokens[count].span = Span::new(start, chars.offset);
+    }
+    // Whatever the step made of an overlong token gives way to one error,
+    // unless a limit of its own kind already caught it.
+    if exceeds(options.max_token_len, chars.offset - start)
+        && rule != Rule::Attribute
+        && (tokens.len() > count || errors.len() > error_count)
+        && !errors[error_count..]
+            .iter()
+            .any(|e| matches!(e, LexError::LimitExceeded(..)))
+    {
+        tokens.truncate(count);
+        errors.truncate(error_count);
+        errors.push(LexError::LimitExceeded(Limit::TokenLength, start));
+    }
+    Some(rule)
+}
+
+/// Roughly how many bytes of typical source make one token, whitespace
+/// included, for sizing token vectors up front.
+const BYTES_PER_TOKEN: usize = 6;
+
+fn lex_helper(mut chars: Cursor, options: &LexerOptions, out: &mut LexOutput) {
+    out.tokens
+        .reserve(chars.source[chars.offset..].len() / BYTES_PER_TOKEN);
+    lex_preamble(&mut chars, options, &mut out.tokens);
+    while chars.peek().is_some() && !error_limit_reached(options, out.errors.len()) {
+        lex_step(&mut chars, options, out);
+        if token_limit_reached(options, 0, out) {
+            break;
+        }
+    }
+}
+
+fn error_limit_reached(options: &LexerOptions, errors: usize) -> bool {
+    options.max_errors.is_some_and(|max| errors >= max)
+}
+
+/// Enforces [`LexerOptions::max_tokens`] on the tokens of `out`, which
+/// follow `lexed` tokens already handed on: drops the tokens past the limit
+/// and reports it. Returns whether lexing has to stop.
+fn token_limit_reached(options: &LexerOptions, lexed: usize, out: &mut LexOutput) -> bool {
+    match options.max_tokens {
+        Some(max) if lexed + out.tokens.len() > max => {
+            let keep = max.saturating_sub(lexed);
+            let at = out.tokens[keep].span.start;
+            out.tokens.truncate(keep);
+            out.errors.push(LexError::LimitExceeded(Limit::Tokens, at));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Whether `len` is past `limit`, if there is one.
+fn exceeds(limit: Option<usize>, len: usize) -> bool {
+    limit.is_some_and(|max| len > max)
+}
+
+/// One item of a [`LexStream`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexEvent {
+    Token(Token),
+    Error(LexError),
+    Warning(LexWarning),
+}
+
+impl LexEvent {
+    /// The byte offset this event is reported at: a token's start, or the
+    /// position an error or warning points to.
+    pub fn offset(&self) -> usize {
+        match self {
+            LexEvent::Token(token) => token.span.start,
+            LexEvent::Error(error) => error.span().start,
+            LexEvent::Warning(LexWarning::LongToken(span))
+            | LexEvent::Warning(LexWarning::UnusualWhitespace(span))
+            | LexEvent::Warning(LexWarning::CombiningSequence(span))
+            | LexEvent::Warning(LexWarning::KeywordCall(_, span)) => span.start,
+        }
+    }
+}
+
+/// Callbacks for [`Lexer::lex_observed`], called in source order as lexing
+/// progresses. Every method does nothing by default.
+pub trait LexObserver {
+    fn on_token(&mut self, _token: &Token) {}
+
+    fn on_error(&mut self, _error: &LexError) {}
+
+    fn on_warning(&mut self, _warning: &LexWarning) {}
+
+    /// Called when lexing enters, switches or leaves a [`Mode`], after the
+    /// events of the step that did so. `transition` is never
+    /// [`Transition::Stay`].
+    fn on_mode_change(&mut self, _transition: &Transition) {}
+
+    /// Called once after everything else, with the length of the input.
+    fn on_eof(&mut self, _len: usize) {}
+}
+
+/// Lexes lazily, yielding tokens, errors and warnings as a single stream.
+///
+/// Events come out in source order: their [`LexEvent::offset`]s never
+/// decrease, so a consumer can merge them straight into one ordered log.
+/// Events at the same offset are yielded token first, then errors, then
+/// warnings. Lexing carries on past errors and enters the lexer's modes, like
+/// [`Lexer::lex_with_diagnostics`], but since layout and filters need every
+/// token, the stream adds no layout tokens and runs no filters. With
+/// [`LexerOptions::eof`], the last event is the [`Type::Eof`] token.
+pub struct LexStream<'a> {
+    chars: Cursor<'a>,
+    options: &'a LexerOptions,
+    modes: &'a Modes,
+    stack: ModeStack,
+    started: bool,
+    step: LexOutput,
+    pending: VecDeque<Queued>,
+    errors: usize,
+    tokens: usize,
+    /// Whether lexing has finished and any [`Type::Eof`] been queued.
+    ended: bool,
+}
+
+/// What a [`LexStream`] has lexed but not yet handed on.
+enum Queued {
+    Event(LexEvent),
+    /// A change of mode, which only [`Lexer::lex_observed`] reports.
+    ModeChange(Transition),
+}
+
+impl Iterator for LexStream<'_> {
+    type Item = LexEvent;
+
+    fn next(&mut self) -> Option<LexEvent> {
+        loop {
+            if let Queued::Event(event) = self.next_queued()? {
+                return Some(event);
+            }
+        }
+    }
+}
+
+impl LexStream<'_> {
+    fn next_queued(&mut self) -> Option<Queued> {
+        while self.pending.is_empty() {
+            let mut transition = None;
+            if !self.started {
+                self.started = true;
+                lex_preamble(&mut self.chars, self.options, &mut self.step.tokens);
+            } else if self.chars.peek().is_some() && !error_limit_reached(self.options, self.errors)
+            {
+                transition = match self.modes.is_empty() {
+                    true => {
+                        lex_step(&mut self.chars, self.options, &mut self.step);
+                        None
+                    }
+                    false => {
+                        self.stack
+                            .step(self.modes, &mut self.chars, self.options, &mut self.step)
+                    }
+                };
+                if token_limit_reached(self.options, self.tokens, &mut self.step) {
+                    self.chars.offset = self.chars.source.len();
+                }
+                self.errors += self.step.errors.len();
+                self.tokens += self.step.tokens.len();
+            } else if !self.ended {
+                self.ended = true;
+                if self.options.eof {
+                    let len = self.chars.source.len();
+                    self.step
+                        .tokens
+                        .push(Token::with_span(Type::Eof, Span::new(len, len)));
+                }
+            } else {
+                return None;
+            }
+            let mut events = self
+                .step
+                .tokens
+                .drain(..)
+                .map(LexEvent::Token)
+                .chain(self.step.errors.drain(..).map(LexEvent::Error))
+                .chain(self.step.warnings.drain(..).map(LexEvent::Warning))
+                .collect::<Vec<LexEvent>>();
+            events.sort_by_key(LexEvent::offset);
+            self.pending.extend(events.into_iter().map(Queued::Event));
+            self.pending.extend(transition.map(Queued::ModeChange));
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Which characters separate tokens.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum WhitespaceRule {
+    /// Every character with the Unicode `White_Space` property.
+    Unicode,
+    /// Exactly the listed characters.
+    Only(Vec<char>),
+}
+
+/// What becomes of a character no rule matches.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum UnknownCharRule {
+    /// Drop it, as if it were whitespace.
+    #[default]
+    Skip,
+    /// Lex it as a [`Type::Unknown`] token.
+    Token,
+    /// Report a [`LexError::UnknownCharacter`].
+    Error,
+}
+
+/// Settings shared by every lex performed with a [`Lexer`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LexerOptions {
+    /// Maps reserved words to the keyword they lex as. Words not in the table
+    /// lex as identifiers.
+    pub keywords: BTreeMap<String, Keyword>,
+    /// Extra operators, lexed as [`Operator::Custom`]. The longest match at a
+    /// position wins, whether custom or built-in.
+    pub operators: Vec<String>,
+    /// Prefixes introducing a number in another radix, such as `("0x", 16)`
+    /// or `("%", 2)`. A prefix only applies when a digit of its radix follows
+    /// it, so `%` can still be an operator; custom operators take precedence.
+    pub radix_prefixes: Vec<(String, u32)>,
+    /// Characters allowed between the digits of a number for readability,
+    /// such as `_` in `1_000`. They do not change the value.
+    pub digit_separators: Vec<char>,
+    /// Emit whitespace and comments as tokens, as [`lex_lossless`] does.
+    pub trivia: bool,
+    /// Skip a `#!` line at the very start of the input (after any byte order
+    /// mark). A leading byte order mark is always skipped.
+    pub shebang: bool,
+    /// Restrict identifiers to `[A-Za-z_][A-Za-z0-9_]*` instead of Unicode
+    /// `XID_Start`/`XID_Continue` (plus a leading underscore).
+    pub ascii_identifiers: bool,
+    /// Match keywords regardless of case, so `DEFINE`, `Define` and `define`
+    /// all lex as [`Keyword::Define`].
+    pub case_insensitive_keywords: bool,
+    pub whitespace: WhitespaceRule,
+    /// How strictly to treat characters no rule matches, such as a stray
+    /// `$`; dropping them, the default, can hide typos.
+    pub unknown_chars: UnknownCharRule,
+    /// Lex an identifier followed by `:` as a [`Type::Label`] when it is the
+    /// first thing on its line.
+    pub labels: bool,
+    /// Lex `.` directly followed by an identifier as a [`Type::Directive`].
+    pub directives: bool,
+    /// Lex `${...}` inside string literals as an interpolated expression;
+    /// see [`Type::StringPart`].
+    pub interpolation: bool,
+    /// Lex `#[...]` as an attribute between a [`Type::AttributeStart`] and a
+    /// [`Type::AttributeEnd`].
+    pub attributes: bool,
+    /// Lex `@` directly followed by an identifier as a [`Type::Decorator`].
+    pub decorators: bool,
+    /// Lex `"""` as the start of a string literal that may span lines and
+    /// ends at the next `"""`. Interpolation does not apply inside it.
+    pub multiline_strings: bool,
+    /// Strip the indentation of `"""` strings: a line break right after the
+    /// opening quotes is dropped, and so is the indentation common to the
+    /// lines that are not blank and to the closing line. Closing quotes on a
+    /// line of their own thus end the string with a line break.
+    pub dedent_strings: bool,
+    /// Mark the block structure given by indentation with [`Type::Newline`],
+    /// [`Type::Indent`] and [`Type::Dedent`] tokens, as in Python. The tokens
+    /// are added before the lexer's filters run, and only by the entry points
+    /// that run them.
+    pub layout: bool,
+    /// End the tokens with a [`Type::Eof`], after the lexer's filters have
+    /// run. Only the entry points that run filters add it.
+    pub eof: bool,
+    /// Lex `///` to the end of its line, and `/** */` blocks, as
+    /// [`Type::DocComment`]s. They are produced whether or not trivia is
+    /// kept, for documentation tools to attach to the tokens that follow.
+    /// As in Rust, `////` starts a plain line comment instead.
+    pub doc_comments: bool,
+    /// Lex `//` to the end of its line as a comment, which like a block
+    /// comment is trivia. With [`LexerOptions::doc_comments`], `///` is
+    /// still a doc comment.
+    pub line_comments: bool,
+    /// Warn about every whitespace character other than space, tab, `\n` and
+    /// `\r`, such as a stray non-breaking space.
+    pub unusual_whitespace_warning: bool,
+    /// Warn about string literals whose character count differs from their
+    /// grapheme count.
+    pub combining_warning: bool,
+    /// Warn about any string, comment or identifier longer than this many
+    /// bytes, which usually means a construct ran away with the rest of the
+    /// file.
+    pub long_token_warning: Option<usize>,
+    /// Keywords to warn about when written like a function call, which is a
+    /// common mistake for keywords that are not call-like in the language.
+    pub keyword_call_warning: Vec<Keyword>,
+    /// Stop lexing once this many errors have been reported, so binary or
+    /// badly broken input does not flood the caller with errors.
+    pub max_errors: Option<usize>,
+    /// Reject any token, string or comment longer than this many bytes,
+    /// skipped trivia aside, with [`Limit::TokenLength`]. The token is
+    /// dropped and lexing carries on after it.
+    pub max_token_len: Option<usize>,
+    /// Reject string literals holding more than this many bytes between
+    /// their quotes with [`Limit::StringLength`], before their contents are
+    /// copied. An interpolated string is skipped to the end of its line.
+    pub max_string_len: Option<usize>,
+    /// Reject block comments and interpolated strings nested more than this
+    /// many levels deep, the outermost included, with [`Limit::Nesting`].
+    /// Interpolated strings never nest deeper than [`MAX_NESTING`] either.
+    pub max_nesting: Option<usize>,
+    /// Stop lexing with [`Limit::Tokens`] at the first token past this many,
+    /// counting those the lexer's rules produce before layout, filters and
+    /// the [`Type::Eof`] are applied.
+    pub max_tokens: Option<usize>,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        LexerOptions {
+            keywords: KEYWORDS
+                .into_iter()
+                .map(|(word, keyword)| (word.to_string(), keyword))
+                .collect(),
+            operators: Vec::new(),
+            radix_prefixes: Vec::new(),
+            digit_separators: Vec::new(),
+            trivia: false,
+            shebang: true,
+            ascii_identifiers: false,
+            case_insensitive_keywords: false,
+            whitespace: WhitespaceRule::Unicode,
+            unknown_chars: UnknownCharRule::Skip,
+            labels: false,
+            directives: false,
+            interpolation: false,
+            attributes: false,
+            decorators: false,
+            multiline_strings: false,
+            dedent_strings: false,
+            layout: false,
+            eof: false,
+            doc_comments: false,
+            line_comments: false,
+            unusual_whitespace_warning: false,
+            combining_warning: false,
+            long_token_warning: None,
+            keyword_call_warning: Vec::new(),
+            max_errors: None,
+            max_token_len: None,
+            max_string_len: None,
+            max_nesting: None,
+            max_tokens: None,
+        }
+    }
+}
+
+/// Builds a [`Lexer`], starting from the default [`LexerOptions`].
+#[derive(Debug, Clone, Default)]
+pub struct LexerBuilder {
+    options: LexerOptions,
+    filters: Pipeline,
+    modes: Modes,
+}
+
+impl LexerBuilder {
+    pub fn new() -> Self {
+        LexerBuilder::default()
+    }
+
+    /// A starting point for assembly-like languages: `name:` labels and
+    /// `.directives`, the registers `r0` to `r15`, `sp`, `lr` and `pc` as
+    /// keywords in place of the usual ones, `0x` and `0b` prefixed numbers,
+    /// and `_` as a digit separator.
+    pub fn assembly() -> Self {
+        let registers = (0..16)
+            .map(|i| format!("r{}", i))
+            .chain(["sp", "lr", "pc"].map(String::from))
+            .map(|r| (r.clone(), Keyword::Custom(r)));
+        LexerBuilder::new()
+            .keywords(registers)
+            .labels(true)
+            .directives(true)
+            .radix_prefix("0x", 16)
+            .radix_prefix("0b", 2)
+            .digit_separator('_')
+    }
+
+    /// Replaces the whole keyword table.
+    pub fn keywords<S: Into<String>>(
+        mut self,
+        keywords: impl IntoIterator<Item = (S, Keyword)>,
+    ) -> Self {
+        self.options.keywords = keywords
+            .into_iter()
+            .map(|(word, keyword)| (word.into(), keyword))
+            .collect();
+        self
+    }
+
+    /// Adds (or remaps) a single reserved word.
+    pub fn keyword(mut self, word: impl Into<String>, keyword: Keyword) -> Self {
+        self.options.keywords.insert(word.into(), keyword);
+        self
+    }
+
+    /// Stops treating `word` as reserved, so it lexes as an identifier.
+    pub fn remove_keyword(mut self, word: &str) -> Self {
+        self.options.keywords.remove(word);
+        self
+    }
+
+    /// Registers an extra operator such as `|>` or `<=>`.
+    pub fn operator(mut self, op: impl Into<String>) -> Self {
+        self.options.operators.push(op.into());
+        self
+    }
+
+    /// Lexes numbers starting with `prefix` in `radix`.
+    ///
+    /// Panics if `radix` is not between 2 and 36.
+    pub fn radix_prefix(mut self, prefix: impl Into<String>, radix: u32) -> Self {
+        assert!((2..=36).contains(&radix), "radix {} is out of range", radix);
+        self.options.radix_prefixes.push((prefix.into(), radix));
+        self
+    }
+
+    pub fn digit_separator(mut self, separator: char) -> Self {
+        self.options.digit_separators.push(separator);
+        self
+    }
+
+    pub fn trivia(mut self, trivia: bool) -> Self {
+        self.options.trivia = trivia;
+        self
+    }
+
+    pub fn shebang(mut self, shebang: bool) -> Self {
+        self.options.shebang = shebang;
+        self
+    }
+
+    pub fn ascii_identifiers(mut self, ascii_identifiers: bool) -> Self {
+        self.options.ascii_identifiers = ascii_identifiers;
+        self
+    }
+
+    pub fn case_insensitive_keywords(mut self, case_insensitive_keywords: bool) -> Self {
+        self.options.case_insensitive_keywords = case_insensitive_keywords;
+        self
+    }
+
+    /// Treats exactly `whitespace` as whitespace. Other characters the
+    /// lexer has no rule for are unknown, see [`LexerBuilder::unknown_chars`].
+    pub fn whitespace(mut self, whitespace: impl IntoIterator<Item = char>) -> Self {
+        self.options.whitespace = WhitespaceRule::Only(whitespace.into_iter().collect());
+        self
+    }
+
+    pub fn unknown_chars(mut self, rule: UnknownCharRule) -> Self {
+        self.options.unknown_chars = rule;
+        self
+    }
+
+    pub fn labels(mut self, labels: bool) -> Self {
+        self.options.labels = labels;
+        self
+    }
+
+    pub fn directives(mut self, directives: bool) -> Self {
+        self.options.directives = directives;
+        self
+    }
+
+    pub fn interpolation(mut self, interpolation: bool) -> Self {
+        self.options.interpolation = interpolation;
+        self
+    }
+
+    pub fn attributes(mut self, attributes: bool) -> Self {
+        self.options.attributes = attributes;
+        self
+    }
+
+    pub fn decorators(mut self, decorators: bool) -> Self {
+        self.options.decorators = decorators;
+        self
+    }
+
+    pub fn multiline_strings(mut self, multiline_strings: bool) -> Self {
+        self.options.multiline_strings = multiline_strings;
+        self
+    }
+
+    pub fn dedent_strings(mut self, dedent_strings: bool) -> Self {
+        self.options.dedent_strings = dedent_strings;
+        self
+    }
+
+    pub fn layout(mut self, layout: bool) -> Self {
+        self.options.layout = layout;
+        self
+    }
+
+    pub fn doc_comments(mut self, doc_comments: bool) -> Self {
+        self.options.doc_comments = doc_comments;
+        self
+    }
+
+    pub fn line_comments(mut self, line_comments: bool) -> Self {
+        self.options.line_comments = line_comments;
+        self
+    }
+
+    pub fn eof(mut self, eof: bool) -> Self {
+        self.options.eof = eof;
+        self
+    }
+
+    pub fn unusual_whitespace_warning(mut self, unusual_whitespace_warning: bool) -> Self {
+        self.options.unusual_whitespace_warning = unusual_whitespace_warning;
+        self
+    }
+
+    pub fn combining_warning(mut self, combining_warning: bool) -> Self {
+        self.options.combining_warning = combining_warning;
+        self
+    }
+
+    pub fn long_token_warning(mut self, limit: usize) -> Self {
+        self.options.long_token_warning = Some(limit);
+        self
+    }
+
+    pub fn keyword_call_warning(mut self, keyword: Keyword) -> Self {
+        self.options.keyword_call_warning.push(keyword);
+        self
+    }
+
+    pub fn max_errors(mut self, limit: usize) -> Self {
+        self.options.max_errors = Some(limit);
+        self
+    }
+
+    pub fn max_token_len(mut self, limit: usize) -> Self {
+        self.options.max_token_len = Some(limit);
+        self
+    }
+
+    pub fn max_string_len(mut self, limit: usize) -> Self {
+        self.options.max_string_len = Some(limit);
+        self
+    }
+
+    pub fn max_nesting(mut self, limit: usize) -> Self {
+        self.options.max_nesting = Some(limit);
+        self
+    }
+
+    pub fn max_tokens(mut self, limit: usize) -> Self {
+        self.options.max_tokens = Some(limit);
+        self
+    }
+
+    /// Adds `filter` as the last stage of the lexer's [`Pipeline`].
+    pub fn filter(mut self, filter: impl TokenFilter + 'static) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Adds `mode` to the lexer's [`Modes`], entered where `trigger` starts.
+    ///
+    /// Panics if `trigger` is empty.
+    pub fn mode(mut self, trigger: impl Into<String>, mode: impl Mode + 'static) -> Self {
+        self.modes.push(trigger, mode);
+        self
+    }
+
+    pub fn build(self) -> Lexer {
+        Lexer {
+            options: self.options,
+            filters: self.filters,
+            modes: self.modes,
+        }
+    }
+}
+
+/// A configured lexer. [`try_lex`] and [`lex_lossless`] are shorthands for
+/// lexing with the default options.
+///
+/// The lexer's own rules never panic, whatever the input: malformed input
+/// becomes [`LexError`]s, and [`Lexer::lex_bytes`] takes input that is not
+/// even UTF-8.
+#[derive(Debug, Clone, Default)]
+pub struct Lexer {
+    options: LexerOptions,
+    filters: Pipeline,
+    modes: Modes,
+}
+
+impl Lexer {
+    pub fn new(options: LexerOptions) -> Self {
+        Lexer {
+            options,
+            filters: Pipeline::new(),
+            modes: Modes::new(),
+        }
+    }
+
+    pub fn builder() -> LexerBuilder {
+        LexerBuilder::new()
+    }
+
+    pub fn options(&self) -> &LexerOptions {
+        &self.options
+    }
+
+    /// Lexes `s` into spanned tokens, failing with the first [`LexError`]
+    /// encountered.
+    pub fn lex(&self, s: &str) -> Result<Vec<Token>, LexError> {
+        let mut output = self.lex_with_diagnostics(s);
+        match output.errors.is_empty() {
+            true => Ok(output.tokens),
+            false => Err(output.errors.remove(0)),
+        }
+    }
+
+    /// Lexes all of `s`, returning every token and every error rather than
+    /// stopping at the first, up to [`LexerOptions::max_errors`].
+    pub fn lex_all_errors(&self, s: &str) -> (Vec<Token>, Vec<LexError>) {
+        let output = self.lex_with_diagnostics(s);
+        (output.tokens, output.errors)
+    }
+
+    /// Lexes the text of `file`; see [`Lexer::lex_with_diagnostics`].
+    pub fn lex_file(&self, file: &SourceFile) -> LexOutput {
+        self.lex_with_diagnostics(file.text())
+    }
+
+    /// Lexes `s` lazily; see [`LexStream`].
+    pub fn stream<'a>(&'a self, s: &'a str) -> LexStream<'a> {
+        LexStream {
+            chars: Cursor::new(s),
+            options: &self.options,
+            modes: &self.modes,
+            stack: ModeStack::new(),
+            started: false,
+            step: LexOutput::default(),
+            pending: VecDeque::new(),
+            errors: 0,
+            tokens: 0,
+            ended: false,
+        }
+    }
+
+    /// Lexes all of `s`, skipping past errors, and returns every token along
+    /// with every error and warning produced on the way.
+    pub fn lex_with_diagnostics(&self, s: &str) -> LexOutput {
+        let mut output = LexOutput::default();
+        self.lex_raw(Cursor::new(s), &mut output);
+        self.finish(s, &mut output);
+        output
+    }
+
+    /// Like [`Lexer::lex`], writing the tokens into `tokens` in place of its
+    /// contents, so that a caller lexing many small inputs can keep reusing
+    /// one allocation. On failure `tokens` holds whatever was lexed.
+    pub fn lex_into(&self, s: &str, tokens: &mut Vec<Token>) -> Result<(), LexError> {
+        tokens.clear();
+        let mut output = LexOutput {
+            tokens: ::core::mem::take(tokens),
+            ..LexOutput::default()
+        };
+        self.lex_raw(Cursor::new(s), &mut output);
+        self.finish(s, &mut output);
+        *tokens = output.tokens;
+        match output.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Lexes the rest of `chars` into `output` with the lexer's modes, if it
+    /// has any.
+    fn lex_raw(&self, chars: Cursor, output: &mut LexOutput) {
+        match self.modes.is_empty() {
+            true => lex_helper(chars, &self.options, output),
+            false => self.modes.lex(chars, &self.options, output),
+        }
+    }
+
+    /// Applies layout, the filters and the end of input token to the tokens
+    /// lexed from `s`.
+    fn finish(&self, s: &str, output: &mut LexOutput) {
+        if self.options.layout {
+            let tokens = ::core::mem::take(&mut output.tokens);
+            output.tokens = layout::layout(tokens, s, &mut output.errors);
+        }
+        if !self.filters.is_empty() {
+            let tokens = ::core::mem::take(&mut output.tokens);
+            output.tokens = self.filters.run(tokens, s);
+        }
+        if self.options.eof {
+            output
+                .tokens
+                .push(Token::with_span(Type::Eof, Span::new(s.len(), s.len())));
+        }
+    }
+
+    /// Lexes `s` as [`Lexer::stream`] does, reporting each token, error,
+    /// warning and change of mode to `observer` as soon as it is produced and
+    /// returning the tokens, errors and warnings.
+    pub fn lex_observed(&self, s: &str, observer: &mut impl LexObserver) -> LexOutput {
+        let mut output = LexOutput::default();
+        let mut stream = self.stream(s);
+        while let Some(queued) = stream.next_queued() {
+            let event = match queued {
+                Queued::Event(event) => event,
+                Queued::ModeChange(transition) => {
+                    observer.on_mode_change(&transition);
+                    continue;
+                }
+            };
+            match event {
+                LexEvent::Token(token) => {
+                    observer.on_token(&token);
+                    output.tokens.push(token);
+                }
+                LexEvent::Error(error) => {
+                    observer.on_error(&error);
+                    output.errors.push(error);
+                }
+                LexEvent::Warning(warning) => {
+                    observer.on_warning(&warning);
+                    output.warnings.push(warning);
+                }
+            }
+        }
+        observer.on_eof(s.len());
+        output
+    }
+}
+
+/// Lexes `s`, silently dropping any input that fails to lex.
+///
+/// This is the original entry point and is kept so existing callers keep
+/// compiling. Its error policy is fixed: malformed input (an unterminated
+/// string or comment) is skipped and lexing carries on, exactly as before
+/// errors were reported at all. It never panics. Tokens still carry spans.
+#[deprecated(note = "use `try_lex`, which reports lexing errors instead of dropping them")]
+pub fn lex(s: String) -> Vec<Token> {
+    let mut output = LexOutput::default();
+    lex_helper(Cursor::new(&s), &LexerOptions::default(), &mut output);
+    output.tokens
+}
+
+/// Lexes `s` into spanned tokens, failing with the first [`LexError`]
+/// encountered.
+pub fn try_lex(s: &str) -> Result<Vec<Token>, LexError> {
+    Lexer::default().lex(s)
+}
+
+/// Lexes everything `reader` produces with the default options, failing with
+/// the first error; see [`Lexer::lex_reader`].
+#[cfg(feature = "std")]
+pub fn lex_reader<R: std::io::Read>(reader: R) -> Result<Vec<Token>, ReadError> {
+    Lexer::default().lex_reader(reader)
+}
+
+/// Lexes `s` with the default options, collecting every error instead of
+/// stopping at the first; see [`Lexer::lex_all_errors`].
+pub fn lex_all_errors(s: &str) -> (Vec<Token>, Vec<LexError>) {
+    Lexer::default().lex_all_errors(s)
+}
+
+/// Lexes `s` without discarding anything: whitespace and comments come back
+/// as [`Type::Whitespace`] and [`Type::Comment`] tokens, and characters the
+/// lexer would otherwise skip come back as [`Type::None`]. The spans of the
+/// returned tokens tile the input, so concatenating `&s[t.span.start..t.span.end]`
+/// over all tokens reproduces `s` byte-for-byte.
+pub fn lex_lossless(s: &str) -> Result<Vec<Token>, LexError> {
+    Lexer::builder().trivia(true).build().lex(s)
+}
+
+/// Turns a token stream back into source text using each token's canonical
+/// lexeme. A stream without trivia gets a single space between adjacent
+/// tokens; a stream containing trivia is assumed to carry its own layout and
+/// is concatenated as-is, so the output of [`lex_lossless`] round-trips
+/// unchanged (apart from skipped characters, which have no lexeme).
+pub fn detokenize(tokens: &[Token]) -> String {
+    let separator = match tokens.iter().any(Token::is_trivia) {
+        true => "",
+        false => " ",
+    };
+    tokens
+        .iter()
+        .map(Token::to_string)
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+/// The index of the first significant token where `a` and `b` differ, along
+/// with the tokens there (`None` past the end of a stream). Trivia is
+/// skipped and spans are ignored, so the index counts significant tokens.
+pub fn first_difference<'a>(
+    a: &'a [Token],
+    b: &'a [Token],
+) -> Option<(usize, Option<&'a Token>, Option<&'a Token>)> {
+    let mut a = a.iter().filter(|t| !t.is_trivia());
+    let mut b = b.iter().filter(|t| !t.is_trivia());
+    let mut i = 0;
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return None,
+            (Some(x), Some(y)) if x.token_type == y.token_type => i += 1,
+            (x, y) => return Some((i, x, y)),
+        }
+    }
+}
+
+/// Whether `a` and `b` hold the same significant tokens, ignoring trivia and
+/// spans; true for two sources that differ only in layout and comments.
+pub fn tokens_equivalent(a: &[Token], b: &[Token]) -> bool {
+    first_difference(a, b).is_none()
+}
+
+/// Panics, naming the first difference, unless [`tokens_equivalent`] holds.
+#[track_caller]
+pub fn assert_tokens_equivalent(a: &[Token], b: &[Token]) {
+    if let Some((i, x, y)) = first_difference(a, b) {
+        let show = |t: Option<&Token>| match t {
+            Some(t) => format!("{:?} `{}` at {}..{}", t.kind(), t, t.span.start, t.span.end),
+            None => "end of stream".to_string(),
+        };
+        panic!(
+            "token streams differ at significant token {}: {} != {}",
+            i,
+            show(x),
+            show(y)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(deprecated)]
+
+    use crate::{
+        Assoc, Keyword, LexError, LexEvent, LexObserver, LexWarning, Lexer, LexerBuilder, Limit,
+        Operator, Span, Token, TokenCategory, TokenKind, TokenKindSet, Type, UnknownCharRule,
+        UnknownKeyword, assert_tokens_equivalent, detokenize, first_difference, lex,
+        lex_all_errors, lex_lossless, memory_footprint, operator_table, tokens_equivalent, try_lex,
+    };
+
+    fn types(s: &str) -> Vec<Type> {
+        try_lex(s)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn test() {
+        assert_eq!(types("\"meow\""), vec![Type::String("meow".to_string())]);
+        assert_eq!(
+            types("\"meow meow\""),
+            vec![Type::String("meow meow".to_string())]
+        );
+        assert_eq!(types("311"), vec![Type::Number(311)]);
+        assert_eq!(types("ident"), vec![Type::Identifier("ident".to_string())]);
+        assert_eq!(
+            types("empty()"),
+            vec![
+                Type::Identifier("empty".to_string()),
+                Type::LeftParen,
+                Type::RightParen
+            ]
+        );
+        assert_eq!(
+            types("1 + 1 == 5"),
+            vec![
+                Type::Number(1),
+                Type::Operator(Operator::Plus),
+                Type::Number(1),
+                Type::Operator(Operator::DoubleEquals),
+                Type::Number(5)
+            ]
+        );
+        assert_eq!(
+            types("define x = 5"),
+            vec![
+                Type::Keyword(Keyword::Define),
+                Type::Identifier("x".to_string()),
+                Type::Operator(Operator::Equals),
+                Type::Number(5)
+            ]
+        );
+        assert_eq!(types("true"), vec![Type::Keyword(Keyword::True)]);
+        assert_eq!(
+            types("if true"),
+            vec![Type::Keyword(Keyword::If), Type::Keyword(Keyword::True),]
+        );
+        assert_eq!(
+            types("if 4 == 4"),
+            vec![
+                Type::Keyword(Keyword::If),
+                Type::Number(4),
+                Type::Operator(Operator::DoubleEquals),
+                Type::Number(4)
+            ]
+        );
+        assert_eq!(
+            types("if 4 == 5"),
+            vec![
+                Type::Keyword(Keyword::If),
+                Type::Number(4),
+                Type::Operator(Operator::DoubleEquals),
+                Type::Number(5)
+            ]
+        );
+    }
+
+    #[test]
+    fn block_comments() {
+        assert_eq!(
+            types("1 /* one */ / 2"),
+            vec![
+                Type::Number(1),
+                Type::Operator(Operator::Slash),
+                Type::Number(2)
+            ]
+        );
+        assert_eq!(
+            types("/* a /* b */ c */ x"),
+            vec![Type::Identifier("x".to_string())]
+        );
+        assert_eq!(
+            try_lex("x /* a /* b */ c"),
+            Err(LexError::UnterminatedComment(2))
+        );
+        assert_eq!(try_lex("\"meow"), Err(LexError::UnterminatedString(0)));
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!(
+            try_lex("x == \"hi\""),
+            Ok(vec![
+                Token::with_span(Type::Identifier("x".to_string()), Span::new(0, 1)),
+                Token::with_span(Type::Operator(Operator::DoubleEquals), Span::new(2, 4)),
+                Token::with_span(Type::String("hi".to_string()), Span::new(5, 9)),
+            ])
+        );
+    }
+
+    #[test]
+    fn deprecated_lex_drops_errors() {
+        assert_eq!(
+            lex("\"oops\n1".to_string()),
+            vec![Token::with_span(Type::Number(1), Span::new(6, 7))]
+        );
+    }
+
+    #[test]
+    fn lossless() {
+        let source = "define x = 1; /* one\n /* two */ */\n\tif x $ { }\n";
+        let tokens = lex_lossless(source).unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| &source[t.span.start..t.span.end])
+                .collect::<String>(),
+            source
+        );
+        assert_eq!(
+            tokens[9].token_type,
+            Type::Comment("/* one\n /* two */ */".to_string())
+        );
+        assert_eq!(tokens[10].token_type, Type::Whitespace("\n\t".to_string()));
+        assert_eq!(tokens[15].token_type, Type::None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Operator::DoubleEquals.to_string(), "==");
+        assert_eq!(Keyword::Define.to_string(), "define");
+        assert_eq!(Type::String("hi".to_string()).to_string(), "\"hi\"");
+        assert_eq!(
+            detokenize(&[
+                Token::new(Type::Keyword(Keyword::Define)),
+                Token::new(Type::Identifier("x".to_string())),
+                Token::new(Type::Operator(Operator::Equals)),
+                Token::new(Type::Number(5)),
+                Token::new(Type::Semicolon),
+            ]),
+            "define x = 5 ;"
+        );
+        let source = "if (x != 1) { y = \"s\"; } /* done */\n";
+        assert_eq!(detokenize(&lex_lossless(source).unwrap()), source);
+        assert_eq!(
+            try_lex(&detokenize(&try_lex(source).unwrap()))
+                .unwrap()
+                .len(),
+            try_lex(source).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn token_kind_set() {
+        const OPERANDS: TokenKindSet =
+            TokenKindSet::new(&[TokenKind::Number, TokenKind::String, TokenKind::Identifier]);
+        let kinds = try_lex("x + \"y\" ; 3")
+            .unwrap()
+            .iter()
+            .map(|t| OPERANDS.contains(t.kind()))
+            .collect::<Vec<bool>>();
+        assert_eq!(kinds, vec![true, false, true, false, true]);
+        let set = OPERANDS.with(TokenKind::None).without(TokenKind::String);
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(TokenKind::None));
+        assert!(!set.contains(TokenKind::String));
+        assert!(TokenKindSet::EMPTY.is_empty());
+        assert_eq!(
+            OPERANDS.union(TokenKindSet::new(&[TokenKind::Semicolon])),
+            OPERANDS.with(TokenKind::Semicolon)
+        );
+    }
+
+    #[test]
+    fn control_flow_keywords() {
+        assert_eq!(
+            types("fn function let else while for return break continue elsewhere"),
+            vec![
+                Type::Keyword(Keyword::Function),
+                Type::Keyword(Keyword::Function),
+                Type::Keyword(Keyword::Let),
+                Type::Keyword(Keyword::Else),
+                Type::Keyword(Keyword::While),
+                Type::Keyword(Keyword::For),
+                Type::Keyword(Keyword::Return),
+                Type::Keyword(Keyword::Break),
+                Type::Keyword(Keyword::Continue),
+                Type::Identifier("elsewhere".to_string()),
+            ]
+        );
+        assert_eq!(Keyword::Function.to_string(), "fn");
+    }
+
+    #[test]
+    fn custom_keywords() {
+        let lexer = Lexer::builder()
+            .keywords([
+                ("let", Keyword::Let),
+                ("in", Keyword::Custom("in".to_string())),
+            ])
+            .keyword("fun", Keyword::Function)
+            .build();
+        assert_eq!(
+            lexer
+                .lex("let f = fun define in")
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token_type)
+                .collect::<Vec<Type>>(),
+            vec![
+                Type::Keyword(Keyword::Let),
+                Type::Identifier("f".to_string()),
+                Type::Operator(Operator::Equals),
+                Type::Keyword(Keyword::Function),
+                Type::Identifier("define".to_string()),
+                Type::Keyword(Keyword::Custom("in".to_string())),
+            ]
+        );
+        assert_eq!(
+            Lexer::builder()
+                .remove_keyword("if")
+                .build()
+                .lex("if")
+                .unwrap()[0]
+                .token_type,
+            Type::Identifier("if".to_string())
+        );
+        assert_eq!(Keyword::Custom("in".to_string()).to_string(), "in");
+    }
+
+    #[test]
+    fn custom_operators() {
+        let lexer = Lexer::builder()
+            .operator("|>")
+            .operator("**")
+            .operator("??")
+            .operator("<=>")
+            .operator("=")
+            .build();
+        assert_eq!(
+            lexer
+                .lex("a |> b ** 2 ?? c <=> d <= e == f")
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token_type)
+                .filter(|t| matches!(t, Type::Operator(_)))
+                .collect::<Vec<Type>>(),
+            vec![
+                Type::Operator(Operator::Custom("|>".to_string())),
+                Type::Operator(Operator::Custom("**".to_string())),
+                Type::Operator(Operator::Custom("??".to_string())),
+                Type::Operator(Operator::Custom("<=>".to_string())),
+                Type::Operator(Operator::LessEqual),
+                Type::Operator(Operator::DoubleEquals),
+            ]
+        );
+        assert_eq!(lexer.lex("a??b").unwrap()[1].span, Span::new(1, 3));
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_eq!(
+            types("xs[0]: a::b ? f -> g => h - > 1"),
+            vec![
+                Type::Identifier("xs".to_string()),
+                Type::LeftBracket,
+                Type::Number(0),
+                Type::RightBracket,
+                Type::Colon,
+                Type::Identifier("a".to_string()),
+                Type::DoubleColon,
+                Type::Identifier("b".to_string()),
+                Type::Question,
+                Type::Identifier("f".to_string()),
+                Type::Arrow,
+                Type::Identifier("g".to_string()),
+                Type::FatArrow,
+                Type::Identifier("h".to_string()),
+                Type::Operator(Operator::Minus),
+                Type::Operator(Operator::Greater),
+                Type::Number(1),
+            ]
+        );
+        assert_eq!(
+            types("a ?? b?.c ?d?: e??"),
+            vec![
+                Type::Identifier("a".to_string()),
+                Type::Operator(Operator::Coalesce),
+                Type::Identifier("b".to_string()),
+                Type::QuestionDot,
+                Type::Identifier("c".to_string()),
+                Type::Question,
+                Type::Identifier("d".to_string()),
+                Type::Question,
+                Type::Colon,
+                Type::Identifier("e".to_string()),
+                Type::Operator(Operator::Coalesce),
+            ]
+        );
+        let tokens = try_lex("x ?. y").unwrap();
+        assert_eq!(tokens[1].span, Span::new(2, 4));
+        assert_eq!(tokens[1].category(), TokenCategory::Punctuation);
+        assert_eq!(tokens[1].to_string(), "?.");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(
+            types("a & b | ~c ^ d << 2 >> 1 && e || f <= g >= h"),
+            vec![
+                Type::Identifier("a".to_string()),
+                Type::Operator(Operator::BitAnd),
+                Type::Identifier("b".to_string()),
+                Type::Operator(Operator::BitOr),
+                Type::Operator(Operator::BitNot),
+                Type::Identifier("c".to_string()),
+                Type::Operator(Operator::BitXor),
+                Type::Identifier("d".to_string()),
+                Type::Operator(Operator::Shl),
+                Type::Number(2),
+                Type::Operator(Operator::Shr),
+                Type::Number(1),
+                Type::Operator(Operator::And),
+                Type::Identifier("e".to_string()),
+                Type::Operator(Operator::Or),
+                Type::Identifier("f".to_string()),
+                Type::Operator(Operator::LessEqual),
+                Type::Identifier("g".to_string()),
+                Type::Operator(Operator::GreaterEqual),
+                Type::Identifier("h".to_string()),
+            ]
+        );
+        // Operators at the end of input are still operators.
+        assert_eq!(
+            types("x ="),
+            vec![
+                Type::Identifier("x".to_string()),
+                Type::Operator(Operator::Equals)
+            ]
+        );
+        assert_eq!(types("!"), vec![Type::Operator(Operator::Bang)]);
+    }
+
+    #[test]
+    fn categories() {
+        let tokens = Lexer::builder()
+            .trivia(true)
+            .labels(true)
+            .build()
+            .lex("start: x = \"a\" + 1; /* c */ true @")
+            .unwrap();
+        let categories: Vec<TokenCategory> = tokens.iter().map(Token::category).collect();
+        assert_eq!(
+            categories,
+            vec![
+                TokenCategory::Identifier,
+                TokenCategory::Trivia,
+                TokenCategory::Identifier,
+                TokenCategory::Trivia,
+                TokenCategory::Operator,
+                TokenCategory::Trivia,
+                TokenCategory::Literal,
+                TokenCategory::Trivia,
+                TokenCategory::Operator,
+                TokenCategory::Trivia,
+                TokenCategory::Literal,
+                TokenCategory::Punctuation,
+                TokenCategory::Trivia,
+                TokenCategory::Trivia,
+                TokenCategory::Trivia,
+                TokenCategory::Keyword,
+                TokenCategory::Trivia,
+                TokenCategory::Error,
+            ]
+        );
+        assert!(
+            tokens
+                .iter()
+                .all(|t| t.is_trivia() == (t.category() == TokenCategory::Trivia))
+        );
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let table = operator_table();
+        assert_eq!(table.len(), crate::OPERATORS.len());
+        assert_eq!(table[0], (Operator::Equals, 1, Assoc::Right));
+        assert!(table.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!(Operator::Star.precedence() > Operator::Plus.precedence());
+        assert!(Operator::Custom("|>".to_string()).precedence() < Operator::Or.precedence());
+        assert_eq!(Operator::Minus.associativity(), Assoc::Left);
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(
+            types("my_var _x x1 café \u{301}y"),
+            vec![
+                Type::Identifier("my_var".to_string()),
+                Type::Identifier("_x".to_string()),
+                Type::Identifier("x1".to_string()),
+                Type::Identifier("café".to_string()),
+                Type::Identifier("y".to_string()),
+            ]
+        );
+        assert_eq!(
+            types("1abc"),
+            vec![Type::Number(1), Type::Identifier("abc".to_string())]
+        );
+        assert_eq!(
+            Lexer::builder()
+                .ascii_identifiers(true)
+                .build()
+                .lex("café")
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token_type)
+                .collect::<Vec<Type>>(),
+            vec![Type::Identifier("caf".to_string())]
+        );
+    }
+
+    #[test]
+    fn footprint() {
+        let tokens = try_lex("x + 1").unwrap();
+        assert_eq!(tokens[1].memory_footprint(), size_of::<Token>());
         assert_eq!(
-            lex("true".to_string()),
-            vec![Token::new(Type::Keyword(Keyword::True))]
+            memory_footprint(&tokens),
+            3 * size_of::<Token>() + tokens[0].memory_footprint() - size_of::<Token>()
         );
+        assert!(tokens[0].memory_footprint() > size_of::<Token>());
+    }
+
+    #[test]
+    fn bom_and_shebang() {
+        let source = "\u{feff}#!/usr/bin/env lexer -x\ndefine x";
+        assert_eq!(
+            types(source),
+            vec![
+                Type::Keyword(Keyword::Define),
+                Type::Identifier("x".to_string()),
+            ]
+        );
+        let tokens = lex_lossless(source).unwrap();
+        assert_eq!(
+            tokens[1],
+            Token::with_span(
+                Type::Shebang("#!/usr/bin/env lexer -x".to_string()),
+                Span::new(3, 26)
+            )
+        );
+        assert_eq!(detokenize(&tokens), source);
+        assert_eq!(
+            Lexer::builder().shebang(false).build().lex("#!x").unwrap()[0].token_type,
+            Type::Operator(Operator::Bang)
+        );
+        assert_eq!(types("x #!y").len(), 3);
+    }
+
+    #[test]
+    fn long_token_warnings() {
+        let lexer = Lexer::builder().long_token_warning(8).build();
+        let output = lexer.lex_with_diagnostics(
+            "short \"a long string\" /* long comment */ areallylongname 123456789\n\"runaway",
+        );
+        assert_eq!(
+            output.warnings,
+            vec![
+                LexWarning::LongToken(Span::new(6, 21)),
+                LexWarning::LongToken(Span::new(22, 40)),
+                LexWarning::LongToken(Span::new(41, 56)),
+            ]
+        );
+        assert_eq!(output.errors, vec![LexError::UnterminatedString(67)]);
+        assert!(
+            Lexer::default()
+                .lex_with_diagnostics("areallylongname")
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        let lexer = Lexer::builder().case_insensitive_keywords(true).build();
+        assert_eq!(
+            lexer
+                .lex("DEFINE Define define Defined")
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token_type)
+                .collect::<Vec<Type>>(),
+            vec![
+                Type::Keyword(Keyword::Define),
+                Type::Keyword(Keyword::Define),
+                Type::Keyword(Keyword::Define),
+                Type::Identifier("Defined".to_string()),
+            ]
+        );
+        assert_eq!(
+            types("DEFINE"),
+            vec![Type::Identifier("DEFINE".to_string())]
+        );
+    }
+
+    #[test]
+    fn whitespace_rules() {
+        let output = Lexer::builder()
+            .unusual_whitespace_warning(true)
+            .build()
+            .lex_with_diagnostics("a\u{a0}b\t\u{b}c");
+        assert_eq!(output.tokens.len(), 3);
+        assert_eq!(
+            output.warnings,
+            vec![
+                LexWarning::UnusualWhitespace(Span::new(1, 3)),
+                LexWarning::UnusualWhitespace(Span::new(5, 6)),
+            ]
+        );
+        let tokens = Lexer::builder()
+            .whitespace([' ', '\n'])
+            .trivia(true)
+            .build()
+            .lex("a \u{a0}\n")
+            .unwrap();
+        assert_eq!(
+            tokens
+                .into_iter()
+                .map(|t| t.token_type)
+                .collect::<Vec<Type>>(),
+            vec![
+                Type::Identifier("a".to_string()),
+                Type::Whitespace(" ".to_string()),
+                Type::None,
+                Type::Whitespace("\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_chars() {
+        let source = "a $ é`";
+        assert_eq!(try_lex(source).unwrap().len(), 2);
+        let tokens = Lexer::builder()
+            .unknown_chars(UnknownCharRule::Token)
+            .trivia(true)
+            .build()
+            .lex(source)
+            .unwrap();
+        assert_eq!(
+            tokens[2],
+            Token::with_span(Type::Unknown('$'), Span::new(2, 3))
+        );
+        assert_eq!(tokens[5].token_type, Type::Unknown('`'));
+        assert_eq!(tokens[2].category(), TokenCategory::Error);
+        assert_eq!(detokenize(&tokens), source);
+
+        let strict = Lexer::builder()
+            .unknown_chars(UnknownCharRule::Error)
+            .build();
+        let output = strict.lex_with_diagnostics(source);
+        assert_eq!(
+            output.errors,
+            vec![
+                LexError::UnknownCharacter('$', 2),
+                LexError::UnknownCharacter('`', 6),
+            ]
+        );
+        assert_eq!(output.errors[1].span(), Span::new(6, 7));
+        assert_eq!(output.tokens.len(), 2);
+        assert_eq!(strict.lex("a $").unwrap_err().code(), "L009");
+    }
+
+    #[test]
+    fn stream_order() {
+        let lexer = Lexer::builder()
+            .trivia(true)
+            .long_token_warning(4)
+            .unusual_whitespace_warning(true)
+            .build();
+        let source = "#!run\nx \u{a0}\"long string\n\"oops\" y /* open";
+        let events = lexer.stream(source).collect::<Vec<LexEvent>>();
+        assert!(events.windows(2).all(|w| w[0].offset() <= w[1].offset()));
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| match e {
+                    LexEvent::Token(t) => format!("{:?}", t.kind()),
+                    LexEvent::Error(e) => format!("{:?}", e),
+                    LexEvent::Warning(w) => format!("{:?}", w),
+                })
+                .collect::<Vec<String>>(),
+            vec![
+                "Shebang",
+                "Whitespace",
+                "Identifier",
+                "Whitespace",
+                "UnusualWhitespace(Span { start: 8, end: 10 })",
+                "UnterminatedString(10)",
+                "LongToken(Span { start: 10, end: 23 })",
+                "String",
+                "LongToken(Span { start: 23, end: 29 })",
+                "Whitespace",
+                "Identifier",
+                "Whitespace",
+                "UnterminatedComment(32)",
+                "LongToken(Span { start: 32, end: 39 })",
+            ]
+        );
+        let output = lexer.lex_with_diagnostics(source);
+        assert_eq!(
+            output.tokens,
+            events
+                .into_iter()
+                .filter_map(|e| match e {
+                    LexEvent::Token(t) => Some(t),
+                    _ => None,
+                })
+                .collect::<Vec<Token>>()
+        );
+    }
+
+    #[test]
+    fn stream_skips_layout() {
+        let lexer = Lexer::builder().layout(true).eof(true).build();
+        let source = "a\n  b\n";
+        let kinds = |tokens: Vec<Token>| tokens.iter().map(Token::kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds(lexer.lex_with_diagnostics(source).tokens),
+            vec![
+                TokenKind::Identifier,
+                TokenKind::Newline,
+                TokenKind::Indent,
+                TokenKind::Identifier,
+                TokenKind::Newline,
+                TokenKind::Dedent,
+                TokenKind::Eof,
+            ]
+        );
+        let streamed = lexer
+            .stream(source)
+            .filter_map(|event| match event {
+                LexEvent::Token(token) => Some(token),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            kinds(streamed),
+            vec![TokenKind::Identifier, TokenKind::Identifier, TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn combining_sequences() {
+        let lexer = Lexer::builder().combining_warning(true).build();
+        assert_eq!(
+            lexer
+                .lex_with_diagnostics("\"café\" \"cafe\u{301} ok\" \"🇳🇱\" x\u{301}")
+                .warnings,
+            vec![
+                LexWarning::CombiningSequence(Span::new(12, 15)),
+                LexWarning::CombiningSequence(Span::new(21, 29)),
+            ]
+        );
+        assert!(
+            Lexer::default()
+                .lex_with_diagnostics("\"e\u{301}\"")
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn observer() {
+        #[derive(Default)]
+        struct Log(Vec<String>);
+
+        impl LexObserver for Log {
+            fn on_token(&mut self, token: &Token) {
+                self.0.push(format!("token {}", token.span.end));
+            }
+
+            fn on_error(&mut self, error: &LexError) {
+                self.0.push(format!("{:?}", error));
+            }
+
+            fn on_eof(&mut self, len: usize) {
+                self.0.push(format!("eof {}", len));
+            }
+        }
+
+        let lexer = Lexer::builder().long_token_warning(2).build();
+        let source = "x + \"abc";
+        let mut log = Log::default();
+        let output = lexer.lex_observed(source, &mut log);
+        assert_eq!(output, lexer.lex_with_diagnostics(source));
+        assert_eq!(
+            log.0,
+            vec!["token 1", "token 3", "UnterminatedString(4)", "eof 8"]
+        );
+
+        let lexer = Lexer::builder().eof(true).build();
+        let mut log = Log::default();
+        let output = lexer.lex_observed("x y", &mut log);
+        assert_eq!(output, lexer.lex_with_diagnostics("x y"));
+        assert_eq!(log.0, vec!["token 1", "token 3", "token 3", "eof 3"]);
+    }
+
+    #[test]
+    fn equivalence() {
+        let a = try_lex("f(x, 1);").unwrap();
+        let b = lex_lossless("f ( x ,\n 1 ) /* c */ ;").unwrap();
+        let c = try_lex("f(x, 2);").unwrap();
+        assert!(tokens_equivalent(&a, &b));
+        assert!(tokens_equivalent(&[], &lex_lossless(" /* */ ").unwrap()));
+        assert!(!tokens_equivalent(&a, &c));
+        assert_eq!(
+            first_difference(&b, &c).map(|(i, x, y)| (i, x.map(|t| t.span), y.map(|t| t.span))),
+            Some((4, Some(Span::new(9, 10)), Some(Span::new(5, 6))))
+        );
+        assert_eq!(
+            first_difference(&c, &c[..2]).map(|(i, _, y)| (i, y)),
+            Some((2, None))
+        );
+        assert_tokens_equivalent(&a, &b);
+        let panic = std::panic::catch_unwind(|| assert_tokens_equivalent(&b, &c)).unwrap_err();
+        assert_eq!(
+            panic.downcast_ref::<String>().map(String::as_str),
+            Some(
+                "token streams differ at significant token 4: Number `1` at 9..10 != Number `2` at 5..6"
+            )
+        );
+    }
+
+    #[test]
+    fn radix_prefixes() {
+        let lexer = Lexer::builder()
+            .radix_prefix("$", 16)
+            .radix_prefix("%", 2)
+            .radix_prefix("0x", 16)
+            .digit_separator('_')
+            .build();
+        let lexed = |s: &str| {
+            lexer
+                .lex(s)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token_type)
+                .collect::<Vec<Type>>()
+        };
+        assert_eq!(
+            lexed("$FF %1010 0x1f 1_000 %x 7 % 2 $"),
+            vec![
+                Type::Number(255),
+                Type::Number(10),
+                Type::Number(31),
+                Type::Number(1000),
+                Type::Operator(Operator::Mod),
+                Type::Identifier("x".to_string()),
+                Type::Number(7),
+                Type::Operator(Operator::Mod),
+                Type::Number(2),
+            ]
+        );
+        assert_eq!(
+            lexer.lex("0x_1 %12").unwrap()[2..]
+                .iter()
+                .map(|t| (t.token_type.clone(), t.span))
+                .collect::<Vec<(Type, Span)>>(),
+            vec![
+                (Type::Number(1), Span::new(5, 7)),
+                (Type::Number(2), Span::new(7, 8)),
+            ]
+        );
+        assert_eq!(lexed("1_0"), vec![Type::Number(10)]);
+        assert_eq!(
+            types("1_0"),
+            vec![Type::Number(1), Type::Identifier("_0".to_string())]
+        );
+
+        assert_eq!(
+            lexed("2147483647 $7fffffff"),
+            vec![Type::Number(i32::MAX); 2]
+        );
+        let output = lexer.lex_with_diagnostics("x 2_147_483_648 $1_0000_0000 y");
+        assert_eq!(
+            output.errors,
+            vec![
+                LexError::NumberTooLarge(Span::new(2, 15)),
+                LexError::NumberTooLarge(Span::new(16, 28)),
+            ]
+        );
+        assert_eq!(
+            output.tokens.iter().map(|t| t.span).collect::<Vec<Span>>(),
+            vec![
+                Span::new(0, 1),
+                Span::new(2, 15),
+                Span::new(16, 28),
+                Span::new(29, 30),
+            ]
+        );
+        assert_eq!(output.tokens[1].token_type, Type::Number(i32::MIN));
+        assert_eq!(
+            output.errors[0].to_string(),
+            "number is too large at 2..15 [L010]"
+        );
+    }
+
+    #[test]
+    fn assembly() {
+        let lexer = Lexer::builder().labels(true).build();
+        assert_eq!(
+            lexer.lex("a: b: c::d\n  e:").unwrap(),
+            vec![
+                Token::with_span(Type::Label("a".to_string()), Span::new(0, 2)),
+                Token::with_span(Type::Identifier("b".to_string()), Span::new(3, 4)),
+                Token::with_span(Type::Colon, Span::new(4, 5)),
+                Token::with_span(Type::Identifier("c".to_string()), Span::new(6, 7)),
+                Token::with_span(Type::DoubleColon, Span::new(7, 9)),
+                Token::with_span(Type::Identifier("d".to_string()), Span::new(9, 10)),
+                Token::with_span(Type::Label("e".to_string()), Span::new(13, 15)),
+            ]
+        );
+        let tokens = LexerBuilder::assembly()
+            .build()
+            .lex("loop: .word 0x1F, r3\n  add r1, sp, 0b1_0 . x")
+            .unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "loop:", ".word", "31", ",", "r3", "add", "r1", ",", "sp", ",", "2", ".", "x"
+            ]
+        );
+        assert_eq!(tokens[4].kind(), TokenKind::CustomKeyword);
+        assert_eq!(tokens[5].kind(), TokenKind::Identifier);
+        assert_eq!(
+            tokens[1],
+            Token::with_span(Type::Directive("word".to_string()), Span::new(6, 11))
+        );
+    }
+
+    #[test]
+    fn error_limit() {
+        let source = "\"a\n\"b\nx \"c\n/* d";
+        let (tokens, errors) = lex_all_errors(source);
+        assert_eq!(
+            tokens,
+            vec![Token::with_span(
+                Type::Identifier("x".to_string()),
+                Span::new(6, 7)
+            )]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                LexError::UnterminatedString(0),
+                LexError::UnterminatedString(3),
+                LexError::UnterminatedString(8),
+                LexError::UnterminatedComment(11),
+            ]
+        );
+        let lexer = Lexer::builder().max_errors(2).build();
+        let (tokens, errors) = lexer.lex_all_errors(source);
+        assert!(tokens.is_empty());
+        assert_eq!(
+            errors,
+            vec![
+                LexError::UnterminatedString(0),
+                LexError::UnterminatedString(3)
+            ]
+        );
+        assert_eq!(
+            lexer
+                .stream(source)
+                .filter(|event| matches!(event, LexEvent::Error(_)))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let lexer = Lexer::builder()
+            .max_token_len(8)
+            .max_string_len(4)
+            .max_nesting(2)
+            .trivia(true)
+            .build();
+        let source = "abcdefghij \"abcde\" \"abcd\" /* /* /* */ */ */ x";
+        let output = lexer.lex_with_diagnostics(source);
+        assert_eq!(
+            output.errors,
+            vec![
+                LexError::LimitExceeded(Limit::TokenLength, 0),
+                LexError::LimitExceeded(Limit::StringLength, 11),
+                LexError::LimitExceeded(Limit::Nesting, 32),
+            ]
+        );
+        let types: Vec<&Type> = output
+            .tokens
+            .iter()
+            .filter(|t| !t.is_trivia())
+            .map(|t| &t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            [
+                &Type::String("abcd".to_string()),
+                &Type::Identifier("x".to_string())
+            ]
+        );
+        assert_eq!(
+            output.errors[0].to_string(),
+            "token is too long at 0..1 [L007]"
+        );
+
+        let interpolated = Lexer::builder()
+            .interpolation(true)
+            .max_nesting(1)
+            .max_string_len(6)
+            .build();
+        assert_eq!(
+            interpolated.lex("\"${\"x\"}\"").unwrap_err(),
+            LexError::LimitExceeded(Limit::Nesting, 3)
+        );
+        assert_eq!(
+            interpolated.lex("\"${x} abc\" y\nz").unwrap_err(),
+            LexError::LimitExceeded(Limit::StringLength, 0)
+        );
+        assert!(interpolated.lex("\"${x} a\"").is_ok());
+
+        let lexer = Lexer::builder().max_tokens(3).eof(true).build();
+        let output = lexer.lex_with_diagnostics("a b c d e");
+        assert_eq!(output.tokens.len(), 4);
+        assert_eq!(
+            output.errors,
+            vec![LexError::LimitExceeded(Limit::Tokens, 6)]
+        );
+        let events: Vec<LexEvent> = lexer.stream("a b c d e").collect();
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                LexEvent::Error(LexError::LimitExceeded(Limit::Tokens, 6)),
+                LexEvent::Token(Token::with_span(Type::Eof, Span::new(9, 9))),
+            ]
+        );
+        assert_eq!(
+            lexer.lex_compact("a b c d e"),
+            Err(LexError::LimitExceeded(Limit::Tokens, 6))
+        );
+        assert!(lexer.lex("a b c").is_ok());
+    }
+
+    #[test]
+    fn keyword_call() {
+        let lexer = Lexer::builder()
+            .keyword_call_warning(Keyword::If)
+            .keyword_call_warning(Keyword::Define)
+            .build();
+        let output = lexer.lex_with_diagnostics("if(x); define(y); define (z); else(w); iff(v)");
+        assert_eq!(
+            output.warnings,
+            vec![
+                LexWarning::KeywordCall(Keyword::If, Span::new(0, 2)),
+                LexWarning::KeywordCall(Keyword::Define, Span::new(7, 13)),
+            ]
+        );
+        assert_eq!(output.tokens.len(), 24);
+        assert!(
+            Lexer::default()
+                .lex_with_diagnostics("if(x)")
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        let lexer = Lexer::builder().interpolation(true).build();
+        let tokens = lexer.lex(r#""a ${x + {b: "${y}"}.b} c" "d""#).unwrap();
+        let types: Vec<Type> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                Type::StringStart,
+                Type::StringPart("a ".to_string()),
+                Type::InterpolationStart,
+                Type::Identifier("x".to_string()),
+                Type::Operator(Operator::Plus),
+                Type::LeftBrace,
+                Type::Identifier("b".to_string()),
+                Type::Colon,
+                Type::StringStart,
+                Type::StringPart(String::new()),
+                Type::InterpolationStart,
+                Type::Identifier("y".to_string()),
+                Type::InterpolationEnd,
+                Type::StringPart(String::new()),
+                Type::StringEnd,
+                Type::RightBrace,
+                Type::Dot,
+                Type::Identifier("b".to_string()),
+                Type::InterpolationEnd,
+                Type::StringPart(" c".to_string()),
+                Type::StringEnd,
+                Type::String("d".to_string()),
+            ]
+        );
+        assert_eq!(tokens[0].span, Span::new(0, 1));
+        assert_eq!(tokens[1].span, Span::new(1, 3));
+        assert_eq!(tokens[2].span, Span::new(3, 5));
+        assert_eq!(tokens[19].span, Span::new(23, 25));
+        assert_eq!(tokens[20].span, Span::new(25, 26));
+        let lossless = Lexer::builder().interpolation(true).trivia(true).build();
+        for source in ["x = \"a ${y} b\";", r#""a ${x + {b: "${y}"}.b} c" "d""#] {
+            let tokens = lossless.lex(source).unwrap();
+            assert_eq!(detokenize(&tokens), source);
+            assert!(
+                tokens
+                    .iter()
+                    .all(|t| t.to_string() == source[t.span.start..t.span.end])
+            );
+        }
+        assert_eq!(
+            lexer.lex("x \"${y}\n\"").unwrap_err(),
+            LexError::UnterminatedString(2)
+        );
+        assert_eq!(
+            Lexer::default().lex("\"${x}\"").unwrap(),
+            vec![Token::with_span(
+                Type::String("${x}".to_string()),
+                Span::new(0, 6)
+            )]
+        );
+
+        // Nesting is bounded rather than recursing as deep as the input goes.
+        let lexer = Lexer::builder().interpolation(true).build();
+        let deep = "\"${".repeat(10_000);
+        let errors = lexer.lex_all_errors(&deep).1;
+        assert!(errors.contains(&LexError::NestingTooDeep(3 * crate::MAX_NESTING)));
+        let nested = format!("{}x{}", "\"${".repeat(8), "}\"".repeat(8));
+        assert!(lexer.lex(&nested).is_ok());
+    }
+
+    #[test]
+    fn attributes() {
+        let lexer = Lexer::builder().attributes(true).decorators(true).build();
+        let source = "#[inline, doc(\"a]\")] @memo(1) define f = [1][0];\n#[x";
+        let output = lexer.lex_with_diagnostics(source);
+        let types: Vec<Type> = output.tokens.iter().map(|t| t.token_type.clone()).collect();
+        let id = |s: &str| Type::Identifier(s.to_string());
         assert_eq!(
-            lex("if true".to_string()),
+            types,
             vec![
-                Token::new(Type::Keyword(Keyword::If)),
-                Token::new(Type::Keyword(Keyword::True)),
+                Type::AttributeStart,
+                id("inline"),
+                Type::Comma,
+                id("doc"),
+                Type::LeftParen,
+                Type::String("a]".to_string()),
+                Type::RightParen,
+                Type::AttributeEnd,
+                Type::Decorator("memo".to_string()),
+                Type::LeftParen,
+                Type::Number(1),
+                Type::RightParen,
+                Type::Keyword(Keyword::Define),
+                id("f"),
+                Type::Operator(Operator::Equals),
+                Type::LeftBracket,
+                Type::Number(1),
+                Type::RightBracket,
+                Type::LeftBracket,
+                Type::Number(0),
+                Type::RightBracket,
+                Type::Semicolon,
             ]
         );
+        assert_eq!(output.tokens[0].span, Span::new(0, 2));
+        assert_eq!(output.tokens[7].span, Span::new(19, 20));
+        assert_eq!(output.tokens[8].span, Span::new(21, 26));
+        assert_eq!(output.errors, vec![LexError::UnterminatedAttribute(49)]);
+        assert_eq!(output.tokens[8].category(), TokenCategory::Keyword);
+
+        let nested = lexer.lex("#[cfg(a[0], #[b])]\nx").unwrap();
+        assert_eq!(nested[6].token_type, Type::RightBracket);
+        assert_eq!(nested[8].token_type, Type::AttributeStart);
+        assert_eq!(nested[10].token_type, Type::AttributeEnd);
+        assert_eq!(nested[12].token_type, Type::AttributeEnd);
+        assert_eq!(detokenize(&nested), "#[ cfg ( a [ 0 ] , #[ b ] ) ] x");
+        let deep = "#[".repeat(10_000);
+        let errors = lexer.lex_all_errors(&deep).1;
+        assert!(errors.contains(&LexError::NestingTooDeep(2 * crate::MAX_NESTING)));
+
+        // Without the options, `#` and `@` match no rule.
+        assert_eq!(
+            Lexer::default().lex("#[a] @b").unwrap()[0].token_type,
+            Type::LeftBracket
+        );
+        assert_eq!(Lexer::default().lex("@b").unwrap()[0].token_type, id("b"));
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let source = "x = \"\"\"\n    a \"b\"\n\n      c\n    \"\"\"; \"d\"";
+        let lexer = Lexer::builder().multiline_strings(true).build();
+        let tokens = lexer.lex(source).unwrap();
+        assert_eq!(
+            tokens[2].token_type,
+            Type::String("\n    a \"b\"\n\n      c\n    ".to_string())
+        );
+        assert_eq!(tokens[2].span, Span::new(4, 34));
+        assert_eq!(tokens[4].token_type, Type::String("d".to_string()));
+        let dedented = Lexer::builder()
+            .multiline_strings(true)
+            .dedent_strings(true)
+            .build()
+            .lex(source)
+            .unwrap();
+        assert_eq!(
+            dedented[2].token_type,
+            Type::String("a \"b\"\n\n  c\n".to_string())
+        );
+        assert_eq!(
+            lexer.lex(&detokenize(&tokens)).unwrap()[2].token_type,
+            tokens[2].token_type
+        );
+        assert_eq!(
+            lexer.lex("y \"\"\"a\n\"\"").unwrap_err(),
+            LexError::UnterminatedString(2)
+        );
+        assert_eq!(
+            Lexer::default().lex("\"a\nb\"").unwrap_err(),
+            LexError::UnterminatedString(0)
+        );
+    }
+
+    #[test]
+    fn eof_and_predicates() {
+        let lexer = Lexer::builder().eof(true).trivia(true).build();
+        let tokens = lexer.lex("if x + 1 ").unwrap();
+        let eof = tokens.last().unwrap();
+        assert!(eof.is_eof());
+        assert_eq!(eof.span, Span::new(9, 9));
+        assert!(tokens[0].is_keyword(Keyword::If));
+        assert!(!tokens[0].is_keyword(Keyword::Else));
+        assert!(tokens[2].is_identifier());
+        assert!(tokens[4].is_operator(Operator::Plus));
+        assert!(!tokens[4].is_identifier());
+        assert_eq!(
+            lexer.lex("").unwrap(),
+            vec![Token::with_span(Type::Eof, Span::new(0, 0))]
+        );
+        assert!(!Lexer::default().lex("x").unwrap().iter().any(Token::is_eof));
+        let mut stream = crate::TokenStream::new(lexer.lex("x").unwrap());
+        stream.next();
+        assert!(stream.is_at_end());
+    }
+
+    #[test]
+    fn lex_into() {
+        let lexer = Lexer::builder().eof(true).build();
+        let mut tokens = Vec::new();
+        lexer.lex_into("define x = f(y);", &mut tokens).unwrap();
+        assert_eq!(tokens, lexer.lex("define x = f(y);").unwrap());
+        let capacity = tokens.capacity();
+        let buffer = tokens.as_ptr();
+        lexer.lex_into("a b", &mut tokens).unwrap();
+        assert_eq!(tokens, lexer.lex("a b").unwrap());
+        assert_eq!((tokens.capacity(), tokens.as_ptr()), (capacity, buffer));
+        assert_eq!(
+            lexer.lex_into("a \"b", &mut tokens),
+            Err(LexError::UnterminatedString(2))
+        );
+        assert_eq!(tokens[0].token_type, Type::Identifier("a".to_string()));
+    }
+
+    #[test]
+    fn keyword_table() {
+        for (word, keyword) in crate::KEYWORDS {
+            assert_eq!(word.parse(), Ok(keyword.clone()));
+            assert_eq!(
+                Keyword::try_from(keyword.to_string().as_str()),
+                Ok(keyword.clone()),
+                "{} spells {:?}",
+                keyword,
+                keyword
+            );
+        }
+        assert_eq!("defined".parse::<Keyword>(), Err(UnknownKeyword));
+        assert_eq!(Keyword::try_from("Define"), Err(UnknownKeyword));
+        assert_eq!(Keyword::Function.to_string(), "fn");
+    }
+
+    #[test]
+    fn doc_comments() {
+        let source =
+            "/// Adds one.\n///\ndefine f /** Takes\n *  x /* y */\n */ x; //// a /*** b */ /**/";
+        let lexer = Lexer::builder().doc_comments(true).build();
+        let tokens = lexer.lex(source).unwrap();
+        assert_eq!(
+            tokens[0],
+            Token::with_span(Type::DocComment("Adds one.".to_string()), Span::new(0, 13))
+        );
+        assert_eq!(tokens[1].token_type, Type::DocComment(String::new()));
+        assert_eq!(
+            tokens[4].token_type,
+            Type::DocComment(" Takes\n x /* y */\n".to_string())
+        );
+        assert!(tokens[4].is_trivia());
+        assert_eq!(
+            tokens.len(),
+            7,
+            "`////` is a plain comment, and `/***` and `/**/` are not doc comments"
+        );
+        let commented = Lexer::builder()
+            .doc_comments(true)
+            .line_comments(true)
+            .trivia(true)
+            .build();
+        assert_eq!(
+            commented
+                .lex(
+                    "//// a
+/// b"
+                )
+                .unwrap()[0],
+            Token::with_span(Type::Comment("//// a".to_string()), Span::new(0, 6))
+        );
+        assert_eq!(
+            commented
+                .lex(
+                    "//// a
+/// b"
+                )
+                .unwrap()[2]
+                .token_type,
+            Type::DocComment("b".to_string())
+        );
+        let tokens = lexer.lex("/*** a */ /**/ b").unwrap();
+        assert_eq!(
+            tokens,
+            vec![Token::with_span(
+                Type::Identifier("b".to_string()),
+                Span::new(15, 16)
+            )]
+        );
+        assert_eq!(
+            Lexer::default().lex("/// x").unwrap().len(),
+            4,
+            "`///` stays three operators without doc comments"
+        );
+        assert_eq!(
+            lexer.lex("x /** y").unwrap_err(),
+            LexError::UnterminatedComment(2)
+        );
+    }
+
+    #[test]
+    fn line_comments() {
+        let source = "x // a /* b\ny //! c\n/// d";
+        let lexer = Lexer::builder().line_comments(true).trivia(true).build();
+        let comments: Vec<Token> = lexer
+            .lex(source)
+            .unwrap()
+            .into_iter()
+            .filter(|t| matches!(t.token_type, Type::Comment(_)))
+            .collect();
         assert_eq!(
-            lex("if 4 == 4".to_string()),
+            comments,
             vec![
-                Token::new(Type::Keyword(Keyword::If)),
-                Token::new(Type::Number(4)),
-                Token::new(Type::Operator(Operator::DoubleEquals)),
-                Token::new(Type::Number(4))
+                Token::with_span(Type::Comment("// a /* b".to_string()), Span::new(2, 11)),
+                Token::with_span(Type::Comment("//! c".to_string()), Span::new(14, 19)),
+                Token::with_span(Type::Comment("/// d".to_string()), Span::new(20, 25)),
             ]
         );
+        let lexer = Lexer::builder()
+            .line_comments(true)
+            .doc_comments(true)
+            .build();
         assert_eq!(
-            lex("if 4 == 5".to_string()),
+            lexer.lex(source).unwrap(),
             vec![
-                Token::new(Type::Keyword(Keyword::If)),
-                Token::new(Type::Number(4)),
-                Token::new(Type::Operator(Operator::DoubleEquals)),
-                Token::new(Type::Number(5))
+                Token::with_span(Type::Identifier("x".to_string()), Span::new(0, 1)),
+                Token::with_span(Type::Identifier("y".to_string()), Span::new(12, 13)),
+                Token::with_span(Type::DocComment("d".to_string()), Span::new(20, 25)),
             ]
         );
+        assert_eq!(Lexer::default().lex("x // y").unwrap().len(), 4);
     }
 }
@@ -0,0 +1,165 @@
+use alloc::{vec, vec::Vec};
+
+use crate::Span;
+
+/// A zero-based line and column. Columns count bytes unless a method says
+/// otherwise.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl LineCol {
+    pub fn new(line: usize, column: usize) -> Self {
+        LineCol { line, column }
+    }
+}
+
+/// Precomputed line starts for a piece of text, turning byte offsets into
+/// lines and columns (and back) with a binary search.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    /// The non-ASCII characters on each line as `(byte column, UTF-8 length)`,
+    /// which is all that's needed to translate columns to UTF-16.
+    wide_chars: Vec<Vec<(usize, usize)>>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut wide_chars = vec![Vec::new()];
+        for (offset, c) in text.char_indices() {
+            if c == '\n' {
+                line_starts.push(offset + 1);
+                wide_chars.push(Vec::new());
+            } else if !c.is_ascii() {
+                let line_start = line_starts[line_starts.len() - 1];
+                if let Some(line) = wide_chars.last_mut() {
+                    line.push((offset - line_start, c.len_utf8()));
+                }
+            }
+        }
+        LineIndex {
+            line_starts,
+            wide_chars,
+            len: text.len(),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The byte range of `line`, including its trailing `\n` if any.
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        let start = *self.line_starts.get(line)?;
+        let end = self.line_starts.get(line + 1).copied().unwrap_or(self.len);
+        Some(Span::new(start, end))
+    }
+
+    /// The line and byte column of `offset`. Offsets past the end of the text
+    /// are clamped to it.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.len);
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        LineCol::new(line, offset - self.line_starts[line])
+    }
+
+    /// The byte offset of a line and byte column, if it lies within the text.
+    pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+        let span = self.line_span(line_col.line)?;
+        let offset = span.start + line_col.column;
+        match offset <= span.end {
+            true => Some(offset),
+            false => None,
+        }
+    }
+
+    /// Converts a byte column into a UTF-16 code unit column, as used by the
+    /// Language Server Protocol.
+    pub fn to_utf16(&self, line_col: LineCol) -> LineCol {
+        let mut column = line_col.column;
+        for &(start, len) in self.wide_chars.get(line_col.line).into_iter().flatten() {
+            if start >= line_col.column {
+                break;
+            }
+            column -= len - len / 4 - 1;
+        }
+        LineCol::new(line_col.line, column)
+    }
+
+    /// Converts a UTF-16 code unit column back into a byte column.
+    pub fn from_utf16(&self, line_col: LineCol) -> LineCol {
+        let mut column = line_col.column;
+        for &(start, len) in self.wide_chars.get(line_col.line).into_iter().flatten() {
+            if start >= column {
+                break;
+            }
+            column += len - len / 4 - 1;
+        }
+        LineCol::new(line_col.line, column)
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl LineIndex {
+    /// Converts a byte offset into an LSP position.
+    pub fn lsp_position(&self, offset: usize) -> lsp_types::Position {
+        let line_col = self.to_utf16(self.line_col(offset));
+        lsp_types::Position::new(line_col.line as u32, line_col.column as u32)
+    }
+
+    /// Converts an LSP position into a byte offset, if it lies within the
+    /// text.
+    pub fn lsp_offset(&self, position: lsp_types::Position) -> Option<usize> {
+        self.offset(self.from_utf16(LineCol::new(
+            position.line as usize,
+            position.character as usize,
+        )))
+    }
+
+    pub fn lsp_range(&self, span: Span) -> lsp_types::Range {
+        lsp_types::Range::new(self.lsp_position(span.start), self.lsp_position(span.end))
+    }
+
+    pub fn span(&self, range: lsp_types::Range) -> Option<Span> {
+        Some(Span::new(
+            self.lsp_offset(range.start)?,
+            self.lsp_offset(range.end)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LineCol, LineIndex, Span};
+
+    #[test]
+    fn line_index() {
+        let index = LineIndex::new("ab\n\nc😀d\n");
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_col(0), LineCol::new(0, 0));
+        assert_eq!(index.line_col(3), LineCol::new(1, 0));
+        assert_eq!(index.line_col(9), LineCol::new(2, 5));
+        assert_eq!(index.line_col(100), LineCol::new(3, 0));
+        assert_eq!(index.offset(LineCol::new(2, 5)), Some(9));
+        assert_eq!(index.offset(LineCol::new(1, 2)), None);
+        assert_eq!(index.line_span(2), Some(Span::new(4, 11)));
+        assert_eq!(index.to_utf16(LineCol::new(2, 5)), LineCol::new(2, 3));
+        assert_eq!(index.from_utf16(LineCol::new(2, 3)), LineCol::new(2, 5));
+        assert_eq!(index.to_utf16(LineCol::new(0, 1)), LineCol::new(0, 1));
+    }
+
+    #[cfg(feature = "lsp")]
+    #[test]
+    fn lsp_ranges() {
+        let index = LineIndex::new("é = 1\nx😀 = 2");
+        let range = index.lsp_range(Span::new(8, 12));
+        assert_eq!(range.start, lsp_types::Position::new(1, 1));
+        assert_eq!(range.end, lsp_types::Position::new(1, 3));
+        assert_eq!(index.span(range), Some(Span::new(8, 12)));
+    }
+}
@@ -0,0 +1,265 @@
+//! Style checks over lexed tokens.
+//!
+//! A [`Lint`] looks at the tokens of one input, and the source they were
+//! lexed from, and reports [`LintWarning`]s. A [`Linter`] runs several and
+//! merges what they report in source order; [`Linter::builtin`] holds the
+//! lints of this module, and downstream tools add their own with
+//! [`Linter::push`]. Closures taking the tokens, the source and the list to
+//! report to are lints too.
+//!
+//! Lints see whatever tokens they are given, so ones that look at
+//! whitespace or comments need tokens lexed with trivia.
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::fmt;
+
+use crate::{KEYWORDS, Span, Token, Type};
+
+/// A style problem found by a [`Lint`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LintWarning {
+    /// The name of the lint reporting it, such as `leading-zeros`.
+    pub lint: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+/// Writes the message, the span as byte offsets and the lint's name, as in
+/// `integer literal with leading zeros at 4..7 [leading-zeros]`.
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{} [{}]",
+            self.message, self.span.start, self.span.end, self.lint
+        )
+    }
+}
+
+/// One check run by a [`Linter`].
+pub trait Lint: Send + Sync {
+    /// Adds a warning to `out` for each problem in `tokens`, which were
+    /// lexed from `source`.
+    fn check(&self, tokens: &[Token], source: &str, out: &mut Vec<LintWarning>);
+}
+
+impl<F> Lint for F
+where
+    F: Fn(&[Token], &str, &mut Vec<LintWarning>) + Send + Sync,
+{
+    fn check(&self, tokens: &[Token], source: &str, out: &mut Vec<LintWarning>) {
+        self(tokens, source, out)
+    }
+}
+
+/// Lints run together over the same tokens.
+#[derive(Clone, Default)]
+pub struct Linter {
+    lints: Vec<Arc<dyn Lint>>,
+}
+
+impl fmt::Debug for Linter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Linter")
+            .field("lints", &self.lints.len())
+            .finish()
+    }
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Linter::default()
+    }
+
+    /// A linter running every lint in this module.
+    pub fn builtin() -> Self {
+        let mut linter = Linter::new();
+        linter.push(LeadingZeros);
+        linter.push(KeywordCasing);
+        linter.push(MixedIndentation);
+        linter.push(AdjacentStrings);
+        linter
+    }
+
+    pub fn push(&mut self, lint: impl Lint + 'static) {
+        self.lints.push(Arc::new(lint));
+    }
+
+    pub fn len(&self) -> usize {
+        self.lints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lints.is_empty()
+    }
+
+    /// Every warning the lints report for `tokens`, lexed from `source`,
+    /// ordered by where they start. Warnings starting at the same place keep
+    /// the order of their lints.
+    pub fn run(&self, tokens: &[Token], source: &str) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+        for lint in &self.lints {
+            lint.check(tokens, source, &mut warnings);
+        }
+        warnings.sort_by_key(|warning| warning.span.start);
+        warnings
+    }
+}
+
+/// Integer literals written with leading zeros, such as `007`, which some
+/// languages read as octal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeadingZeros;
+
+impl Lint for LeadingZeros {
+    fn check(&self, tokens: &[Token], source: &str, out: &mut Vec<LintWarning>) {
+        for token in tokens {
+            let Type::Number(_) = token.token_type else {
+                continue;
+            };
+            let text = source.get(token.span.start..token.span.end).unwrap_or("");
+            let mut digits = text.bytes();
+            if digits.next() == Some(b'0') && digits.next().is_some_and(|b| b.is_ascii_digit()) {
+                out.push(LintWarning {
+                    lint: "leading-zeros",
+                    message: String::from("integer literal with leading zeros"),
+                    span: token.span,
+                });
+            }
+        }
+    }
+}
+
+/// Identifiers that spell a built-in keyword in another casing, such as
+/// `Define` or `IF`. A keyword the lexer leaves as an identifier, spelled
+/// the same, is not flagged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeywordCasing;
+
+impl Lint for KeywordCasing {
+    fn check(&self, tokens: &[Token], _source: &str, out: &mut Vec<LintWarning>) {
+        for token in tokens {
+            let Type::Identifier(name) = &token.token_type else {
+                continue;
+            };
+            if let Some((word, _)) = KEYWORDS
+                .iter()
+                .find(|(word, _)| word.eq_ignore_ascii_case(name) && *word != name)
+            {
+                out.push(LintWarning {
+                    lint: "keyword-casing",
+                    message: format!(
+                        "`{}` differs from the keyword `{}` only in case",
+                        name, word
+                    ),
+                    span: token.span,
+                });
+            }
+        }
+    }
+}
+
+/// Lines indented with both tabs and spaces. Reads the source rather than
+/// the tokens, so it needs no trivia.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MixedIndentation;
+
+impl Lint for MixedIndentation {
+    fn check(&self, _tokens: &[Token], source: &str, out: &mut Vec<LintWarning>) {
+        let mut start = 0;
+        for line in source.split_inclusive('\n') {
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            let indent_text = &line[..indent];
+            if indent_text.contains(' ') && indent_text.contains('\t') {
+                out.push(LintWarning {
+                    lint: "mixed-indentation",
+                    message: String::from("indentation mixes tabs and spaces"),
+                    span: Span::new(start, start + indent),
+                });
+            }
+            start += line.len();
+        }
+    }
+}
+
+/// String literals with nothing but trivia between them, as in
+/// `f("a" "b")`, which usually means a missing comma or operator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdjacentStrings;
+
+impl Lint for AdjacentStrings {
+    fn check(&self, tokens: &[Token], _source: &str, out: &mut Vec<LintWarning>) {
+        let mut previous: Option<&Token> = None;
+        for token in tokens.iter().filter(|token| !token.is_trivia()) {
+            if let (Some(last), Type::String(_)) = (previous, &token.token_type)
+                && matches!(last.token_type, Type::String(_))
+            {
+                out.push(LintWarning {
+                    lint: "adjacent-strings",
+                    message: String::from("adjacent string literals"),
+                    span: Span::new(last.span.start, token.span.end),
+                });
+            }
+            previous = Some(token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer, Span, Token, Type,
+        lint::{LintWarning, Linter},
+    };
+
+    #[test]
+    fn lints() {
+        let source = "define x = 007 + 0 + 10;\n\t If(x) {\n  \t f(\"a\" /* */ \"b\", \"c\");\n}";
+        let tokens = Lexer::builder().trivia(true).build().lex(source).unwrap();
+        let warnings = Linter::builtin().run(&tokens, source);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.lint, w.span))
+                .collect::<Vec<_>>(),
+            vec![
+                ("leading-zeros", Span::new(11, 14)),
+                ("mixed-indentation", Span::new(25, 27)),
+                ("keyword-casing", Span::new(27, 29)),
+                ("mixed-indentation", Span::new(35, 39)),
+                ("adjacent-strings", Span::new(41, 54)),
+            ]
+        );
+        assert_eq!(
+            warnings[2].to_string(),
+            "`If` differs from the keyword `if` only in case at 27..29 [keyword-casing]"
+        );
+
+        let mut linter = Linter::new();
+        linter.push(|tokens: &[Token], _: &str, out: &mut Vec<LintWarning>| {
+            for token in tokens {
+                if token.token_type == Type::Identifier("x".to_string()) {
+                    out.push(LintWarning {
+                        lint: "short-name",
+                        message: "one-letter name".to_string(),
+                        span: token.span,
+                    });
+                }
+            }
+        });
+        assert_eq!(linter.run(&tokens, source).len(), 2);
+        assert!(Linter::new().run(&tokens, source).is_empty());
+
+        let lexer = Lexer::builder().remove_keyword("if").build();
+        let tokens = lexer.lex("if IF").unwrap();
+        assert_eq!(tokens[0].token_type, Type::Identifier("if".to_string()));
+        assert_eq!(
+            Linter::builtin()
+                .run(&tokens, "if IF")
+                .iter()
+                .map(|w| (w.lint, w.span))
+                .collect::<Vec<_>>(),
+            vec![("keyword-casing", Span::new(3, 5))]
+        );
+    }
+}
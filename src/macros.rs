@@ -0,0 +1,188 @@
+//! Shorthand for writing tokens by hand, in tests and in code generating
+//! synthetic streams.
+
+use alloc::vec::Vec;
+
+use crate::Token;
+
+/// A [`Token`] with an empty span, written as it would appear
+/// in source: `tok!(+)`, `tok!(define)`, `tok!(;)`.
+///
+/// Tokens with a payload name their type first: `tok!(ident "x")`,
+/// `tok!(str "text")`, `tok!(num 1)`, `tok!(part "text")`,
+/// `tok!(label "l")`, `tok!(directive "d")`, `tok!(decorator "d")`, and
+/// `tok!(kw "unless")` or `tok!(op "<=>")` for custom keywords and
+/// operators. Brackets and the comma are quoted, as in `tok!("(")`, and
+/// tokens without a spelling of their own are named: `tok!(newline)`,
+/// `tok!(indent)`, `tok!(dedent)` and `tok!(eof)`, as are the quotes of an
+/// interpolated string, `tok!(string_start)` and `tok!(string_end)`.
+#[macro_export]
+macro_rules! tok {
+    (ident $name:expr) => {
+        $crate::tok!(@ Identifier(::core::convert::Into::into($name)))
+    };
+    (str $text:expr) => {
+        $crate::tok!(@ String(::core::convert::Into::into($text)))
+    };
+    (num $n:expr) => {
+        $crate::tok!(@ Number($n))
+    };
+    (part $text:expr) => {
+        $crate::tok!(@ StringPart(::core::convert::Into::into($text)))
+    };
+    (label $name:expr) => {
+        $crate::tok!(@ Label(::core::convert::Into::into($name)))
+    };
+    (directive $name:expr) => {
+        $crate::tok!(@ Directive(::core::convert::Into::into($name)))
+    };
+    (decorator $name:expr) => {
+        $crate::tok!(@ Decorator(::core::convert::Into::into($name)))
+    };
+    (kw $word:expr) => {
+        $crate::tok!(@ Keyword($crate::Keyword::Custom(::core::convert::Into::into($word))))
+    };
+    (op $op:expr) => {
+        $crate::tok!(@ Operator($crate::Operator::Custom(::core::convert::Into::into($op))))
+    };
+    (define) => { $crate::tok!(@kw Define) };
+    (true) => { $crate::tok!(@kw True) };
+    (false) => { $crate::tok!(@kw False) };
+    (if) => { $crate::tok!(@kw If) };
+    (null) => { $crate::tok!(@kw Null) };
+    (else) => { $crate::tok!(@kw Else) };
+    (while) => { $crate::tok!(@kw While) };
+    (for) => { $crate::tok!(@kw For) };
+    (return) => { $crate::tok!(@kw Return) };
+    (fn) => { $crate::tok!(@kw Function) };
+    (function) => { $crate::tok!(@kw Function) };
+    (let) => { $crate::tok!(@kw Let) };
+    (break) => { $crate::tok!(@kw Break) };
+    (continue) => { $crate::tok!(@kw Continue) };
+    (+) => { $crate::tok!(@op Plus) };
+    (-) => { $crate::tok!(@op Minus) };
+    (*) => { $crate::tok!(@op Star) };
+    (/) => { $crate::tok!(@op Slash) };
+    (=) => { $crate::tok!(@op Equals) };
+    (==) => { $crate::tok!(@op DoubleEquals) };
+    (!=) => { $crate::tok!(@op NotEquals) };
+    (!) => { $crate::tok!(@op Bang) };
+    (%) => { $crate::tok!(@op Mod) };
+    (>) => { $crate::tok!(@op Greater) };
+    (<) => { $crate::tok!(@op Less) };
+    (>=) => { $crate::tok!(@op GreaterEqual) };
+    (<=) => { $crate::tok!(@op LessEqual) };
+    (&&) => { $crate::tok!(@op And) };
+    (||) => { $crate::tok!(@op Or) };
+    (&) => { $crate::tok!(@op BitAnd) };
+    (|) => { $crate::tok!(@op BitOr) };
+    (^) => { $crate::tok!(@op BitXor) };
+    (~) => { $crate::tok!(@op BitNot) };
+    (<<) => { $crate::tok!(@op Shl) };
+    (>>) => { $crate::tok!(@op Shr) };
+    ("(") => { $crate::tok!(@ LeftParen) };
+    (")") => { $crate::tok!(@ RightParen) };
+    ("{") => { $crate::tok!(@ LeftBrace) };
+    ("}") => { $crate::tok!(@ RightBrace) };
+    ("[") => { $crate::tok!(@ LeftBracket) };
+    ("]") => { $crate::tok!(@ RightBracket) };
+    (",") => { $crate::tok!(@ Comma) };
+    ("${") => { $crate::tok!(@ InterpolationStart) };
+    (string_start) => { $crate::tok!(@ StringStart) };
+    (string_end) => { $crate::tok!(@ StringEnd) };
+    ("#[") => { $crate::tok!(@ AttributeStart) };
+    (.) => { $crate::tok!(@ Dot) };
+    (;) => { $crate::tok!(@ Semicolon) };
+    (:) => { $crate::tok!(@ Colon) };
+    (::) => { $crate::tok!(@ DoubleColon) };
+    (?) => { $crate::tok!(@ Question) };
+    (?.) => { $crate::tok!(@ QuestionDot) };
+    (??) => { $crate::tok!(@op Coalesce) };
+    (->) => { $crate::tok!(@ Arrow) };
+    (=>) => { $crate::tok!(@ FatArrow) };
+    (newline) => { $crate::tok!(@ Newline) };
+    (indent) => { $crate::tok!(@ Indent) };
+    (dedent) => { $crate::tok!(@ Dedent) };
+    (eof) => { $crate::tok!(@ Eof) };
+    (@kw $keyword:ident) => {
+        $crate::tok!(@ Keyword($crate::Keyword::$keyword))
+    };
+    (@op $op:ident) => {
+        $crate::tok!(@ Operator($crate::Operator::$op))
+    };
+    (@ $($variant:tt)+) => {
+        $crate::Token::new($crate::Type::$($variant)+)
+    };
+}
+
+/// A `Vec` of tokens, each written as for [`tok!`] and separated by commas:
+/// `tokens![define, ident "x", =, num 1, ;]`. Payloads in a list must be
+/// literals.
+#[macro_export]
+macro_rules! tokens {
+    (@ [$($done:expr,)*]) => {
+        $crate::__token_vec([$($done),*])
+    };
+    (@ [$($done:expr,)*] $kind:ident $value:literal $(, $($rest:tt)*)?) => {
+        $crate::tokens!(@ [$($done,)* $crate::tok!($kind $value),] $($($rest)*)?)
+    };
+    (@ [$($done:expr,)*] $token:tt $(, $($rest:tt)*)?) => {
+        $crate::tokens!(@ [$($done,)* $crate::tok!($token),] $($($rest)*)?)
+    };
+    (@ [$($done:expr,)*] $first:tt $second:tt $(, $($rest:tt)*)?) => {
+        $crate::tokens!(@ [$($done,)* $crate::tok!($first $second),] $($($rest)*)?)
+    };
+    ($($input:tt)*) => {
+        $crate::tokens!(@ [] $($input)*)
+    };
+}
+
+/// Collects the tokens of [`tokens!`], which cannot name `Vec` in crates
+/// without `alloc` in scope.
+#[doc(hidden)]
+pub fn __token_vec<const N: usize>(tokens: [Token; N]) -> Vec<Token> {
+    Vec::from(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Keyword, Lexer, Operator, Token, Type};
+
+    #[test]
+    fn token_macros() {
+        assert_eq!(tok!(+), Token::new(Type::Operator(Operator::Plus)));
+        assert_eq!(tok!(define), Token::new(Type::Keyword(Keyword::Define)));
+        assert_eq!(
+            tok!(ident "x"),
+            Token::new(Type::Identifier("x".to_string()))
+        );
+        assert_eq!(
+            tok!(op "<=>"),
+            Token::new(Type::Operator(Operator::Custom("<=>".to_string())))
+        );
+        let n = 3;
+        assert_eq!(tok!(num n + 1), Token::new(Type::Number(4)));
+
+        let source = "define f = fn(a, b) { a[0] <= b :: c; } else -> !x;";
+        let types = |tokens: Vec<Token>| -> Vec<Type> {
+            tokens.into_iter().map(|t| t.token_type).collect()
+        };
+        assert_eq!(
+            types(Lexer::default().lex(source).unwrap()),
+            types(tokens![
+                define, ident "f", =, fn, "(", ident "a", ",", ident "b", ")", "{",
+                ident "a", "[", num 0, "]", <=, ident "b", ::, ident "c", ;, "}",
+                else, ->, !, ident "x", ;,
+            ])
+        );
+        assert_eq!(
+            types(Lexer::default().lex("a ?? b?.c ? d : e").unwrap()),
+            types(tokens![ident "a", ??, ident "b", ?., ident "c", ?, ident "d", :, ident "e"])
+        );
+        assert_eq!(tokens![], Vec::<Token>::new());
+        assert_eq!(
+            tokens![str "s"],
+            vec![Token::new(Type::String("s".to_string()))]
+        );
+    }
+}
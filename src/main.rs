@@ -0,0 +1,453 @@
+use std::{
+    fs,
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use lexer::{
+    Lexer, SourceFile, Token,
+    analyze::token_stats,
+    diagnostics::Diagnostic,
+    export::to_json,
+    highlight::to_ansi,
+    repl::{Repl, check_script},
+    repro::Bundle,
+    trace,
+};
+
+const USAGE: &str = "usage: lexer [--format debug|json|plain] [FILE]
+       lexer highlight [FILE]
+       lexer trace [FILE]
+       lexer stats [FILE]
+       lexer repl
+       lexer repro BUNDLE
+       lexer test-scripts PATH...
+
+Lexes FILE (or standard input when FILE is missing or `-`) and prints the
+resulting tokens. Exits with status 1 if the input fails to lex and 2 on
+invalid usage.
+
+Commands:
+  highlight    print FILE with ANSI syntax highlighting
+  trace        list every rule the lexer applied to FILE and what it produced
+  stats        count the tokens of FILE by kind, along with its most frequent
+               identifiers, strings, numbers and lines
+  repl         lex lines interactively
+  repro        replay a reproduction bundle, printing every token, error and
+               warning it produces
+  test-scripts check the .lexrepl scripts at PATH (files or directories)
+               against their expected output; exits with status 1 on any
+               mismatch";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Format {
+    Debug,
+    Json,
+    Plain,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Args {
+    format: Format,
+    path: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Lex(Args),
+    Highlight(Option<String>),
+    Trace(Option<String>),
+    Stats(Option<String>),
+    Repl,
+    Repro(String),
+    TestScripts(Vec<String>),
+}
+
+fn parse_command(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("repl") => match args.nth(1) {
+            Some(arg) => Err(format!("unexpected argument `{}`", arg)),
+            None => Ok(Command::Repl),
+        },
+        Some("highlight") => {
+            args.next();
+            parse_path(args).map(Command::Highlight)
+        }
+        Some("repro") => {
+            args.next();
+            match parse_path(args)? {
+                Some(path) => Ok(Command::Repro(path)),
+                None => Err("expected a BUNDLE".to_string()),
+            }
+        }
+        Some("test-scripts") => {
+            args.next();
+            let paths = args.collect::<Vec<String>>();
+            match paths.iter().find(|p| p.starts_with('-')) {
+                Some(arg) => Err(format!("unknown option `{}`", arg)),
+                None if paths.is_empty() => Err("expected at least one PATH".to_string()),
+                None => Ok(Command::TestScripts(paths)),
+            }
+        }
+        Some("trace") => {
+            args.next();
+            parse_path(args).map(Command::Trace)
+        }
+        Some("stats") => {
+            args.next();
+            parse_path(args).map(Command::Stats)
+        }
+        _ => parse_args(args).map(Command::Lex),
+    }
+}
+
+fn parse_path(args: impl IntoIterator<Item = String>) -> Result<Option<String>, String> {
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "-" => path = None,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if path.is_some() => return Err("expected at most one FILE".to_string()),
+            _ => path = Some(arg),
+        }
+    }
+    Ok(path)
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        format: Format::Plain,
+        path: None,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" | "-f" => {
+                parsed.format = match args.next().as_deref() {
+                    Some("debug") => Format::Debug,
+                    Some("json") => Format::Json,
+                    Some("plain") => Format::Plain,
+                    Some(other) => return Err(format!("unknown format `{}`", other)),
+                    None => return Err("`--format` needs a value".to_string()),
+                }
+            }
+            "-" => parsed.path = None,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if parsed.path.is_some() => return Err("expected at most one FILE".to_string()),
+            _ => parsed.path = Some(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn read_input(path: Option<&str>) -> io::Result<String> {
+    match path {
+        Some(path) => fs::read_to_string(path),
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+    }
+}
+
+fn print_tokens(tokens: &[Token], format: Format, file: &SourceFile) {
+    match format {
+        Format::Debug => {
+            for token in tokens {
+                println!("{:?}", token);
+            }
+        }
+        Format::Json => println!("{}", to_json(tokens)),
+        Format::Plain => {
+            for token in tokens {
+                let position = file.line_col(token.span.start);
+                println!(
+                    "{}:{}\t{:?}\t{}",
+                    position.line + 1,
+                    position.column + 1,
+                    token.kind(),
+                    token
+                );
+            }
+        }
+    }
+}
+
+fn lex(args: Args) -> ExitCode {
+    let name = args.path.as_deref().unwrap_or("<stdin>");
+    let source = match read_input(args.path.as_deref()) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", name, e);
+            return ExitCode::from(2);
+        }
+    };
+    let file = SourceFile::with_name(name, source);
+    let mut output = Lexer::default().lex_file(&file);
+    match output.errors.is_empty() {
+        true => {
+            print_tokens(&output.tokens, args.format, &file);
+            ExitCode::SUCCESS
+        }
+        false => {
+            let color = io::stderr().is_terminal();
+            let diagnostic = Diagnostic::from(&output.errors.remove(0));
+            eprint!("{}", diagnostic.render_file(&file, color));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn highlight(path: Option<String>) -> ExitCode {
+    match read_input(path.as_deref()) {
+        Ok(source) => {
+            print!("{}", to_ansi(&source));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            let name = path.as_deref().unwrap_or("<stdin>");
+            eprintln!("error: cannot read {}: {}", name, e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn trace(path: Option<String>) -> ExitCode {
+    match read_input(path.as_deref()) {
+        Ok(source) => {
+            print!(
+                "{}",
+                trace::trace(&Lexer::default(), &source).render(&source)
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            let name = path.as_deref().unwrap_or("<stdin>");
+            eprintln!("error: cannot read {}: {}", name, e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn stats(path: Option<String>) -> ExitCode {
+    let name = path.as_deref().unwrap_or("<stdin>");
+    let source = match read_input(path.as_deref()) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", name, e);
+            return ExitCode::from(2);
+        }
+    };
+    let file = SourceFile::with_name(name, source);
+    let output = Lexer::default().lex_file(&file);
+    print!("{}", token_stats(&output.tokens, file.text()).render());
+    match output.errors.first() {
+        None => ExitCode::SUCCESS,
+        Some(error) => {
+            let color = io::stderr().is_terminal();
+            eprint!("{}", Diagnostic::from(error).render_file(&file, color));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn repro(path: String) -> ExitCode {
+    let bundle = match fs::read_to_string(&path) {
+        Ok(text) => match Bundle::parse(&text) {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                eprintln!("error: invalid bundle {}: {:?}", path, e);
+                return ExitCode::from(2);
+            }
+        },
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", path, e);
+            return ExitCode::from(2);
+        }
+    };
+    let version = env!("CARGO_PKG_VERSION");
+    if let Some(recorded) = bundle.version.as_deref().filter(|v| *v != version) {
+        eprintln!(
+            "warning: bundle was written by version {}, replaying with {}",
+            recorded, version
+        );
+    }
+    let output = bundle.replay();
+    for token in &output.tokens {
+        println!("{:?}", token);
+    }
+    for error in &output.errors {
+        println!("error: {:?}", error);
+    }
+    for warning in &output.warnings {
+        println!("warning: {:?}", warning);
+    }
+    ExitCode::SUCCESS
+}
+
+fn collect_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        scripts.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|e| e == "lexrepl") {
+            collect_scripts(&entry, scripts)?;
+        }
+    }
+    Ok(())
+}
+
+fn test_scripts(paths: Vec<String>) -> ExitCode {
+    let mut scripts = Vec::new();
+    for path in &paths {
+        if let Err(e) = collect_scripts(Path::new(path), &mut scripts) {
+            eprintln!("error: cannot read {}: {}", path, e);
+            return ExitCode::from(2);
+        }
+    }
+    let mut failed = 0;
+    for script in &scripts {
+        let name = script.display();
+        let failures = match fs::read_to_string(script)
+            .and_then(|source| check_script(Lexer::default(), &source))
+        {
+            Ok(failures) => failures,
+            Err(e) => {
+                eprintln!("error: cannot read {}: {}", name, e);
+                return ExitCode::from(2);
+            }
+        };
+        if failures.is_empty() {
+            println!("ok      {}", name);
+            continue;
+        }
+        failed += 1;
+        println!("FAILED  {}", name);
+        for failure in failures {
+            println!("  {}:{}: > {}", name, failure.line, failure.input);
+            for line in &failure.expected {
+                println!("  -{}", line);
+            }
+            for line in &failure.actual {
+                println!("  +{}", line);
+            }
+        }
+    }
+    println!(
+        "{} scripts, {} passed, {} failed",
+        scripts.len(),
+        scripts.len() - failed,
+        failed
+    );
+    match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
+
+fn main() -> ExitCode {
+    match parse_command(std::env::args().skip(1)) {
+        Ok(Command::Lex(args)) => lex(args),
+        Ok(Command::Highlight(path)) => highlight(path),
+        Ok(Command::Trace(path)) => trace(path),
+        Ok(Command::Stats(path)) => stats(path),
+        Ok(Command::Repro(path)) => repro(path),
+        Ok(Command::TestScripts(paths)) => test_scripts(paths),
+        Ok(Command::Repl) => {
+            match Repl::new(Lexer::default()).run(io::stdin().lock(), io::stdout()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Args, Command, Format, parse_args, parse_command};
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn args() {
+        assert_eq!(
+            parse(&[]),
+            Ok(Args {
+                format: Format::Plain,
+                path: None
+            })
+        );
+        assert_eq!(
+            parse(&["--format", "json", "in.lx"]),
+            Ok(Args {
+                format: Format::Json,
+                path: Some("in.lx".to_string())
+            })
+        );
+        assert_eq!(
+            parse(&["-f", "debug", "-"]),
+            Ok(Args {
+                format: Format::Debug,
+                path: None
+            })
+        );
+        assert!(parse(&["--format", "xml"]).is_err());
+        assert!(parse(&["--format"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        let command = |args: &[&str]| parse_command(args.iter().map(|a| a.to_string()));
+        assert_eq!(command(&["repl"]), Ok(Command::Repl));
+        assert_eq!(
+            command(&["highlight", "a.lx"]),
+            Ok(Command::Highlight(Some("a.lx".to_string())))
+        );
+        assert!(command(&["highlight", "--format", "json"]).is_err());
+        assert_eq!(command(&["trace"]), Ok(Command::Trace(None)));
+        assert_eq!(
+            command(&["stats", "big.lx"]),
+            Ok(Command::Stats(Some("big.lx".to_string())))
+        );
+        assert_eq!(
+            command(&["test-scripts", "a.lexrepl", "tests"]),
+            Ok(Command::TestScripts(vec![
+                "a.lexrepl".to_string(),
+                "tests".to_string()
+            ]))
+        );
+        assert!(command(&["test-scripts"]).is_err());
+        assert_eq!(
+            command(&["repro", "bug.repro"]),
+            Ok(Command::Repro("bug.repro".to_string()))
+        );
+        assert!(command(&["repro"]).is_err());
+        assert!(command(&["repl", "x"]).is_err());
+        assert_eq!(
+            command(&["-f", "json"]),
+            Ok(Command::Lex(Args {
+                format: Format::Json,
+                path: None
+            }))
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::vec::Vec;
+
+use crate::Token;
+
+/// A side table attaching values of type `T` to tokens by their index in a
+/// token stream, so later passes can record types, symbols, colors and the
+/// like without changing [`Token`]. Use one map per kind of data.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TokenMap<T> {
+    slots: Vec<Option<T>>,
+}
+
+impl<T> Default for TokenMap<T> {
+    fn default() -> Self {
+        TokenMap { slots: Vec::new() }
+    }
+}
+
+impl<T> TokenMap<T> {
+    pub fn new() -> Self {
+        TokenMap::default()
+    }
+
+    /// An empty map with room for one value per token in `tokens`.
+    pub fn for_tokens(tokens: &[Token]) -> Self {
+        let mut slots = Vec::with_capacity(tokens.len());
+        slots.resize_with(tokens.len(), || None);
+        TokenMap { slots }
+    }
+
+    /// Attaches `value` to the token at `index`, returning the value it
+    /// replaces.
+    pub fn insert(&mut self, index: usize, value: T) -> Option<T> {
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.slots[index].replace(value)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slots.get(index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.slots.get_mut(index)?.as_mut()
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.slots.get_mut(index)?.take()
+    }
+
+    /// The number of tokens with a value attached.
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    /// The attached values in token order, with their token indices.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot.as_ref()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{TokenMap, Type, try_lex};
+
+    #[test]
+    fn token_map() {
+        let tokens = try_lex("define x = y").unwrap();
+        let mut symbols = TokenMap::for_tokens(&tokens);
+        for (i, token) in tokens.iter().enumerate() {
+            if let Type::Identifier(name) = &token.token_type {
+                symbols.insert(i, name.len());
+            }
+        }
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.get(1), Some(&1));
+        assert_eq!(symbols.get(2), None);
+        assert_eq!(symbols.insert(3, 5), Some(1));
+        *symbols.get_mut(1).unwrap() += 1;
+        assert_eq!(symbols.iter().collect::<Vec<_>>(), vec![(1, &2), (3, &5)]);
+        assert_eq!(symbols.remove(1), Some(2));
+        assert_eq!(symbols.remove(10), None);
+
+        let mut colors = TokenMap::new();
+        assert!(colors.is_empty());
+        colors.insert(7, "red");
+        assert_eq!(colors.get(7), Some(&"red"));
+    }
+}
@@ -0,0 +1,369 @@
+//! A stack of lexing modes, for embedding sub-languages such as template
+//! literals or inline regexes.
+//!
+//! A [`Mode`] lexes one step at a time and says with a [`Transition`] whether
+//! to keep lexing in it, to enter another mode on top of it, or to return to
+//! the mode below. The bottom of the stack is always [`Normal`], the lexer's
+//! built-in rules, which also lex strings, interpolations and comments. Modes
+//! added through [`crate::LexerBuilder::mode`] form the lexer's [`Modes`] and
+//! are entered from [`Normal`] where their trigger text starts; from there
+//! on, modes enter each other. A mode can hand a step to [`Normal`] to lex
+//! ordinary tokens, as an interpolated expression does.
+//!
+//! Modes run in the entry points that run the lexer's filters, and in
+//! [`crate::Lexer::stream`] and [`crate::Lexer::lex_observed`], which
+//! reports each change of mode. Lexers with modes lex their whole input
+//! even where they could otherwise reuse or split work, as in
+//! [`crate::Lexer::relex`]. [`crate::trace`] and, with the `std` feature,
+//! `Lexer::lex_source` and `Lexer::lex_reader` lex with the built-in rules
+//! only.
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    BYTES_PER_TOKEN, Cursor, LexOutput, LexerOptions, Span, error_limit_reached, lex_preamble,
+    lex_step, token_limit_reached,
+};
+
+/// What to do with the stack of modes after a [`Mode::step`].
+#[derive(Clone)]
+pub enum Transition {
+    /// Keep lexing in the current mode.
+    Stay,
+    /// Lex in the given mode until it returns.
+    Push(Arc<dyn Mode>),
+    /// Replace the current mode with the given one.
+    Switch(Arc<dyn Mode>),
+    /// Return to the mode below the current one.
+    Pop,
+}
+
+impl fmt::Debug for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transition::Stay => f.write_str("Stay"),
+            Transition::Push(_) => f.write_str("Push(..)"),
+            Transition::Switch(_) => f.write_str("Switch(..)"),
+            Transition::Pop => f.write_str("Pop"),
+        }
+    }
+}
+
+/// One way of lexing, active while it is on top of the stack.
+pub trait Mode: Send + Sync {
+    /// Lexes one step at `chars`, adding what it finds to `out`. A single
+    /// token produced by the step is spanned over the input it consumed.
+    ///
+    /// A step that consumes nothing must leave the mode, or the mode is
+    /// popped for it.
+    fn step(&self, chars: &mut Cursor, options: &LexerOptions, out: &mut LexOutput) -> Transition;
+}
+
+impl<F> Mode for F
+where
+    F: Fn(&mut Cursor, &LexerOptions, &mut LexOutput) -> Transition + Send + Sync,
+{
+    fn step(&self, chars: &mut Cursor, options: &LexerOptions, out: &mut LexOutput) -> Transition {
+        self(chars, options, out)
+    }
+}
+
+/// The lexer's built-in rules. Never leaves the stack.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Normal;
+
+impl Mode for Normal {
+    fn step(&self, chars: &mut Cursor, options: &LexerOptions, out: &mut LexOutput) -> Transition {
+        lex_step(chars, options, out);
+        Transition::Stay
+    }
+}
+
+/// The modes a lexer enters from [`Normal`], each with its trigger.
+#[derive(Clone, Default)]
+pub struct Modes {
+    triggers: Vec<(String, Arc<dyn Mode>)>,
+}
+
+impl fmt::Debug for Modes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.triggers.iter().map(|(trigger, _)| trigger))
+            .finish()
+    }
+}
+
+impl Modes {
+    pub fn new() -> Self {
+        Modes::default()
+    }
+
+    /// Enters `mode` where `trigger` starts, leaving the trigger for the
+    /// mode's first step to consume. The longest trigger that matches wins.
+    ///
+    /// Panics if `trigger` is empty.
+    pub fn push(&mut self, trigger: impl Into<String>, mode: impl Mode + 'static) {
+        let trigger = trigger.into();
+        assert!(!trigger.is_empty(), "a mode trigger cannot be empty");
+        self.triggers.push((trigger, Arc::new(mode)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    fn triggered(&self, rest: &str) -> Option<&Arc<dyn Mode>> {
+        self.triggers
+            .iter()
+            .filter(|(trigger, _)| rest.starts_with(trigger.as_str()))
+            .max_by_key(|(trigger, _)| trigger.len())
+            .map(|(_, mode)| mode)
+    }
+
+    /// Lexes the rest of `chars`, starting in [`Normal`].
+    pub(crate) fn lex(&self, mut chars: Cursor, options: &LexerOptions, out: &mut LexOutput) {
+        out.tokens.reserve(chars.rest().len() / BYTES_PER_TOKEN);
+        lex_preamble(&mut chars, options, &mut out.tokens);
+        let mut stack = ModeStack::new();
+        while chars.peek().is_some() && !error_limit_reached(options, out.errors.len()) {
+            stack.step(self, &mut chars, options, out);
+            if token_limit_reached(options, 0, out) {
+                break;
+            }
+        }
+    }
+}
+
+/// The modes entered so far, with [`Normal`] at the bottom.
+pub(crate) struct ModeStack {
+    stack: Vec<Arc<dyn Mode>>,
+    /// Where a trigger last entered a mode, so that a mode leaving without
+    /// consuming anything is not entered again at the same place.
+    entered: Option<usize>,
+}
+
+impl ModeStack {
+    pub(crate) fn new() -> Self {
+        ModeStack {
+            stack: vec![Arc::new(Normal)],
+            entered: None,
+        }
+    }
+
+    /// Lexes one step at `chars` in the mode on top, or enters one of
+    /// `modes` if its trigger starts there. Returns the change to the stack,
+    /// if there was one.
+    pub(crate) fn step(
+        &mut self,
+        modes: &Modes,
+        chars: &mut Cursor,
+        options: &LexerOptions,
+        out: &mut LexOutput,
+    ) -> Option<Transition> {
+        let start = chars.offset;
+        let count = out.tokens.len();
+        let depth = self.stack.len();
+        let transition = match depth {
+            1 => match modes.triggered(chars.rest()) {
+                Some(mode) if self.entered != Some(start) => {
+                    self.entered = Some(start);
+                    Transition::Push(mode.clone())
+                }
+                _ => Normal.step(chars, options, out),
+            },
+            _ => self.stack[depth - 1].step(chars, options, out),
+        };
+        if out.tokens.len() == count + 1 {
+            out.tokens[count].span = Span::new(start, chars.offset);
+        }
+        match transition {
+            Transition::Push(mode) => {
+                self.stack.push(mode.clone());
+                Some(Transition::Push(mode))
+            }
+            Transition::Switch(mode) if depth > 1 => {
+                self.stack[depth - 1] = mode.clone();
+                Some(Transition::Switch(mode))
+            }
+            Transition::Stay if chars.offset > start => None,
+            _ if depth > 1 => {
+                self.stack.pop();
+                Some(Transition::Pop)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::String, sync::Arc, vec::Vec};
+
+    use crate::{
+        Cursor, LexError, LexEvent, LexObserver, LexOutput, Lexer, LexerOptions, Operator, Span,
+        Token, Type,
+        mode::{Mode, Normal, Transition},
+    };
+
+    /// A template literal: text between backticks, in which `${...}` holds
+    /// an expression.
+    fn backtick(chars: &mut Cursor, _: &LexerOptions, _: &mut LexOutput) -> Transition {
+        chars.eat("`");
+        Transition::Switch(Arc::new(template))
+    }
+
+    fn template(chars: &mut Cursor, _: &LexerOptions, out: &mut LexOutput) -> Transition {
+        if chars.eat("`") {
+            return Transition::Pop;
+        }
+        if chars.eat("${") {
+            out.tokens.push(Token::new(Type::InterpolationStart));
+            return Transition::Push(Arc::new(expression));
+        }
+        let start = chars.offset;
+        while !chars.rest().is_empty() && !chars.rest().starts_with('`') {
+            if chars.rest().starts_with("${") {
+                break;
+            }
+            chars.next();
+        }
+        let text = &chars.source[start..chars.offset];
+        out.tokens
+            .push(Token::new(Type::StringPart(text.to_string())));
+        Transition::Stay
+    }
+
+    /// An expression up to its closing `}`, skipping over nested braces.
+    fn expression(chars: &mut Cursor, options: &LexerOptions, out: &mut LexOutput) -> Transition {
+        match chars.peek() {
+            Some('}') => {
+                chars.next();
+                out.tokens.push(Token::new(Type::InterpolationEnd));
+                Transition::Pop
+            }
+            Some('`') => Transition::Push(Arc::new(backtick)),
+            Some('{') => {
+                Normal.step(chars, options, out);
+                Transition::Push(Arc::new(braces))
+            }
+            _ => Normal.step(chars, options, out),
+        }
+    }
+
+    /// Braces nested in an expression, which close with an ordinary `}`.
+    fn braces(chars: &mut Cursor, options: &LexerOptions, out: &mut LexOutput) -> Transition {
+        match chars.peek() {
+            Some('}') => {
+                Normal.step(chars, options, out);
+                Transition::Pop
+            }
+            _ => expression(chars, options, out),
+        }
+    }
+
+    #[test]
+    fn modes() {
+        let lexer = Lexer::builder().mode("`", backtick).build();
+        let source = "x = `a ${f({b: `c${1}`})} d` + y";
+        let tokens = lexer.lex(source).unwrap();
+        let types: Vec<Type> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        let id = |s: &str| Type::Identifier(s.to_string());
+        let part = |s: &str| Type::StringPart(s.to_string());
+        assert_eq!(
+            types,
+            vec![
+                id("x"),
+                Type::Operator(Operator::Equals),
+                part("a "),
+                Type::InterpolationStart,
+                id("f"),
+                Type::LeftParen,
+                Type::LeftBrace,
+                id("b"),
+                Type::Colon,
+                part("c"),
+                Type::InterpolationStart,
+                Type::Number(1),
+                Type::InterpolationEnd,
+                Type::RightBrace,
+                Type::RightParen,
+                Type::InterpolationEnd,
+                part(" d"),
+                Type::Operator(Operator::Plus),
+                id("y"),
+            ]
+        );
+        assert_eq!(tokens[2].span, Span::new(5, 7));
+        assert_eq!(tokens[3].span, Span::new(7, 9));
+        assert_eq!(tokens[13].span, Span::new(22, 23));
+
+        // Without the mode, the backtick is an unknown character.
+        assert_eq!(Lexer::default().lex(source).unwrap()[2].token_type, id("a"));
+        // The built-in rules still report errors inside a mode.
+        assert_eq!(
+            lexer.lex("`${\"a}`").unwrap_err(),
+            LexError::UnterminatedString(3)
+        );
+    }
+
+    #[test]
+    fn streamed_modes() {
+        #[derive(Default)]
+        struct Log(Vec<String>);
+
+        impl LexObserver for Log {
+            fn on_token(&mut self, token: &Token) {
+                self.0.push(format!("{}", token.token_type));
+            }
+
+            fn on_mode_change(&mut self, transition: &Transition) {
+                self.0.push(format!("{:?}", transition));
+            }
+        }
+
+        let lexer = Lexer::builder().mode("`", backtick).build();
+        let source = "x `a ${b}` y";
+        let streamed: Vec<Token> = lexer
+            .stream(source)
+            .filter_map(|event| match event {
+                LexEvent::Token(token) => Some(token),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(streamed, lexer.lex(source).unwrap());
+
+        let mut log = Log::default();
+        let output = lexer.lex_observed(source, &mut log);
+        assert_eq!(output, lexer.lex_with_diagnostics(source));
+        assert_eq!(
+            log.0,
+            vec![
+                "x",
+                "Push(..)",
+                "Switch(..)",
+                "a ",
+                "${",
+                "Push(..)",
+                "b",
+                "}",
+                "Pop",
+                "Pop",
+                "y"
+            ]
+        );
+    }
+
+    #[test]
+    fn stuck_mode() {
+        let stuck = |_: &mut Cursor, _: &LexerOptions, _: &mut LexOutput| Transition::Stay;
+        let lexer = Lexer::builder().mode("@", stuck).trivia(true).build();
+        let tokens = lexer.lex("@x").unwrap();
+        assert_eq!(tokens[0], Token::with_span(Type::None, Span::new(0, 1)));
+        assert_eq!(tokens[1].token_type, Type::Identifier("x".to_string()));
+    }
+}
@@ -0,0 +1,158 @@
+//! Lexing large inputs on several threads.
+//!
+//! The input is cut into chunks at line starts and every chunk is lexed on its
+//! own thread. A cut can fall inside a string or block comment, so the chunks
+//! are stitched together in order: the lexer carries no state from one token
+//! to the next, so a chunk's results are kept from the first position where
+//! the chunk before it stopped and the chunk itself started a token. Before
+//! that position, the input is lexed again on the calling thread.
+
+use std::thread;
+
+use crate::{Cursor, LexOutput, Lexer, LexerOptions, lex_preamble, lex_step};
+
+/// Inputs shorter than this are not worth the threads.
+const MIN_CHUNK: usize = 1 << 16;
+
+/// Where a step of the lexer started, and how much output came before it.
+struct Step {
+    offset: usize,
+    tokens: usize,
+    errors: usize,
+    warnings: usize,
+}
+
+/// What lexing one chunk produced.
+struct Chunk {
+    steps: Vec<Step>,
+    out: LexOutput,
+    /// Where the last step stopped, which may be past the end of the chunk.
+    end: usize,
+}
+
+impl Lexer {
+    /// Like [`Lexer::lex_with_diagnostics`], lexing `s` on as many threads
+    /// as the machine offers. The output is the same.
+    ///
+    /// Lexers with an error or token limit depend on everything lexed before
+    /// a position, and an interpolated string, an attribute or a mode may
+    /// span lines with text that would lex differently on its own, so lexers
+    /// with any of these lex on the calling thread.
+    pub fn lex_parallel(&self, s: &str) -> LexOutput {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        self.lex_chunked(s, threads.min(s.len() / MIN_CHUNK))
+    }
+
+    fn lex_chunked(&self, s: &str, chunks: usize) -> LexOutput {
+        let options = self.options();
+        if chunks <= 1
+            || options.max_errors.is_some()
+            || options.max_tokens.is_some()
+            || options.interpolation
+            || options.attributes
+            || !self.modes.is_empty()
+        {
+            return self.lex_with_diagnostics(s);
+        }
+        let mut bounds = vec![0];
+        for i in 1..chunks {
+            let at = (s.len() * i / chunks).max(bounds[bounds.len() - 1]);
+            match s.as_bytes()[at..].iter().position(|&b| b == b'\n') {
+                Some(newline) => bounds.push(at + newline + 1),
+                None => break,
+            }
+        }
+        bounds.push(s.len());
+        bounds.dedup();
+        let chunks: Vec<Chunk> = thread::scope(|scope| {
+            let handles: Vec<_> = bounds
+                .windows(2)
+                .map(|bounds| scope.spawn(move || lex_chunk(s, options, bounds[0], bounds[1])))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("lexing thread panicked"))
+                .collect()
+        });
+        let mut chunks = chunks.into_iter();
+        let Some(first) = chunks.next() else {
+            return LexOutput::default();
+        };
+        let mut output = first.out;
+        let mut chars = Cursor::new(s);
+        chars.offset = first.end;
+        for chunk in chunks {
+            while chars.offset < chunk.end {
+                if let Ok(i) = chunk
+                    .steps
+                    .binary_search_by_key(&chars.offset, |step| step.offset)
+                {
+                    let step = &chunk.steps[i];
+                    let mut out = chunk.out;
+                    output.tokens.extend(out.tokens.drain(step.tokens..));
+                    output.errors.extend(out.errors.drain(step.errors..));
+                    output.warnings.extend(out.warnings.drain(step.warnings..));
+                    chars.offset = chunk.end;
+                    break;
+                }
+                lex_step(&mut chars, options, &mut output);
+            }
+        }
+        self.finish(s, &mut output);
+        output
+    }
+}
+
+fn lex_chunk(source: &str, options: &LexerOptions, start: usize, end: usize) -> Chunk {
+    let mut chars = Cursor::new(source);
+    chars.offset = start;
+    let mut chunk = Chunk {
+        steps: Vec::new(),
+        out: LexOutput::default(),
+        end,
+    };
+    if start == 0 {
+        lex_preamble(&mut chars, options, &mut chunk.out.tokens);
+    }
+    while chars.offset < end && chars.peek().is_some() {
+        chunk.steps.push(Step {
+            offset: chars.offset,
+            tokens: chunk.out.tokens.len(),
+            errors: chunk.out.errors.len(),
+            warnings: chunk.out.warnings.len(),
+        });
+        lex_step(&mut chars, options, &mut chunk.out);
+    }
+    chunk.end = chars.offset;
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Lexer;
+
+    #[test]
+    fn lex_chunked() {
+        let lexers = [
+            Lexer::default(),
+            Lexer::builder()
+                .trivia(true)
+                .labels(true)
+                .long_token_warning(8)
+                .build(),
+            Lexer::builder().layout(true).eof(true).build(),
+        ];
+        let source = "#!run\ndefine é = \"a\n/* b\n c */ y: z\n\"s\" /* \n\n*/ 1\nq";
+        for lexer in &lexers {
+            let expected = lexer.lex_with_diagnostics(source);
+            for chunks in 1..source.len() {
+                assert_eq!(
+                    lexer.lex_chunked(source, chunks),
+                    expected,
+                    "{chunks} chunks"
+                );
+            }
+            assert_eq!(lexer.lex_parallel(source), expected);
+        }
+    }
+}
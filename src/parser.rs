@@ -0,0 +1,1232 @@
+//! A precedence-climbing expression parser over a [`TokenStream`].
+//!
+//! Binary operators bind, from loosest to tightest:
+//!
+//! ```text
+//! custom operators
+//! ??
+//! ||
+//! &&
+//! |
+//! ^
+//! &
+//! == !=
+//! < > <= >=
+//! << >>
+//! + -
+//! * / %
+//! ```
+//!
+//! All of them are left-associative, as [`Operator::precedence`] and
+//! [`Operator::associativity`] have it. This is the [`Default`]
+//! [`PrecedenceTable`]; [`parse_expression`] and [`parse_statement`] take
+//! any other. The prefix operators `!`, `-` and `~` bind tighter than any
+//! binary operator, and calls tighter still.
+//!
+//! Statements are `define NAME = EXPR;`, `if (EXPR) BLOCK` with an optional
+//! `else BLOCK` or `else if ...`, brace-delimited blocks, and expressions
+//! followed by `;`.
+//!
+//! [`parse_program_all_errors`] recovers from a statement that fails to
+//! parse by skipping to the next `;`, `}` or `define` or `if` keyword
+//! outside the braces the statement opened, leaving a [`Stmt::Error`] in its
+//! place. Where an expression is missing, it leaves an [`Expr::Error`]
+//! instead and parses on, so that a statement fails only on a token it
+//! cannot skip over; the errors that follow from a missing expression are
+//! not reported.
+//!
+//! Trees nest at most [`MAX_DEPTH`] deep; deeper input fails with
+//! [`ParseError::NestingTooDeep`] instead of exhausting the stack.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+use crate::{
+    Assoc, Keyword, LexError, Lexer, Operator, Span, Token, TokenKind, TokenStream, Type,
+    Unexpected, operator_table,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Literal {
+    Number(i32),
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+impl fmt::Display for Literal {
+    /// Strings are quoted and escaped, so the output is always one line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Number(n) => write!(f, "{}", n),
+            Literal::String(s) => write!(f, "{:?}", s),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Null => f.write_str("null"),
+        }
+    }
+}
+
+/// An expression, with the span of source it was parsed from.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expr {
+    Literal(Literal, Span),
+    Identifier(String, Span),
+    Unary {
+        op: Operator,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: Operator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    /// A parenthesized expression; the span includes the parentheses.
+    Grouping(Box<Expr>, Span),
+    /// A missing expression, with an empty span where it was expected. Only
+    /// produced by [`parse_program_all_errors`].
+    Error(Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(_, span)
+            | Expr::Identifier(_, span)
+            | Expr::Grouping(_, span)
+            | Expr::Error(span) => *span,
+            Expr::Unary { span, .. } | Expr::Binary { span, .. } | Expr::Call { span, .. } => *span,
+        }
+    }
+
+    /// The expression as an S-expression, such as `(+ 1 (* 2 3))` for
+    /// `1 + 2 * 3`. Calls print as `(call f x)`, parentheses as `(group x)`
+    /// and missing expressions as `(error)`; spans are left out.
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        self.write_sexpr(&mut out);
+        out
+    }
+
+    fn write_sexpr(&self, out: &mut String) {
+        match self {
+            Expr::Literal(literal, _) => {
+                let _ = write!(out, "{}", literal);
+            }
+            Expr::Identifier(name, _) => out.push_str(name),
+            Expr::Unary { op, operand, .. } => {
+                let _ = write!(out, "({} ", op);
+                operand.write_sexpr(out);
+                out.push(')');
+            }
+            Expr::Binary {
+                op, left, right, ..
+            } => {
+                let _ = write!(out, "({} ", op);
+                left.write_sexpr(out);
+                out.push(' ');
+                right.write_sexpr(out);
+                out.push(')');
+            }
+            Expr::Call { callee, args, .. } => {
+                out.push_str("(call ");
+                callee.write_sexpr(out);
+                for arg in args {
+                    out.push(' ');
+                    arg.write_sexpr(out);
+                }
+                out.push(')');
+            }
+            Expr::Grouping(inner, _) => {
+                out.push_str("(group ");
+                inner.write_sexpr(out);
+                out.push(')');
+            }
+            Expr::Error(_) => out.push_str("(error)"),
+        }
+    }
+
+    /// The expression as an indented tree, one node per line with its span.
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        let span = self.span();
+        let _ = write!(out, "{:indent$}", "", indent = depth * 2);
+        let _ = match self {
+            Expr::Literal(literal, _) => write!(out, "Literal {}", literal),
+            Expr::Identifier(name, _) => write!(out, "Identifier {}", name),
+            Expr::Unary { op, .. } => write!(out, "Unary {}", op),
+            Expr::Binary { op, .. } => write!(out, "Binary {}", op),
+            Expr::Call { .. } => write!(out, "Call"),
+            Expr::Grouping(..) => write!(out, "Grouping"),
+            Expr::Error(_) => write!(out, "Error"),
+        };
+        let _ = writeln!(out, " {}..{}", span.start, span.end);
+        match self {
+            Expr::Literal(..) | Expr::Identifier(..) | Expr::Error(_) => (),
+            Expr::Unary { operand, .. } => operand.write_tree(depth + 1, out),
+            Expr::Binary { left, right, .. } => {
+                left.write_tree(depth + 1, out);
+                right.write_tree(depth + 1, out);
+            }
+            Expr::Call { callee, args, .. } => {
+                callee.write_tree(depth + 1, out);
+                for arg in args {
+                    arg.write_tree(depth + 1, out);
+                }
+            }
+            Expr::Grouping(inner, _) => inner.write_tree(depth + 1, out),
+        }
+    }
+}
+
+/// A statement, with the span of source it was parsed from.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Stmt {
+    Define {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    If {
+        condition: Expr,
+        /// Always a [`Stmt::Block`].
+        then_branch: Box<Stmt>,
+        /// A [`Stmt::Block`], or a [`Stmt::If`] for `else if`.
+        else_branch: Option<Box<Stmt>>,
+        span: Span,
+    },
+    /// An expression statement; the span includes the `;`.
+    Expr(Expr, Span),
+    Block(Vec<Stmt>, Span),
+    /// A statement that failed to parse, spanning the tokens skipped to
+    /// recover. Only produced by [`parse_program_all_errors`].
+    Error(Span),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Define { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::Expr(_, span)
+            | Stmt::Block(_, span)
+            | Stmt::Error(span) => *span,
+        }
+    }
+
+    /// The statement as an S-expression, in the style of
+    /// [`Expr::to_sexpr`]: `(define x 1)`, `(if c (block ...) (block ...))`,
+    /// `(expr e)`, `(block ...)` and `(error)`.
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        self.write_sexpr(&mut out);
+        out
+    }
+
+    fn write_sexpr(&self, out: &mut String) {
+        match self {
+            Stmt::Define { name, value, .. } => {
+                let _ = write!(out, "(define {} ", name);
+                value.write_sexpr(out);
+                out.push(')');
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                out.push_str("(if ");
+                condition.write_sexpr(out);
+                out.push(' ');
+                then_branch.write_sexpr(out);
+                if let Some(else_branch) = else_branch {
+                    out.push(' ');
+                    else_branch.write_sexpr(out);
+                }
+                out.push(')');
+            }
+            Stmt::Expr(expr, _) => {
+                out.push_str("(expr ");
+                expr.write_sexpr(out);
+                out.push(')');
+            }
+            Stmt::Block(statements, _) => {
+                out.push_str("(block");
+                for statement in statements {
+                    out.push(' ');
+                    statement.write_sexpr(out);
+                }
+                out.push(')');
+            }
+            Stmt::Error(_) => out.push_str("(error)"),
+        }
+    }
+
+    /// The statement as an indented tree, in the style of
+    /// [`Expr::to_tree`].
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        let span = self.span();
+        let _ = write!(out, "{:indent$}", "", indent = depth * 2);
+        let _ = match self {
+            Stmt::Define { name, .. } => write!(out, "Define {}", name),
+            Stmt::If { .. } => write!(out, "If"),
+            Stmt::Expr(..) => write!(out, "Expr"),
+            Stmt::Block(..) => write!(out, "Block"),
+            Stmt::Error(_) => write!(out, "Error"),
+        };
+        let _ = writeln!(out, " {}..{}", span.start, span.end);
+        match self {
+            Stmt::Define { value, .. } => value.write_tree(depth + 1, out),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                condition.write_tree(depth + 1, out);
+                then_branch.write_tree(depth + 1, out);
+                if let Some(else_branch) = else_branch {
+                    else_branch.write_tree(depth + 1, out);
+                }
+            }
+            Stmt::Expr(expr, _) => expr.write_tree(depth + 1, out),
+            Stmt::Block(statements, _) => {
+                for statement in statements {
+                    statement.write_tree(depth + 1, out);
+                }
+            }
+            Stmt::Error(_) => (),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    /// The source failed to lex.
+    Lex(LexError),
+    /// An expression was expected; carries the token found instead, or
+    /// `None` at the end of input.
+    ExpectedExpression(Option<Token>),
+    /// A specific token was expected.
+    Unexpected(Unexpected),
+    /// [`parse`] finished an expression before the end of input; carries the
+    /// first token left over. Also reported for a `}` with no block to close.
+    TrailingInput(Token),
+    /// Expressions or blocks nest more than [`MAX_DEPTH`] deep; carries the
+    /// token where parsing gave up, or `None` at the end of input.
+    NestingTooDeep(Option<Token>),
+}
+
+impl ParseError {
+    /// A short, stable identifier of the kind of error, such as `P001`; a
+    /// [`ParseError::Lex`] has the code of its [`LexError`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::Lex(error) => error.code(),
+            ParseError::ExpectedExpression(_) => "P001",
+            ParseError::Unexpected(_) => "P002",
+            ParseError::TrailingInput(_) => "P003",
+            ParseError::NestingTooDeep(_) => "P004",
+        }
+    }
+
+    /// The source the error points at, or `None` for an error at the end of
+    /// input.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParseError::Lex(error) => Some(error.span()),
+            ParseError::ExpectedExpression(token) | ParseError::NestingTooDeep(token) => {
+                token.as_ref().map(|token| token.span)
+            }
+            ParseError::Unexpected(unexpected) => unexpected.found.as_ref().map(|token| token.span),
+            ParseError::TrailingInput(token) => Some(token.span),
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            ParseError::Lex(error) => error.message().to_string(),
+            ParseError::ExpectedExpression(token) => {
+                format!("expected an expression, found {}", found(token.as_ref()))
+            }
+            ParseError::Unexpected(unexpected) => format!(
+                "expected {:?}, found {}",
+                unexpected.expected,
+                found(unexpected.found.as_ref())
+            ),
+            ParseError::TrailingInput(token) => {
+                format!("unexpected {} after the end", found(Some(token)))
+            }
+            ParseError::NestingTooDeep(_) => "nesting too deep".to_string(),
+        }
+    }
+}
+
+fn found(token: Option<&Token>) -> String {
+    match token {
+        Some(token) => format!("`{}`", token),
+        None => "end of input".to_string(),
+    }
+}
+
+/// Writes the message, the span as byte offsets unless the error is at the
+/// end of input, and the code, as in `expected an expression, found end of
+/// input [P001]`.
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Lex(error) => write!(f, "{}", error),
+            _ => {
+                f.write_str(&self.message())?;
+                if let Some(span) = self.span() {
+                    write!(f, " at {}..{}", span.start, span.end)?;
+                }
+                write!(f, " [{}]", self.code())
+            }
+        }
+    }
+}
+
+impl core::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            ParseError::Lex(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(error: LexError) -> Self {
+        ParseError::Lex(error)
+    }
+}
+
+impl From<Unexpected> for ParseError {
+    fn from(unexpected: Unexpected) -> Self {
+        ParseError::Unexpected(unexpected)
+    }
+}
+
+/// How deeply the trees the parser builds may nest, counting every
+/// statement, block, parenthesis, operator and call, before parsing fails
+/// with [`ParseError::NestingTooDeep`] rather than overflowing the stack.
+/// [`crate::eval`] stops at the same depth.
+pub const MAX_DEPTH: usize = 256;
+
+/// The binding power and associativity of each binary operator, higher
+/// binding tighter. An operator missing from the table ends the expression
+/// in front of it.
+///
+/// The [`Default`] table holds every built-in operator but `=`, `!` and `~`
+/// at its [`Operator::precedence`], with its [`Operator::associativity`],
+/// and puts custom operators without an entry of their own at the custom
+/// operators' level, grouping to the left.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PrecedenceTable {
+    entries: Vec<(Operator, u8, Assoc)>,
+    /// Where custom operators without an entry of their own bind.
+    custom: Option<(u8, Assoc)>,
+}
+
+impl Default for PrecedenceTable {
+    fn default() -> Self {
+        let mut entries = operator_table();
+        entries.retain(|(op, _, _)| {
+            !matches!(op, Operator::Equals | Operator::Bang | Operator::BitNot)
+        });
+        let custom = Operator::Custom(String::new());
+        PrecedenceTable {
+            entries,
+            custom: Some((custom.precedence(), custom.associativity())),
+        }
+    }
+}
+
+impl PrecedenceTable {
+    /// A table without any binary operators.
+    pub fn empty() -> Self {
+        PrecedenceTable {
+            entries: Vec::new(),
+            custom: None,
+        }
+    }
+
+    /// Gives `op` the binding power `precedence` and the associativity
+    /// `assoc`, replacing its entry if it had one. An entry for an
+    /// [`Operator::Custom`] applies to that spelling only.
+    pub fn insert(&mut self, op: Operator, precedence: u8, assoc: Assoc) {
+        self.remove(&op);
+        self.entries.push((op, precedence, assoc));
+    }
+
+    /// Removes the entry for `op`, if there is one.
+    pub fn remove(&mut self, op: &Operator) {
+        self.entries.retain(|(entry, _, _)| entry != op);
+    }
+
+    /// Sets where custom operators without an entry of their own bind, or
+    /// with `None`, leaves them out of expressions.
+    pub fn set_custom(&mut self, binding: Option<(u8, Assoc)>) {
+        self.custom = binding;
+    }
+
+    /// The binding power and associativity of `op` as a binary operator, if
+    /// it is one.
+    pub fn get(&self, op: &Operator) -> Option<(u8, Assoc)> {
+        let entry = self.entries.iter().find(|(entry, _, _)| entry == op);
+        match (entry, op) {
+            (Some(&(_, precedence, assoc)), _) => Some((precedence, assoc)),
+            (None, Operator::Custom(_)) => self.custom,
+            (None, _) => None,
+        }
+    }
+}
+
+/// The state of one parse.
+struct Parser<'a> {
+    tokens: &'a mut TokenStream,
+    table: &'a PrecedenceTable,
+    /// The expressions and blocks open around the next token.
+    depth: usize,
+    /// With `Some`, missing expressions and statements that fail to parse
+    /// are recovered from, their errors collected here.
+    errors: Option<Vec<ParseError>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a mut TokenStream, table: &'a PrecedenceTable) -> Self {
+        Parser {
+            tokens,
+            table,
+            depth: 0,
+            errors: None,
+        }
+    }
+
+    /// Goes one level deeper, failing past [`MAX_DEPTH`].
+    fn deepen(&mut self) -> Result<(), ParseError> {
+        match self.depth == MAX_DEPTH {
+            true => Err(ParseError::NestingTooDeep(self.tokens.peek().cloned())),
+            false => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs `parse` one level deeper.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.deepen()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let Some(token) = self.tokens.peek().cloned() else {
+            return self.missing(ParseError::ExpectedExpression(None));
+        };
+        let span = token.span;
+        let expr = match token.token_type {
+            Type::Number(n) => Expr::Literal(Literal::Number(n), span),
+            Type::String(s) => Expr::Literal(Literal::String(s), span),
+            Type::Keyword(Keyword::True) => Expr::Literal(Literal::Bool(true), span),
+            Type::Keyword(Keyword::False) => Expr::Literal(Literal::Bool(false), span),
+            Type::Keyword(Keyword::Null) => Expr::Literal(Literal::Null, span),
+            Type::Identifier(name) => Expr::Identifier(name, span),
+            Type::LeftParen => {
+                self.tokens.next();
+                let inner = self.nested(|p| p.expression(0))?;
+                let end = self.tokens.expect(TokenKind::RightParen)?.span.end;
+                return Ok(Expr::Grouping(Box::new(inner), Span::new(span.start, end)));
+            }
+            _ => return self.missing(ParseError::ExpectedExpression(Some(token))),
+        };
+        self.tokens.next();
+        Ok(expr)
+    }
+
+    /// Fails with `error`, or when collecting errors, adds it to them and
+    /// returns an [`Expr::Error`] before the next token, consuming nothing.
+    fn missing(&mut self, error: ParseError) -> Result<Expr, ParseError> {
+        let Some(errors) = &mut self.errors else {
+            return Err(error);
+        };
+        errors.push(error);
+        let at = match (self.tokens.peek(), self.tokens.previous()) {
+            (Some(next), _) => next.span.start,
+            (None, Some(previous)) => previous.span.end,
+            (None, None) => 0,
+        };
+        Ok(Expr::Error(Span::new(at, at)))
+    }
+
+    /// Parses calls, each of which nests its callee a level deeper.
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let depth = self.depth;
+        let result = self.calls();
+        self.depth = depth;
+        result
+    }
+
+    fn calls(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
+        while self.tokens.at(TokenKind::LeftParen) {
+            self.deepen()?;
+            self.tokens.next();
+            let args = self.nested(|p| {
+                let mut args = Vec::new();
+                if !p.tokens.at(TokenKind::RightParen) {
+                    loop {
+                        args.push(p.expression(0)?);
+                        if !p.tokens.eat(TokenKind::Comma) {
+                            break;
+                        }
+                    }
+                }
+                Ok(args)
+            })?;
+            let end = self.tokens.expect(TokenKind::RightParen)?.span.end;
+            let span = Span::new(expr.span().start, end);
+            expr = Expr::Call {
+                callee: Box::new(expr),
+                args,
+                span,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let op = match self.tokens.peek() {
+            Some(Token {
+                token_type:
+                    Type::Operator(op @ (Operator::Bang | Operator::Minus | Operator::BitNot)),
+                span,
+            }) => Some((op.clone(), span.start)),
+            _ => None,
+        };
+        let Some((op, start)) = op else {
+            return self.call();
+        };
+        self.tokens.next();
+        let operand = self.nested(Parser::unary)?;
+        let span = Span::new(start, operand.span().end);
+        Ok(Expr::Unary {
+            op,
+            operand: Box::new(operand),
+            span,
+        })
+    }
+
+    /// Parses operators binding at least as tightly as `min_precedence`.
+    /// Each operator nests its left operand a level deeper.
+    fn expression(&mut self, min_precedence: u16) -> Result<Expr, ParseError> {
+        let depth = self.depth;
+        let result = self.binary(min_precedence);
+        self.depth = depth;
+        result
+    }
+
+    fn binary(&mut self, min_precedence: u16) -> Result<Expr, ParseError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.tokens.peek() {
+                Some(Token {
+                    token_type: Type::Operator(op),
+                    ..
+                }) => op.clone(),
+                _ => return Ok(left),
+            };
+            let Some((precedence, assoc)) = self.table.get(&op) else {
+                return Ok(left);
+            };
+            let precedence = u16::from(precedence);
+            if precedence < min_precedence {
+                return Ok(left);
+            }
+            self.deepen()?;
+            self.tokens.next();
+            let right = match assoc {
+                Assoc::Left => self.nested(|p| p.expression(precedence + 1))?,
+                Assoc::Right => self.nested(|p| p.expression(precedence))?,
+            };
+            let span = Span::new(left.span().start, right.span().end);
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+                span,
+            };
+        }
+    }
+
+    /// Parses a block, recovering from the statements in it that fail to
+    /// parse when collecting errors.
+    fn block(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.tokens.expect(TokenKind::LeftBrace)?.span.start;
+        let statements = self.nested(|p| {
+            let mut statements = Vec::new();
+            while !p.tokens.at(TokenKind::RightBrace) && !p.tokens.is_at_end() {
+                statements.push(match p.errors.is_some() {
+                    true => p.recover(),
+                    false => p.statement()?,
+                });
+            }
+            Ok(statements)
+        })?;
+        let end = self.tokens.expect(TokenKind::RightBrace)?.span.end;
+        Ok(Stmt::Block(statements, Span::new(start, end)))
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.tokens.expect(TokenKind::If)?.span.start;
+        self.tokens.expect(TokenKind::LeftParen)?;
+        let condition = self.expression(0)?;
+        self.tokens.expect(TokenKind::RightParen)?;
+        let then_branch = self.block()?;
+        let else_branch = match self.tokens.eat(TokenKind::Else) {
+            true if self.tokens.at(TokenKind::If) => Some(self.nested(Parser::if_statement)?),
+            true => Some(self.block()?),
+            false => None,
+        };
+        let end = else_branch.as_ref().unwrap_or(&then_branch).span().end;
+        Ok(Stmt::If {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+            span: Span::new(start, end),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.nested(Parser::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> Result<Stmt, ParseError> {
+        match self.tokens.peek().map(Token::kind) {
+            Some(TokenKind::Define) => {
+                let start = self.tokens.expect(TokenKind::Define)?.span.start;
+                let name = self.tokens.expect(TokenKind::Identifier)?.to_string();
+                self.tokens.expect(TokenKind::Equals)?;
+                let value = self.expression(0)?;
+                let end = self.tokens.expect(TokenKind::Semicolon)?.span.end;
+                Ok(Stmt::Define {
+                    name,
+                    value,
+                    span: Span::new(start, end),
+                })
+            }
+            Some(TokenKind::If) => self.if_statement(),
+            Some(TokenKind::LeftBrace) => self.block(),
+            _ => {
+                let expr = self.expression(0)?;
+                let end = self.tokens.expect(TokenKind::Semicolon)?.span.end;
+                let span = Span::new(expr.span().start, end);
+                Ok(Stmt::Expr(expr, span))
+            }
+        }
+    }
+
+    /// Parses one statement, or if it fails, adds the error to the errors
+    /// collected, unless an [`Expr::Error`] in the statement already caused
+    /// it, and skips past the statement, returning a [`Stmt::Error`]. Only
+    /// called with a token left.
+    fn recover(&mut self) -> Stmt {
+        let checkpoint = self.tokens.checkpoint();
+        let reported = self.errors.as_ref().map_or(0, Vec::len);
+        match self.statement() {
+            Ok(stmt) => return stmt,
+            Err(error) => {
+                let errors = self.errors.get_or_insert_default();
+                if errors.len() == reported {
+                    errors.push(error);
+                }
+            }
+        }
+        // Skip from the start of the statement, so that the braces it opened
+        // are known, to the next statement outside them. The first token is
+        // always skipped, so that parsing moves on.
+        let tokens = &mut *self.tokens;
+        tokens.rewind(checkpoint);
+        let mut span = tokens.peek().map_or(Span::new(0, 0), |token| token.span);
+        let mut depth = 0usize;
+        while let Some(token) = tokens.peek().filter(|_| !tokens.is_at_end()) {
+            let kind = token.kind();
+            let starts_next = matches!(
+                kind,
+                TokenKind::RightBrace | TokenKind::Define | TokenKind::If
+            );
+            if starts_next && depth == 0 && tokens.checkpoint() != checkpoint {
+                break;
+            }
+            span.end = token.span.end;
+            tokens.next();
+            match kind {
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightBrace if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 && !tokens.at(TokenKind::Else) {
+                        break;
+                    }
+                }
+                TokenKind::Semicolon if depth == 0 => break,
+                _ => (),
+            }
+        }
+        Stmt::Error(span)
+    }
+}
+
+/// Parses one expression from the front of `tokens`, binding operators as
+/// `table` has them and leaving whatever follows unconsumed.
+pub fn parse_expression(
+    tokens: &mut TokenStream,
+    table: &PrecedenceTable,
+) -> Result<Expr, ParseError> {
+    Parser::new(tokens, table).expression(0)
+}
+
+/// Parses one statement from the front of `tokens`, binding operators as
+/// `table` has them.
+pub fn parse_statement(
+    tokens: &mut TokenStream,
+    table: &PrecedenceTable,
+) -> Result<Stmt, ParseError> {
+    Parser::new(tokens, table).statement()
+}
+
+/// Lexes `source` with `lexer` and parses all of it as a sequence of
+/// statements, with the default [`PrecedenceTable`].
+pub fn parse_program(lexer: &Lexer, source: &str) -> Result<Vec<Stmt>, ParseError> {
+    let mut tokens = TokenStream::new(lexer.lex(source)?);
+    let table = PrecedenceTable::default();
+    let mut statements = Vec::new();
+    while let Some(token) = tokens.peek().filter(|_| !tokens.is_at_end()) {
+        if token.kind() == TokenKind::RightBrace {
+            return Err(ParseError::TrailingInput(token.clone()));
+        }
+        statements.push(parse_statement(&mut tokens, &table)?);
+    }
+    Ok(statements)
+}
+
+/// Lexes `source` with `lexer` and parses all of it as a sequence of
+/// statements, recovering from errors instead of stopping at the first.
+/// Returns every statement, with a [`Stmt::Error`] for each that failed to
+/// parse, and every error: those from lexing first, then those from
+/// parsing, in order. A `}` with nothing to close is reported as
+/// [`ParseError::TrailingInput`] and skipped.
+pub fn parse_program_all_errors(lexer: &Lexer, source: &str) -> (Vec<Stmt>, Vec<ParseError>) {
+    let (tokens, lex_errors) = lexer.lex_all_errors(source);
+    let mut tokens = TokenStream::new(tokens);
+    let table = PrecedenceTable::default();
+    let mut parser = Parser::new(&mut tokens, &table);
+    parser.errors = Some(lex_errors.into_iter().map(ParseError::Lex).collect());
+    let mut statements = Vec::new();
+    while let Some(token) = parser.tokens.peek().filter(|_| !parser.tokens.is_at_end()) {
+        statements.push(match token.kind() {
+            TokenKind::RightBrace => {
+                let token = token.clone();
+                parser.tokens.next();
+                let span = token.span;
+                parser
+                    .errors
+                    .get_or_insert_default()
+                    .push(ParseError::TrailingInput(token));
+                Stmt::Error(span)
+            }
+            _ => parser.recover(),
+        });
+    }
+    (statements, parser.errors.unwrap_or_default())
+}
+
+/// Lexes `source` with `lexer` and parses it as a single expression, which
+/// must make up the whole input, with the default [`PrecedenceTable`].
+pub fn parse(lexer: &Lexer, source: &str) -> Result<Expr, ParseError> {
+    let mut tokens = TokenStream::new(lexer.lex(source)?);
+    let expr = parse_expression(&mut tokens, &PrecedenceTable::default())?;
+    match tokens.peek().filter(|_| !tokens.is_at_end()) {
+        Some(token) => Err(ParseError::TrailingInput(token.clone())),
+        None => Ok(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Assoc, LexError, Lexer, Operator, Span, Token, TokenKind, TokenStream, Type, Unexpected,
+        eval::{Environment, EvalError, Value},
+        parser::{
+            Expr, Literal, MAX_DEPTH, ParseError, PrecedenceTable, Stmt, parse, parse_expression,
+            parse_program, parse_program_all_errors,
+        },
+    };
+
+    fn number(n: i32, start: usize) -> Box<Expr> {
+        Box::new(Expr::Literal(
+            Literal::Number(n),
+            Span::new(start, start + 1),
+        ))
+    }
+
+    #[test]
+    fn expressions() {
+        let lexer = Lexer::default();
+        assert_eq!(
+            parse(&lexer, "1 + 2 * 3 - 4"),
+            Ok(Expr::Binary {
+                op: Operator::Minus,
+                left: Box::new(Expr::Binary {
+                    op: Operator::Plus,
+                    left: number(1, 0),
+                    right: Box::new(Expr::Binary {
+                        op: Operator::Star,
+                        left: number(2, 4),
+                        right: number(3, 8),
+                        span: Span::new(4, 9),
+                    }),
+                    span: Span::new(0, 9),
+                }),
+                right: number(4, 12),
+                span: Span::new(0, 13),
+            })
+        );
+        assert_eq!(
+            parse(&lexer, "-f(x, (1))"),
+            Ok(Expr::Unary {
+                op: Operator::Minus,
+                operand: Box::new(Expr::Call {
+                    callee: Box::new(Expr::Identifier("f".to_string(), Span::new(1, 2))),
+                    args: vec![
+                        Expr::Identifier("x".to_string(), Span::new(3, 4)),
+                        Expr::Grouping(number(1, 7), Span::new(6, 9)),
+                    ],
+                    span: Span::new(1, 10),
+                }),
+                span: Span::new(0, 10),
+            })
+        );
+        assert_eq!(
+            parse(&lexer, "!true || null == \"s\"").map(|e| e.span()),
+            Ok(Span::new(0, 20))
+        );
+        assert_eq!(
+            parse(&lexer, "(1 + )"),
+            Err(ParseError::ExpectedExpression(Some(Token::with_span(
+                Type::RightParen,
+                Span::new(5, 6)
+            ))))
+        );
+        assert_eq!(
+            parse(&lexer, "f(1"),
+            Err(ParseError::Unexpected(Unexpected {
+                expected: TokenKind::RightParen,
+                found: None,
+            }))
+        );
+        assert_eq!(
+            parse(&lexer, "1 2"),
+            Err(ParseError::TrailingInput(Token::with_span(
+                Type::Number(2),
+                Span::new(2, 3)
+            )))
+        );
+        assert_eq!(
+            parse(&lexer, "\"open"),
+            Err(ParseError::Lex(LexError::UnterminatedString(0)))
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let lexer = Lexer::default();
+        let program = parse_program(
+            &lexer,
+            "define x = 1;\nif (x) { f(x); } else if (y) {} else { { 2; } }",
+        )
+        .unwrap();
+        assert_eq!(
+            program[0],
+            Stmt::Define {
+                name: "x".to_string(),
+                value: *number(1, 11),
+                span: Span::new(0, 13),
+            }
+        );
+        let Stmt::If {
+            condition,
+            then_branch,
+            else_branch: Some(else_branch),
+            span,
+        } = &program[1]
+        else {
+            panic!("expected an if statement, got {:?}", program[1]);
+        };
+        assert_eq!(
+            *condition,
+            Expr::Identifier("x".to_string(), Span::new(18, 19))
+        );
+        assert_eq!(then_branch.span(), Span::new(21, 30));
+        assert!(matches!(**else_branch, Stmt::If { span, .. } if span == Span::new(36, 61)));
+        assert_eq!(*span, Span::new(14, 61));
+        assert_eq!(program.len(), 2);
+
+        assert_eq!(
+            parse_program(&lexer, "define = 1;"),
+            Err(ParseError::Unexpected(Unexpected {
+                expected: TokenKind::Identifier,
+                found: Some(Token::with_span(
+                    Type::Operator(Operator::Equals),
+                    Span::new(7, 8)
+                )),
+            }))
+        );
+        assert_eq!(
+            parse_program(&lexer, "{ x; "),
+            Err(ParseError::Unexpected(Unexpected {
+                expected: TokenKind::RightBrace,
+                found: None,
+            }))
+        );
+        assert_eq!(
+            parse_program(&lexer, "x }"),
+            Err(ParseError::Unexpected(Unexpected {
+                expected: TokenKind::Semicolon,
+                found: Some(Token::with_span(Type::RightBrace, Span::new(2, 3))),
+            }))
+        );
+        assert_eq!(
+            parse_program(&lexer, "x; }"),
+            Err(ParseError::TrailingInput(Token::with_span(
+                Type::RightBrace,
+                Span::new(3, 4)
+            )))
+        );
+
+        let eof = Lexer::builder().eof(true).build();
+        assert_eq!(parse_program(&eof, "x; y;"), parse_program(&lexer, "x; y;"));
+        assert_eq!(parse(&eof, "1 + 2"), parse(&lexer, "1 + 2"));
+        assert!(matches!(
+            parse(&eof, "1 2"),
+            Err(ParseError::TrailingInput(_))
+        ));
+    }
+
+    #[test]
+    fn nesting() {
+        let lexer = Lexer::default();
+        let parens = "(".repeat(100_000) + "1" + &")".repeat(100_000);
+        assert_eq!(
+            parse(&lexer, &parens),
+            Err(ParseError::NestingTooDeep(Some(Token::with_span(
+                Type::LeftParen,
+                Span::new(MAX_DEPTH + 1, MAX_DEPTH + 2)
+            ))))
+        );
+        let negations = "-".repeat(100_000) + "x";
+        assert_eq!(parse(&lexer, &negations).unwrap_err().code(), "P004");
+        let sum = "1".to_string() + &" + 1".repeat(100_000);
+        assert_eq!(
+            parse(&lexer, &sum).unwrap_err().to_string(),
+            format!(
+                "nesting too deep at {0}..{1} [P004]",
+                4 * MAX_DEPTH,
+                4 * MAX_DEPTH + 1
+            )
+        );
+
+        // The deepest trees the parser accepts still evaluate.
+        let shapes: [&dyn Fn(usize) -> String; 6] = [
+            &|n| "(".repeat(n) + "x" + &")".repeat(n) + ";",
+            &|n| "-".repeat(n) + "x;",
+            &|n| "x".to_string() + &" + x".repeat(n) + ";",
+            &|n| "{".repeat(n) + "x;" + &"}".repeat(n),
+            &|n| "if (true) {".repeat(n) + &"}".repeat(n),
+            &|n| "if (false) {} else ".repeat(n) + "{}",
+        ];
+        for shape in shapes {
+            let deepest = (1..)
+                .map(shape)
+                .take_while(|source| parse_program(&lexer, source).is_ok())
+                .last()
+                .unwrap();
+            assert!(matches!(
+                parse_program(&lexer, &(shape(MAX_DEPTH))),
+                Err(ParseError::NestingTooDeep(_))
+            ));
+            let mut env = Environment::new();
+            env.define("x", Value::Number(1));
+            assert_eq!(env.run(&parse_program(&lexer, &deepest).unwrap()), Ok(()));
+        }
+        let mut deep = Expr::Identifier("x".to_string(), Span::new(0, 1));
+        for _ in 0..=MAX_DEPTH {
+            deep = Expr::Grouping(Box::new(deep), Span::new(0, 1));
+        }
+        assert_eq!(
+            Environment::new().eval(&deep),
+            Err(EvalError::NestingTooDeep(Span::new(0, 1)))
+        );
+    }
+
+    #[test]
+    fn recovery() {
+        let lexer = Lexer::default();
+        let source = "define = 1;\nx = (1 +;\nif (y +) { z; } else { w; }\n{ a; b }\nok;\n} \
+                      define last = \"open";
+        let (program, errors) = parse_program_all_errors(&lexer, source);
+        assert_eq!(
+            program.iter().map(Stmt::to_sexpr).collect::<Vec<String>>(),
+            vec![
+                "(error)",
+                "(error)",
+                "(if (+ y (error)) (block (expr z)) (block (expr w)))",
+                "(block (expr a) (error))",
+                "(expr ok)",
+                "(error)",
+                "(error)",
+            ]
+        );
+        assert_eq!(program[0].span(), Span::new(0, 11));
+        assert_eq!(program[1].span(), Span::new(12, 21));
+        assert_eq!(program[2].span(), Span::new(22, 49));
+        assert_eq!(
+            errors.iter().map(ParseError::code).collect::<Vec<&str>>(),
+            vec!["L001", "P002", "P002", "P001", "P002", "P003", "P001"]
+        );
+        assert_eq!(errors[0], ParseError::Lex(LexError::UnterminatedString(79)));
+        assert_eq!(errors[6], ParseError::ExpectedExpression(None));
+
+        let valid = "define x = 1; if (x) { x; }";
+        let (program, errors) = parse_program_all_errors(&lexer, valid);
+        assert_eq!(Ok(program), parse_program(&lexer, valid));
+        assert!(errors.is_empty());
+        let eof = Lexer::builder().eof(true).build();
+        assert_eq!(parse_program_all_errors(&eof, "x; )").0.len(), 2);
+
+        // A missing expression leaves an Expr::Error, and the statement
+        // around it fails only where the parser cannot carry on.
+        let (program, errors) = parse_program_all_errors(&lexer, "-;\nf(1, );\n(1 +;");
+        assert_eq!(
+            program.iter().map(Stmt::to_sexpr).collect::<Vec<String>>(),
+            vec!["(expr (- (error)))", "(expr (call f 1 (error)))", "(error)"]
+        );
+        assert_eq!(
+            program[0].to_tree(),
+            "Expr 0..2\n  Unary - 0..1\n    Error 1..1\n"
+        );
+        assert_eq!(
+            errors.iter().map(ParseError::code).collect::<Vec<&str>>(),
+            vec!["P001", "P001", "P001"]
+        );
+        assert_eq!(
+            Environment::new().execute(&program[0]),
+            Err(EvalError::InvalidExpression(Span::new(1, 1)))
+        );
+        let (_, errors) = parse_program_all_errors(&lexer, "define x = -");
+        assert_eq!(errors, vec![ParseError::ExpectedExpression(None)]);
+        assert_eq!(
+            parse(&lexer, "-;"),
+            Err(ParseError::ExpectedExpression(Some(Token::with_span(
+                Type::Semicolon,
+                Span::new(1, 2)
+            ))))
+        );
+    }
+
+    #[test]
+    fn precedence_table() {
+        let lexer = Lexer::builder().operator("|>").operator("<>").build();
+        let sexpr = |source: &str, table: &PrecedenceTable| {
+            let mut tokens = TokenStream::new(lexer.lex(source).unwrap());
+            parse_expression(&mut tokens, table).unwrap().to_sexpr()
+        };
+        let default = PrecedenceTable::default();
+        assert_eq!(sexpr("a |> b + c", &default), "(|> a (+ b c))");
+        assert_eq!(sexpr("a - b - c", &default), "(- (- a b) c)");
+        assert_eq!(
+            default.get(&Operator::Star),
+            Some((Operator::Star.precedence(), Assoc::Left))
+        );
+        assert_eq!(default.get(&Operator::Equals), None);
+
+        let mut table = PrecedenceTable::default();
+        table.insert(Operator::Custom("|>".to_string()), 20, Assoc::Right);
+        table.insert(Operator::Minus, 12, Assoc::Right);
+        assert_eq!(sexpr("a |> b + c", &table), "(+ (|> a b) c)");
+        assert_eq!(sexpr("a |> b |> c", &table), "(|> a (|> b c))");
+        assert_eq!(sexpr("a - b - c", &table), "(- a (- b c))");
+        assert_eq!(sexpr("a <> b * c", &table), "(<> a (* b c))");
+        assert_eq!(sexpr("a * b <> c", &table), "(<> (* a b) c)");
+        table.insert(Operator::Star, u8::MAX, Assoc::Left);
+        assert_eq!(sexpr("a * b * c", &table), "(* (* a b) c)");
+
+        table.set_custom(None);
+        table.remove(&Operator::Plus);
+        let mut tokens = TokenStream::new(lexer.lex("a <> b + c").unwrap());
+        assert_eq!(
+            parse_expression(&mut tokens, &table).unwrap().to_sexpr(),
+            "a"
+        );
+        assert_eq!(tokens.peek().map(|t| t.span), Some(Span::new(2, 4)));
+        assert_eq!(sexpr("a |> b", &table), "(|> a b)");
+        assert_eq!(sexpr("a + b", &PrecedenceTable::empty()), "a");
+    }
+
+    #[test]
+    fn printing() {
+        let lexer = Lexer::default();
+        assert_eq!(
+            parse(&lexer, "1 + 2 * -3").unwrap().to_sexpr(),
+            "(+ 1 (* 2 (- 3)))"
+        );
+        assert_eq!(
+            parse(&lexer, "(a || b) && f(\"q\t\", null)")
+                .unwrap()
+                .to_sexpr(),
+            "(&& (group (|| a b)) (call f \"q\\t\" null))"
+        );
+        let program = parse_program(&lexer, "define x = f(1); if (x) { x; } else {}").unwrap();
+        assert_eq!(
+            program.iter().map(Stmt::to_sexpr).collect::<Vec<String>>(),
+            vec!["(define x (call f 1))", "(if x (block (expr x)) (block))"]
+        );
+        assert_eq!(
+            program[0].to_tree(),
+            concat!(
+                "Define x 0..16\n",
+                "  Call 11..15\n",
+                "    Identifier f 11..12\n",
+                "    Literal 1 13..14\n",
+            )
+        );
+    }
+}
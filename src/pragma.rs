@@ -0,0 +1,156 @@
+//! Directives written in comments, such as `// lexer: ignore-next-line`, for
+//! build tools that honor per-file or per-line settings.
+//!
+//! A comment is a pragma when its text, after the comment markers and any
+//! whitespace, starts with `lexer:` or with the word `pragma`. The first word
+//! after that is the pragma's name and the rest its argument:
+//!
+//! ```text
+//! // lexer: ignore-next-line        name "ignore-next-line", argument ""
+//! //! pragma foo                    name "foo", argument ""
+//! /* pragma max-width 100 */        name "max-width", argument "100"
+//! /// lexer: deprecated use g       name "deprecated", argument "use g"
+//! ```
+//!
+//! Block comments, and with [`crate::LexerOptions::line_comments`] `//`,
+//! `//!` and `///` line comments, can hold pragmas, as can doc comments with
+//! [`crate::LexerOptions::doc_comments`]. Without `line_comments`, `//` is
+//! two `/` operators and holds nothing. What a pragma means is up to the tool reading
+//! it; its span tells whether it sits on a line of its own or at the top of
+//! the file.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{Cursor, LexOutput, Lexer, Span, Token, Type, filter::Pipeline};
+
+/// The markers that start the text of a pragma.
+const PREFIXES: [&str; 2] = ["lexer:", "pragma "];
+
+/// A directive found in a comment.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pragma {
+    pub name: String,
+    /// The text after the name, trimmed; empty when there is none.
+    pub argument: String,
+    /// The whole comment holding the pragma.
+    pub span: Span,
+}
+
+impl Pragma {
+    /// The pragma in a comment with the text `text`, markers stripped.
+    fn parse(text: &str, span: Span) -> Option<Pragma> {
+        let text = text.trim_start();
+        let rest = PREFIXES
+            .iter()
+            .find_map(|prefix| text.strip_prefix(prefix))?
+            .trim();
+        let (name, argument) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        match name.is_empty() {
+            true => None,
+            false => Some(Pragma {
+                name: String::from(name),
+                argument: String::from(argument.trim()),
+                span,
+            }),
+        }
+    }
+}
+
+/// The pragmas in the comments among `tokens`, in order. Block comments are
+/// only tokens when trivia is kept; see [`Lexer::lex_with_pragmas`] for a
+/// lexer without it.
+pub fn pragmas(tokens: &[Token]) -> Vec<Pragma> {
+    tokens
+        .iter()
+        .filter_map(|token| match &token.token_type {
+            Type::Comment(text) => match text.strip_prefix("//") {
+                Some(body) => {
+                    let body = body.strip_prefix(['!', '/']).unwrap_or(body);
+                    Pragma::parse(body, token.span)
+                }
+                None => {
+                    let body = text.strip_prefix("/*").unwrap_or(text);
+                    Pragma::parse(body.strip_suffix("*/").unwrap_or(body), token.span)
+                }
+            },
+            Type::DocComment(text) => Pragma::parse(text, token.span),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Lexer {
+    /// Like [`Lexer::lex_with_diagnostics`], also returning the pragmas in
+    /// the comments of `s`, whether or not the lexer keeps trivia.
+    pub fn lex_with_pragmas(&self, s: &str) -> (LexOutput, Vec<Pragma>) {
+        let output = self.lex_with_diagnostics(s);
+        if self.options.trivia && self.filters.is_empty() {
+            let found = pragmas(&output.tokens);
+            return (output, found);
+        }
+        // Filters may drop comments, so they are found in a second, raw lex.
+        let mut options = self.options.clone();
+        options.trivia = true;
+        let lexer = Lexer {
+            options,
+            filters: Pipeline::new(),
+            modes: self.modes.clone(),
+        };
+        let mut raw = LexOutput::default();
+        lexer.lex_raw(Cursor::new(s), &mut raw);
+        (output, pragmas(&raw.tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer, Span,
+        filter::InsertSemicolons,
+        pragma::{Pragma, pragmas},
+    };
+
+    #[test]
+    fn lex_with_pragmas() {
+        let source = "/* lexer: ignore-next-line */\nx = 1\n/*pragma  max-width 100 */ \
+                      y\n/* lexer: */ /* not a pragma */ /* pragmatic */\n/// lexer: doc";
+        let pragma = |name: &str, argument: &str, span| Pragma {
+            name: name.to_string(),
+            argument: argument.to_string(),
+            span,
+        };
+        let expected = vec![
+            pragma("ignore-next-line", "", Span::new(0, 29)),
+            pragma("max-width", "100", Span::new(36, 62)),
+        ];
+        let (output, found) = Lexer::default().lex_with_pragmas(source);
+        assert_eq!(found, expected);
+        assert!(output.errors.is_empty());
+        assert!(output.tokens.iter().all(|t| !t.is_trivia()));
+
+        let lossless = Lexer::builder().trivia(true).build();
+        assert_eq!(pragmas(&lossless.lex(source).unwrap()), expected);
+        let filtered = Lexer::builder()
+            .filter(InsertSemicolons)
+            .doc_comments(true)
+            .build();
+        let found = filtered.lex_with_pragmas(source).1;
+        assert_eq!(found[..2], expected);
+        assert_eq!(found[2], pragma("doc", "", Span::new(113, 127)));
+
+        let source =
+            "//! pragma foo\n// lexer: ignore-next-line\nx = 1 // not a pragma\n/// lexer: max 2";
+        let lexer = Lexer::builder().line_comments(true).build();
+        let (output, found) = lexer.lex_with_pragmas(source);
+        assert_eq!(output.tokens.len(), 3);
+        assert_eq!(
+            found,
+            vec![
+                pragma("foo", "", Span::new(0, 14)),
+                pragma("ignore-next-line", "", Span::new(15, 41)),
+                pragma("max", "2", Span::new(64, 80)),
+            ]
+        );
+        assert!(Lexer::default().lex_with_pragmas(source).1.is_empty());
+    }
+}
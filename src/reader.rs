@@ -0,0 +1,431 @@
+//! Lexing input that arrives in pieces (files, sockets, process pipes,
+//! decompression streams, the chunks of a rope) and is too large to gather
+//! into one string first.
+//!
+//! A [`CharSource`] hands out the input a piece at a time. Every iterator of
+//! `char`s is one, and [`Lexer::lex_reader`] decodes an [`io::Read`] as
+//! UTF-8, carrying a sequence split across two reads over to the next one. A
+//! token is only taken once the line after it is complete, since no rule
+//! looks past the end of a line to decide a token; consumed lines are dropped
+//! from the buffer as lexing moves on. A token that is already too long for
+//! [`crate::LexerOptions::max_token_len`] or
+//! [`crate::LexerOptions::max_string_len`] fails as soon as that much of it
+//! has been read, so that a hostile input cannot make the buffer grow without
+//! bound.
+
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use crate::{
+    Cursor, LexError, LexOutput, Lexer, LexerOptions, Span, Token, Type, lex_preamble, lex_step,
+    token_limit_reached,
+};
+
+const CHUNK: usize = 1 << 13;
+
+/// Input for [`Lexer::lex_source`], produced a piece at a time.
+pub trait CharSource {
+    /// Appends the next piece of input to `text`, returning its length in
+    /// bytes; 0 means the input is exhausted.
+    fn fill(&mut self, text: &mut String) -> io::Result<usize>;
+}
+
+impl<I: Iterator<Item = char>> CharSource for I {
+    fn fill(&mut self, text: &mut String) -> io::Result<usize> {
+        let len = text.len();
+        text.extend(self.take(CHUNK));
+        Ok(text.len() - len)
+    }
+}
+
+/// Decodes a reader as UTF-8.
+struct Utf8Source<R> {
+    reader: R,
+    /// The bytes of a UTF-8 sequence cut off by the last read.
+    partial: Vec<u8>,
+}
+
+impl<R: Read> CharSource for Utf8Source<R> {
+    fn fill(&mut self, text: &mut String) -> io::Result<usize> {
+        loop {
+            let mut bytes = std::mem::take(&mut self.partial);
+            let start = bytes.len();
+            bytes.resize(start + CHUNK, 0);
+            let read = loop {
+                match self.reader.read(&mut bytes[start..]) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            bytes.truncate(start + read);
+            let valid = match std::str::from_utf8(&bytes) {
+                Ok(text) => text.len(),
+                Err(e) if e.error_len().is_none() && read > 0 => e.valid_up_to(),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            };
+            self.partial = bytes.split_off(valid);
+            text.push_str(std::str::from_utf8(&bytes).unwrap_or_default());
+            // A read may end inside the first character, decoding nothing.
+            if valid > 0 || read == 0 {
+                return Ok(valid);
+            }
+        }
+    }
+}
+
+/// Why [`Lexer::lex_source`] or [`Lexer::lex_reader`] failed.
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading failed, or the input of [`Lexer::lex_reader`] is not valid
+    /// UTF-8.
+    Io(io::Error),
+    Lex(LexError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "{}", e),
+            ReadError::Lex(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Lex(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+/// Input read so far that has not been lexed yet, starting at the beginning
+/// of the line being lexed.
+struct Buffer<S> {
+    source: S,
+    text: String,
+    /// The offset of `text` in the whole input.
+    base: usize,
+    eof: bool,
+}
+
+impl<S: CharSource> Buffer<S> {
+    /// Reads the next piece of input, setting `eof` at the end of it.
+    fn fill(&mut self) -> io::Result<()> {
+        self.eof = self.source.fill(&mut self.text)? == 0;
+        Ok(())
+    }
+
+    /// Drops the lines before `offset` in `text`, returning where `offset`
+    /// ends up.
+    fn consume(&mut self, offset: usize) -> usize {
+        let line_start = self.text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        self.text.drain(..line_start);
+        self.base += line_start;
+        offset - line_start
+    }
+
+    /// Whether the input read so far may start with a shebang line, which
+    /// has to be read whole before lexing starts.
+    fn may_start_shebang(&self, options: &LexerOptions) -> bool {
+        let text = self.text.strip_prefix('\u{feff}').unwrap_or(&self.text);
+        options.shebang && (text.starts_with("#!") || "#!".starts_with(text))
+    }
+
+    /// Where a step may end for its tokens to be final: before the last line
+    /// break read, or anywhere once the input is exhausted.
+    fn settled(&self) -> usize {
+        match self.eof {
+            true => self.text.len() + 1,
+            false => self.text.rfind('\n').unwrap_or(0),
+        }
+    }
+}
+
+fn shift(span: Span, base: usize) -> Span {
+    Span::new(span.start + base, span.end + base)
+}
+
+impl Lexer {
+    /// Like [`Lexer::lex`], reading the input from `reader`.
+    ///
+    /// Reads are buffered, so `reader` need not be. Tokens are not passed
+    /// through the lexer's filters, which need the whole source.
+    pub fn lex_reader<R: Read>(&self, reader: R) -> Result<Vec<Token>, ReadError> {
+        self.lex_source(Utf8Source {
+            reader,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Like [`Lexer::lex`], taking the input from an iterator of characters.
+    pub fn lex_chars(&self, chars: impl IntoIterator<Item = char>) -> Result<Vec<Token>, LexError> {
+        match self.lex_source(chars.into_iter()) {
+            Ok(tokens) => Ok(tokens),
+            Err(ReadError::Lex(e)) => Err(e),
+            Err(ReadError::Io(_)) => unreachable!("iterators of chars cannot fail"),
+        }
+    }
+
+    /// Like [`Lexer::lex`], taking the input from `source` a piece at a time;
+    /// see [`Lexer::lex_reader`].
+    pub fn lex_source(&self, source: impl CharSource) -> Result<Vec<Token>, ReadError> {
+        let options = self.options();
+        let mut buffer = Buffer {
+            source,
+            text: String::new(),
+            base: 0,
+            eof: false,
+        };
+        while !buffer.eof && !buffer.text.contains('\n') && buffer.may_start_shebang(options) {
+            buffer.fill()?;
+        }
+        let mut tokens = Vec::new();
+        let mut offset = {
+            let mut chars = Cursor::new(&buffer.text);
+            lex_preamble(&mut chars, options, &mut tokens);
+            chars.offset
+        };
+        let mut probe = offset;
+        loop {
+            let mut step = lex_settled(&buffer, offset, options);
+            token_limit_reached(options, tokens.len(), &mut step.out);
+            tokens.extend(step.out.tokens.into_iter().map(|mut token| {
+                token.span = shift(token.span, buffer.base);
+                token
+            }));
+            if let Some(error) = step.out.errors.into_iter().next() {
+                return Err(ReadError::Lex(shift_error(error, buffer.base)));
+            }
+            if buffer.eof {
+                if options.eof {
+                    let end = buffer.base + buffer.text.len();
+                    tokens.push(Token::with_span(Type::Eof, Span::new(end, end)));
+                }
+                return Ok(tokens);
+            }
+            probe = match probe_limits(&buffer, probe.max(step.offset), options) {
+                Ok(probe) => probe,
+                Err(error) => return Err(ReadError::Lex(shift_error(error, buffer.base))),
+            };
+            let base = buffer.base;
+            offset = buffer.consume(step.offset);
+            probe -= buffer.base - base;
+            buffer.fill()?;
+        }
+    }
+}
+
+fn shift_error(error: LexError, base: usize) -> LexError {
+    match error {
+        LexError::UnterminatedString(at) => LexError::UnterminatedString(at + base),
+        LexError::UnterminatedComment(at) => LexError::UnterminatedComment(at + base),
+        LexError::UnterminatedAttribute(at) => LexError::UnterminatedAttribute(at + base),
+        LexError::NestingTooDeep(at) => LexError::NestingTooDeep(at + base),
+        LexError::UnknownCharacter(c, at) => LexError::UnknownCharacter(c, at + base),
+        LexError::LimitExceeded(limit, at) => LexError::LimitExceeded(limit, at + base),
+        LexError::NumberTooLarge(span) => {
+            LexError::NumberTooLarge(Span::new(span.start + base, span.end + base))
+        }
+        LexError::MixedIndentation(_) | LexError::UnalignedDedent(_) => {
+            unreachable!("layout is not applied to read input")
+        }
+        LexError::InvalidUtf8(_) => unreachable!("read input is decoded before lexing"),
+    }
+}
+
+/// Lexes ahead of the line being waited for, from `probe`, to fail on a
+/// token over a limit without reading the rest of it. A token over a limit
+/// stays over it however its line ends, and a step that ends far enough
+/// before the end of `buffer` ends there in the whole input too, so the
+/// probe moves on to it. Returns where the probe stopped.
+fn probe_limits<S: CharSource>(
+    buffer: &Buffer<S>,
+    probe: usize,
+    options: &LexerOptions,
+) -> Result<usize, LexError> {
+    if options.max_token_len.is_none()
+        && options.max_string_len.is_none()
+        && options.max_nesting.is_none()
+    {
+        return Ok(probe);
+    }
+    // The furthest any rule looks ahead of its start, or past its end.
+    let lookahead = options
+        .operators
+        .iter()
+        .map(String::len)
+        .chain(
+            options
+                .radix_prefixes
+                .iter()
+                .map(|(prefix, _)| prefix.len()),
+        )
+        .fold(4, usize::max);
+    let mut chars = Cursor::new(&buffer.text);
+    chars.offset = probe;
+    let mut step = LexOutput::default();
+    loop {
+        let start = chars.offset;
+        let end = lex_step(&mut chars, options, &mut step).map(|_| chars.offset);
+        match (step.errors.first(), end) {
+            (Some(LexError::LimitExceeded(..)), _) => return Err(step.errors.swap_remove(0)),
+            // Other errors wait for the rest of the line, like the tokens.
+            (None, Some(end)) if end + lookahead <= buffer.text.len() => {
+                step = LexOutput::default()
+            }
+            _ => return Ok(start),
+        }
+    }
+}
+
+/// What [`lex_settled`] lexed, and where it stopped.
+struct Settled {
+    out: LexOutput,
+    offset: usize,
+}
+
+/// Lexes the steps of `buffer` from `offset` that end before
+/// [`Buffer::settled`].
+fn lex_settled<S: CharSource>(
+    buffer: &Buffer<S>,
+    offset: usize,
+    options: &LexerOptions,
+) -> Settled {
+    let settled = buffer.settled();
+    let mut chars = Cursor::new(&buffer.text);
+    chars.offset = offset;
+    let mut out = LexOutput::default();
+    let mut step = LexOutput::default();
+    while out.errors.is_empty() {
+        let start = chars.offset;
+        if lex_step(&mut chars, options, &mut step).is_none() || chars.offset >= settled {
+            chars.offset = start;
+            break;
+        }
+        out.tokens.append(&mut step.tokens);
+        out.errors.append(&mut step.errors);
+    }
+    Settled {
+        out,
+        offset: chars.offset,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use crate::{CharSource, LexError, Lexer, Limit, ReadError, lex_reader};
+
+    /// Hands out its input a few bytes at a time.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.1.min(self.0.len()).min(buf.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn lex_reader_matches_lex() {
+        let lexer = Lexer::builder().trivia(true).labels(true).eof(true).build();
+        let source = "\u{feff}#!run\nstart: x = \"é😀\" /* a\n\n b */ y::z\n  loop: 12\n";
+        for step in 1..8 {
+            let tokens = lexer.lex_reader(Trickle(source.as_bytes(), step)).unwrap();
+            assert_eq!(tokens, lexer.lex(source).unwrap());
+        }
+        assert_eq!(
+            lex_reader(source.as_bytes()).unwrap(),
+            Lexer::default().lex(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn lex_reader_limits() {
+        let lexer = Lexer::builder()
+            .trivia(true)
+            .interpolation(true)
+            .operator("=>>")
+            .max_token_len(6)
+            .max_string_len(4)
+            .max_nesting(2)
+            .max_tokens(12)
+            .build();
+        let sources = [
+            "a = \"abc\" /* /* */ */\nbb =>> \"${x}\"\n",
+            "x \"abcde\" y\n",
+            "x \"ab\ny /* /* /* */ */ */ z\n",
+            "abcdefg \"open\n",
+            "\"${\"${\"\"}\"}\" abc\n",
+            "a b c d e f g h\ni j k l m n",
+        ];
+        for source in sources {
+            for step in 1..8 {
+                let read = lexer.lex_reader(Trickle(source.as_bytes(), step));
+                match (read, lexer.lex(source)) {
+                    (Ok(tokens), Ok(expected)) => assert_eq!(tokens, expected),
+                    (Err(ReadError::Lex(error)), Err(expected)) => assert_eq!(error, expected),
+                    (read, expected) => panic!("{:?}: {:?} != {:?}", source, read, expected),
+                }
+            }
+        }
+    }
+
+    /// A source made of string pieces, as a rope hands out its chunks.
+    struct Chunks<'a>(std::slice::Iter<'a, &'a str>);
+
+    impl CharSource for Chunks<'_> {
+        fn fill(&mut self, text: &mut String) -> io::Result<usize> {
+            let chunk = self.0.next().copied().unwrap_or_default();
+            text.push_str(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn lex_chars_and_sources() {
+        let lexer = Lexer::builder().labels(true).eof(true).build();
+        let source = "a: \"x\ny\" /* é\n */ b:";
+        let expected = lexer.lex(source);
+        assert_eq!(lexer.lex_chars(source.chars()), expected);
+        let chunks = ["a", ": \"x\n", "y\" /", "* é\n */", " b:", "\n"];
+        assert_eq!(lexer.lex_source(Chunks(chunks.iter())).ok(), expected.ok());
+    }
+
+    #[test]
+    fn lex_reader_errors() {
+        let source = "x\ny \"z\n";
+        match lex_reader(Trickle(source.as_bytes(), 1)) {
+            Err(ReadError::Lex(error)) => assert_eq!(error, LexError::UnterminatedString(4)),
+            other => panic!("unexpected {:?}", other),
+        }
+        let invalid: &[u8] = b"x\n\xffy";
+        assert!(matches!(lex_reader(invalid), Err(ReadError::Io(_))));
+        let truncated: &[u8] = "é".as_bytes();
+        assert!(matches!(lex_reader(&truncated[..1]), Err(ReadError::Io(_))));
+
+        // An overlong string fails before the rest of it is read, here never.
+        let endless = b"x \"".chain(io::repeat(b'a'));
+        let lexer = Lexer::builder().max_string_len(1 << 16).build();
+        assert!(matches!(
+            lexer.lex_reader(endless),
+            Err(ReadError::Lex(LexError::LimitExceeded(
+                Limit::StringLength,
+                2
+            )))
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Incremental relexing for editors, which re-scan only the part of a buffer
+//! an edit can have changed.
+//!
+//! The lexer carries no state from one token to the next, so it can resume at
+//! the end of any token. [`Lexer::relex`] resumes at the last token ending
+//! shortly before the edit and lexes the new text until it reaches a position that
+//! started a token before the edit; from there on, the old tokens are reused
+//! with their spans shifted.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{Cursor, LexOutput, Lexer, Span, Token, Type, lex_preamble, lex_step};
+
+/// A replacement of `span` in a text by `text`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(span: Span, text: impl Into<String>) -> Self {
+        TextEdit {
+            span,
+            text: text.into(),
+        }
+    }
+
+    /// The text after applying the edit to `source`.
+    pub fn apply(&self, source: &str) -> String {
+        let mut out = source.to_string();
+        out.replace_range(self.span.start..self.span.end, &self.text);
+        out
+    }
+}
+
+/// How many bytes past the end of a token the lexer may look to decide it,
+/// as for a label, which must not be followed by `::`.
+const LOOKAHEAD: usize = 2;
+
+impl Lexer {
+    /// Updates `old_tokens`, the tokens of a text before `edit`, to the
+    /// tokens of `source`, the text after it. The result equals the tokens of
+    /// [`Lexer::lex_with_diagnostics`] on `source`.
+    ///
+    /// Lexers with filters, layout or an error or token limit depend on the
+    /// whole input, and with interpolation, attributes or modes the tokens of
+    /// a string, attribute or mode may line up with old ones midway through
+    /// it, so these lex `source` from scratch.
+    pub fn relex(&self, source: &str, old_tokens: &[Token], edit: &TextEdit) -> Vec<Token> {
+        let options = self.options();
+        if !self.filters.is_empty()
+            || options.layout
+            || options.max_errors.is_some()
+            || options.max_tokens.is_some()
+            || options.interpolation
+            || options.attributes
+            || !self.modes.is_empty()
+        {
+            return self.lex_with_diagnostics(source).tokens;
+        }
+        // The first line may hold a byte order mark and shebang, which are
+        // only recognized when lexing from the start.
+        let kept = match source[..edit.span.start].find('\n') {
+            Some(newline) => {
+                let kept = old_tokens.partition_point(|t| t.span.end + LOOKAHEAD < edit.span.start);
+                match kept > 0 && old_tokens[kept - 1].span.end <= newline {
+                    true => 0,
+                    false => kept,
+                }
+            }
+            None => 0,
+        };
+        let mut tokens = old_tokens[..kept].to_vec();
+        let mut chars = Cursor::new(source);
+        chars.offset = kept.checked_sub(1).map_or(0, |i| old_tokens[i].span.end);
+        let inserted_end = edit.span.start + edit.text.len();
+        // Labels depend on what precedes them on their line, so with labels on
+        // the old tokens are only trusted again from the next line.
+        let sync_from = match options.labels {
+            true => source[inserted_end..]
+                .find('\n')
+                .map_or(source.len(), |i| inserted_end + i + 1),
+            false => inserted_end,
+        };
+        let mut out = LexOutput::default();
+        if chars.offset == 0 {
+            lex_preamble(&mut chars, options, &mut out.tokens);
+        }
+        loop {
+            tokens.append(&mut out.tokens);
+            if chars.offset >= sync_from {
+                let old_offset = chars.offset - inserted_end + edit.span.end;
+                let i = old_tokens.partition_point(|t| t.span.start < old_offset);
+                // A shebang token only exists at the start of input.
+                if old_tokens.get(i).is_some_and(|t| {
+                    t.span.start == old_offset && !matches!(t.token_type, Type::Shebang(_))
+                }) {
+                    tokens.extend(old_tokens[i..].iter().map(|token| {
+                        let start = token.span.start - edit.span.end + inserted_end;
+                        let end = token.span.end - edit.span.end + inserted_end;
+                        Token::with_span(token.token_type.clone(), Span::new(start, end))
+                    }));
+                    return tokens;
+                }
+            }
+            if lex_step(&mut chars, options, &mut out).is_none() {
+                if options.eof {
+                    tokens.push(Token::with_span(
+                        Type::Eof,
+                        Span::new(chars.offset, chars.offset),
+                    ));
+                }
+                return tokens;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Lexer, Span, TextEdit};
+
+    #[test]
+    fn relex() {
+        let lexers = [
+            Lexer::default(),
+            Lexer::builder().trivia(true).build(),
+            Lexer::builder().labels(true).build(),
+            Lexer::builder().eof(true).build(),
+        ];
+        let source = "#!run\ndefine x = 1; // c\n  a: y /* z */ \"s\"\nb: f(x,\n y)";
+        let edits = [
+            TextEdit::new(Span::new(0, 0), " "),
+            TextEdit::new(Span::new(13, 14), "12"),
+            TextEdit::new(Span::new(14, 14), "0"),
+            TextEdit::new(Span::new(19, 19), "/*"),
+            TextEdit::new(Span::new(20, 21), ""),
+            TextEdit::new(Span::new(25, 25), "q "),
+            TextEdit::new(Span::new(36, 38), ""),
+            TextEdit::new(Span::new(41, 41), "\"\n"),
+            TextEdit::new(Span::new(44, 44), "c "),
+            TextEdit::new(Span::new(53, 54), ""),
+            TextEdit::new(Span::new(55, 55), "+"),
+        ];
+        for lexer in &lexers {
+            let old_tokens = lexer.lex_with_diagnostics(source).tokens;
+            for edit in &edits {
+                let new_source = edit.apply(source);
+                assert_eq!(
+                    lexer.relex(&new_source, &old_tokens, edit),
+                    lexer.lex_with_diagnostics(&new_source).tokens,
+                    "{:?} on {:?}",
+                    edit,
+                    new_source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn relex_after_preamble() {
+        let lexer = Lexer::default();
+        let source = "\u{feff}#!run\nx y";
+        let old_tokens = lexer.lex_with_diagnostics(source).tokens;
+        let edit = TextEdit::new(Span::new(9, 10), "z");
+        let new_source = edit.apply(source);
+        assert_eq!(
+            lexer.relex(&new_source, &old_tokens, &edit),
+            lexer.lex_with_diagnostics(&new_source).tokens
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! An interactive read-lex-print loop, as used by the `lexer repl` command.
+//!
+//! Every line read is lexed and its tokens are printed one per line. Lines
+//! starting with `:` are commands (`:help` lists them), and `!!` or `!N`
+//! re-run the previous or the `N`th entry of the session history.
+//!
+//! [`check_script`] replays a `.lexrepl` script, a transcript of a session
+//! in which each input line starts with `> ` and is followed by the output
+//! it is expected to print:
+//!
+//! ```text
+//! # lines starting with `#` and blank lines are ignored
+//! > x + 1
+//!   Identifier     x            0..1
+//!   Plus           +            2..3
+//!   Number         1            4..5
+//! ```
+
+use std::io::{self, BufRead, Write};
+
+use crate::Lexer;
+
+const HELP: &str = "\
+:help       show this message
+:history    list the inputs entered so far
+:quit       leave the repl (end of input works too)
+!!          lex the previous input again
+!N          lex input number N from :history again";
+
+/// An input of a `.lexrepl` script whose output differed from the script.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScriptFailure {
+    /// The 1-based line number of the input in the script.
+    pub line: usize,
+    pub input: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+}
+
+/// The state of one REPL session.
+#[derive(Debug, Clone, Default)]
+pub struct Repl {
+    lexer: Lexer,
+    history: Vec<String>,
+}
+
+impl Repl {
+    pub fn new(lexer: Lexer) -> Self {
+        Repl {
+            lexer,
+            history: Vec::new(),
+        }
+    }
+
+    /// Every input lexed so far, oldest first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Handles one line of input, writing the response to `out`. Returns
+    /// `false` once the session should end.
+    pub fn handle(&mut self, line: &str, out: &mut impl Write) -> io::Result<bool> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let input = match line.trim() {
+            "" => return Ok(true),
+            ":quit" | ":q" => return Ok(false),
+            ":help" => {
+                writeln!(out, "{}", HELP)?;
+                return Ok(true);
+            }
+            ":history" => {
+                for (i, entry) in self.history.iter().enumerate() {
+                    writeln!(out, "{:>4}  {}", i + 1, entry)?;
+                }
+                return Ok(true);
+            }
+            "!!" => match self.history.last() {
+                Some(entry) => entry.clone(),
+                None => {
+                    writeln!(out, "error: history is empty")?;
+                    return Ok(true);
+                }
+            },
+            command
+                if command
+                    .strip_prefix('!')
+                    .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())) =>
+            {
+                let entry = command[1..]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| self.history.get(n.checked_sub(1)?));
+                match entry {
+                    Some(entry) => entry.clone(),
+                    None => {
+                        writeln!(out, "error: no history entry `{}`", &command[1..])?;
+                        return Ok(true);
+                    }
+                }
+            }
+            command if command.starts_with(':') => {
+                writeln!(out, "error: unknown command `{}` (try :help)", command)?;
+                return Ok(true);
+            }
+            _ => line.to_string(),
+        };
+        let output = self.lexer.lex_with_diagnostics(&input);
+        for token in &output.tokens {
+            writeln!(
+                out,
+                "  {:<14} {:<12} {}..{}",
+                format!("{:?}", token.kind()),
+                token.to_string(),
+                token.span.start,
+                token.span.end
+            )?;
+        }
+        for error in &output.errors {
+            writeln!(out, "  error: {:?}", error)?;
+        }
+        for warning in &output.warnings {
+            writeln!(out, "  warning: {:?}", warning)?;
+        }
+        self.history.push(input);
+        Ok(true)
+    }
+
+    /// Runs the loop until `:quit` or the end of `input`, printing a prompt
+    /// before every line.
+    pub fn run(&mut self, mut input: impl BufRead, mut out: impl Write) -> io::Result<()> {
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 || !self.handle(&line, &mut out)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Runs every input of `script` through a fresh session with `lexer`,
+/// returning the inputs whose output differs from the lines that follow
+/// them. Trailing whitespace is ignored on both sides.
+pub fn check_script(lexer: Lexer, script: &str) -> io::Result<Vec<ScriptFailure>> {
+    let mut repl = Repl::new(lexer);
+    let mut failures = Vec::new();
+    let mut lines = script.lines().enumerate().peekable();
+    while let Some((i, line)) = lines.next() {
+        let Some(input) = line.strip_prefix("> ") else {
+            continue;
+        };
+        let mut expected = Vec::new();
+        while let Some((_, next)) =
+            lines.next_if(|(_, l)| !l.starts_with("> ") && !l.starts_with('#'))
+        {
+            if !next.trim().is_empty() {
+                expected.push(next.trim_end().to_string());
+            }
+        }
+        let mut out = Vec::new();
+        if !repl.handle(input, &mut out)? {
+            break;
+        }
+        let actual = String::from_utf8_lossy(&out)
+            .lines()
+            .map(|l| l.trim_end().to_string())
+            .collect::<Vec<String>>();
+        if actual != expected {
+            failures.push(ScriptFailure {
+                line: i + 1,
+                input: input.to_string(),
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer,
+        repl::{Repl, ScriptFailure, check_script},
+    };
+
+    #[test]
+    fn repl() {
+        let mut repl = Repl::new(Lexer::default());
+        let mut out = Vec::new();
+        repl.run(
+            "x + 1\n:history\n!!\n!1\n!7\n!x\n\"oops\n:quit\nignored\n".as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<&str>>(),
+            vec![
+                ">   Identifier     x            0..1",
+                "  Plus           +            2..3",
+                "  Number         1            4..5",
+                ">    1  x + 1",
+                ">   Identifier     x            0..1",
+                "  Plus           +            2..3",
+                "  Number         1            4..5",
+                ">   Identifier     x            0..1",
+                "  Plus           +            2..3",
+                "  Number         1            4..5",
+                "> error: no history entry `7`",
+                ">   Bang           !            0..1",
+                "  Identifier     x            1..2",
+                ">   error: UnterminatedString(0)",
+                "> ",
+            ]
+        );
+        assert_eq!(repl.history(), ["x + 1", "x + 1", "x + 1", "!x", "\"oops"]);
+    }
+
+    #[test]
+    fn script() {
+        let script = "\
+# arithmetic
+> x + 1
+  Identifier     x            0..1
+  Plus           +            2..3
+  Number         1            4..5
+
+> :history
+   1  x + 1
+> 7
+  Number         8            0..1
+> :quit
+> never run
+";
+        assert_eq!(
+            check_script(Lexer::default(), script).unwrap(),
+            vec![ScriptFailure {
+                line: 9,
+                input: "7".to_string(),
+                expected: vec!["  Number         8            0..1".to_string()],
+                actual: vec!["  Number         7            0..1".to_string()],
+            }]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! Reproduction bundles: a lexer configuration and an input in one file, so
+//! a bug seen with a custom configuration can be replayed exactly.
+//!
+//! A bundle lists one option per line, then a `source` line; everything
+//! after that line is the input, byte for byte:
+//!
+//! ```text
+//! lexer-repro 1
+//! version 0.1.0
+//! trivia true
+//! whitespace only " \n"
+//! keyword "define" define
+//! keyword "unless" "unless"
+//! operator "<=>"
+//! radix_prefix "0x" 16
+//! long_token_warning 80
+//! source
+//! define x = 0x1F;
+//! ```
+//!
+//! Strings are quoted with Rust escapes. A `keyword` line maps a word to a
+//! built-in keyword by its spelling, or to a [`Keyword::Custom`] by a quoted
+//! string. Options left out keep their default, and `version` records the
+//! version of this crate that wrote the bundle. Filters are code rather than
+//! configuration, so bundles do not capture them.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+use crate::{Keyword, LexOutput, Lexer, LexerOptions, UnknownCharRule, WhitespaceRule};
+
+const HEADER: &str = "lexer-repro 1";
+
+const OPTIONS: [&str; 32] = [
+    "version",
+    "trivia",
+    "shebang",
+    "ascii_identifiers",
+    "case_insensitive_keywords",
+    "labels",
+    "directives",
+    "interpolation",
+    "attributes",
+    "decorators",
+    "multiline_strings",
+    "dedent_strings",
+    "layout",
+    "eof",
+    "doc_comments",
+    "line_comments",
+    "unusual_whitespace_warning",
+    "combining_warning",
+    "whitespace",
+    "unknown_chars",
+    "keywords",
+    "keyword",
+    "operator",
+    "radix_prefix",
+    "digit_separator",
+    "long_token_warning",
+    "keyword_call_warning",
+    "max_errors",
+    "max_token_len",
+    "max_string_len",
+    "max_nesting",
+    "max_tokens",
+];
+
+/// Why [`Bundle::parse`] rejected its input, with the 1-based line number.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BundleError {
+    /// The first line is not a bundle header.
+    MissingHeader,
+    /// The line does not start with a known option.
+    UnknownOption(usize),
+    /// The option is missing its value or has an invalid one.
+    InvalidValue(usize),
+    /// The bundle ends before its `source` line.
+    MissingSource,
+}
+
+/// A parsed reproduction bundle, in the format described in the
+/// [module docs](self).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Bundle {
+    /// The crate version that wrote the bundle, if recorded.
+    pub version: Option<String>,
+    pub options: LexerOptions,
+    pub source: String,
+}
+
+/// Writes a bundle replaying the lexing of `source` with `options`.
+///
+/// Keywords are written in sorted order, so the same configuration always
+/// produces the same bundle.
+pub fn bundle_repro(source: &str, options: &LexerOptions) -> String {
+    let defaults = LexerOptions::default();
+    let mut out = format!("{}\nversion {}\n", HEADER, env!("CARGO_PKG_VERSION"));
+    let flags = [
+        ("trivia", options.trivia),
+        ("shebang", options.shebang),
+        ("ascii_identifiers", options.ascii_identifiers),
+        (
+            "case_insensitive_keywords",
+            options.case_insensitive_keywords,
+        ),
+        ("labels", options.labels),
+        ("directives", options.directives),
+        ("interpolation", options.interpolation),
+        ("attributes", options.attributes),
+        ("decorators", options.decorators),
+        ("multiline_strings", options.multiline_strings),
+        ("dedent_strings", options.dedent_strings),
+        ("layout", options.layout),
+        ("eof", options.eof),
+        ("doc_comments", options.doc_comments),
+        ("line_comments", options.line_comments),
+        (
+            "unusual_whitespace_warning",
+            options.unusual_whitespace_warning,
+        ),
+        ("combining_warning", options.combining_warning),
+    ];
+    for (name, value) in flags {
+        out.push_str(&format!("{} {}\n", name, value));
+    }
+    if let WhitespaceRule::Only(set) = &options.whitespace {
+        out.push_str(&format!("whitespace only {:?}\n", String::from_iter(set)));
+    }
+    match options.unknown_chars {
+        UnknownCharRule::Skip => (),
+        UnknownCharRule::Token => out.push_str("unknown_chars token\n"),
+        UnknownCharRule::Error => out.push_str("unknown_chars error\n"),
+    }
+    if options.keywords != defaults.keywords {
+        out.push_str("keywords none\n");
+        for (word, keyword) in &options.keywords {
+            out.push_str(&format!("keyword {:?} {}\n", word, keyword_name(keyword)));
+        }
+    }
+    for op in &options.operators {
+        out.push_str(&format!("operator {:?}\n", op));
+    }
+    for (prefix, radix) in &options.radix_prefixes {
+        out.push_str(&format!("radix_prefix {:?} {}\n", prefix, radix));
+    }
+    for separator in &options.digit_separators {
+        out.push_str(&format!("digit_separator {:?}\n", separator.to_string()));
+    }
+    if let Some(limit) = options.long_token_warning {
+        out.push_str(&format!("long_token_warning {}\n", limit));
+    }
+    for keyword in &options.keyword_call_warning {
+        out.push_str(&format!("keyword_call_warning {}\n", keyword_name(keyword)));
+    }
+    let limits = [
+        ("max_errors", options.max_errors),
+        ("max_token_len", options.max_token_len),
+        ("max_string_len", options.max_string_len),
+        ("max_nesting", options.max_nesting),
+        ("max_tokens", options.max_tokens),
+    ];
+    for (name, limit) in limits {
+        if let Some(limit) = limit {
+            out.push_str(&format!("{} {}\n", name, limit));
+        }
+    }
+    out.push_str("source\n");
+    out.push_str(source);
+    out
+}
+
+fn keyword_name(keyword: &Keyword) -> String {
+    match keyword {
+        Keyword::Custom(word) => format!("{:?}", word),
+        keyword => keyword.to_string(),
+    }
+}
+
+fn parse_keyword(value: &str) -> Option<Keyword> {
+    match value {
+        _ if value.starts_with('"') => unquote(value).map(Keyword::Custom),
+        _ => value.parse().ok(),
+    }
+}
+
+/// Splits off the first argument of `value`, quoted or bare, returning it
+/// and the rest.
+fn split_arg(value: &str) -> Option<(&str, &str)> {
+    let value = value.trim_start();
+    let end = match value.strip_prefix('"') {
+        Some(rest) => {
+            let mut escaped = false;
+            let close = rest.find(|c| {
+                let close = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                close
+            })?;
+            close + 2
+        }
+        None => value.find(char::is_whitespace).unwrap_or(value.len()),
+    };
+    match end {
+        0 => None,
+        _ => Some((&value[..end], &value[end..])),
+    }
+}
+
+/// Reads a string quoted as `{:?}` writes it.
+fn unquote(quoted: &str) -> Option<String> {
+    let mut chars = quoted.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let hex = chars.as_str().strip_prefix('{')?;
+                let (digits, rest) = hex.split_once('}')?;
+                chars = rest.chars();
+                char::from_u32(u32::from_str_radix(digits, 16).ok()?)?
+            }
+            c @ ('\\' | '"' | '\'') => c,
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+impl Bundle {
+    pub fn parse(bundle: &str) -> Result<Bundle, BundleError> {
+        let mut lines = bundle.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some(HEADER) {
+            return Err(BundleError::MissingHeader);
+        }
+        let mut parsed = Bundle {
+            version: None,
+            options: LexerOptions::default(),
+            source: String::new(),
+        };
+        let options = &mut parsed.options;
+        for (i, line) in lines.by_ref().enumerate() {
+            let number = i + 2;
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line == "source" {
+                parsed.source = lines.collect();
+                return Ok(parsed);
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let invalid = BundleError::InvalidValue(number);
+            let args = match (split_arg(value), value.is_empty()) {
+                (Some((first, rest)), _) => (first, rest.trim()),
+                (None, true) => ("", ""),
+                (None, false) => return Err(invalid),
+            };
+            let flag = match args.0 {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => None,
+            };
+            let number_arg = args.0.parse::<usize>().ok();
+            let single = args.1.is_empty();
+            match (name, flag, single) {
+                ("version", _, true) if !args.0.is_empty() => {
+                    parsed.version = Some(args.0.to_string())
+                }
+                ("trivia", Some(flag), true) => options.trivia = flag,
+                ("shebang", Some(flag), true) => options.shebang = flag,
+                ("ascii_identifiers", Some(flag), true) => options.ascii_identifiers = flag,
+                ("case_insensitive_keywords", Some(flag), true) => {
+                    options.case_insensitive_keywords = flag
+                }
+                ("labels", Some(flag), true) => options.labels = flag,
+                ("directives", Some(flag), true) => options.directives = flag,
+                ("interpolation", Some(flag), true) => options.interpolation = flag,
+                ("attributes", Some(flag), true) => options.attributes = flag,
+                ("decorators", Some(flag), true) => options.decorators = flag,
+                ("multiline_strings", Some(flag), true) => options.multiline_strings = flag,
+                ("dedent_strings", Some(flag), true) => options.dedent_strings = flag,
+                ("layout", Some(flag), true) => options.layout = flag,
+                ("eof", Some(flag), true) => options.eof = flag,
+                ("doc_comments", Some(flag), true) => options.doc_comments = flag,
+                ("line_comments", Some(flag), true) => options.line_comments = flag,
+                ("unusual_whitespace_warning", Some(flag), true) => {
+                    options.unusual_whitespace_warning = flag
+                }
+                ("combining_warning", Some(flag), true) => options.combining_warning = flag,
+                ("whitespace", _, _) if args.0 == "only" => {
+                    let set = unquote(args.1).ok_or(invalid)?;
+                    options.whitespace = WhitespaceRule::Only(set.chars().collect());
+                }
+                ("unknown_chars", _, true) => {
+                    options.unknown_chars = match args.0 {
+                        "skip" => UnknownCharRule::Skip,
+                        "token" => UnknownCharRule::Token,
+                        "error" => UnknownCharRule::Error,
+                        _ => return Err(invalid),
+                    }
+                }
+                ("keywords", _, true) if args.0 == "none" => options.keywords.clear(),
+                ("keyword", _, false) => {
+                    let word = unquote(args.0).ok_or(invalid.clone())?;
+                    let keyword = parse_keyword(args.1).ok_or(invalid)?;
+                    options.keywords.insert(word, keyword);
+                }
+                ("operator", _, true) => options.operators.push(unquote(args.0).ok_or(invalid)?),
+                ("radix_prefix", _, false) => {
+                    let prefix = unquote(args.0).ok_or(invalid.clone())?;
+                    let radix = args.1.parse().ok().filter(|r| (2..=36).contains(r));
+                    options.radix_prefixes.push((prefix, radix.ok_or(invalid)?));
+                }
+                ("digit_separator", _, true) => {
+                    let separator = unquote(args.0).ok_or(invalid.clone())?;
+                    let mut chars = separator.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => options.digit_separators.push(c),
+                        _ => return Err(invalid),
+                    }
+                }
+                ("long_token_warning", _, true) if number_arg.is_some() => {
+                    options.long_token_warning = number_arg
+                }
+                ("keyword_call_warning", _, true) => options
+                    .keyword_call_warning
+                    .push(parse_keyword(args.0).ok_or(invalid)?),
+                ("max_errors", _, true) if number_arg.is_some() => options.max_errors = number_arg,
+                ("max_token_len", _, true) if number_arg.is_some() => {
+                    options.max_token_len = number_arg
+                }
+                ("max_string_len", _, true) if number_arg.is_some() => {
+                    options.max_string_len = number_arg
+                }
+                ("max_nesting", _, true) if number_arg.is_some() => {
+                    options.max_nesting = number_arg
+                }
+                ("max_tokens", _, true) if number_arg.is_some() => options.max_tokens = number_arg,
+                _ if OPTIONS.contains(&name) => return Err(invalid),
+                _ => return Err(BundleError::UnknownOption(number)),
+            }
+        }
+        Err(BundleError::MissingSource)
+    }
+
+    /// Lexes the bundled source with the bundled options.
+    pub fn replay(&self) -> LexOutput {
+        Lexer::new(self.options.clone()).lex_with_diagnostics(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Keyword, Lexer, LexerOptions, UnknownCharRule,
+        repro::{Bundle, BundleError, bundle_repro},
+    };
+
+    #[test]
+    fn round_trip() {
+        let lexer = Lexer::builder()
+            .trivia(true)
+            .whitespace([' ', '\n', '\u{a0}'])
+            .unknown_chars(UnknownCharRule::Token)
+            .remove_keyword("let")
+            .keyword("unless", Keyword::Custom("un \"less\"".to_string()))
+            .operator("<=>")
+            .radix_prefix("0x", 16)
+            .digit_separator('_')
+            .long_token_warning(4)
+            .keyword_call_warning(Keyword::If)
+            .max_errors(3)
+            .max_token_len(100)
+            .max_string_len(50)
+            .max_nesting(8)
+            .max_tokens(1000)
+            .build();
+        let source = "source\nunless 0x1_F <=> if(x) \"abc\ndef\r\n";
+        let bundle = bundle_repro(source, lexer.options());
+        let parsed = Bundle::parse(&bundle).unwrap();
+        assert_eq!(parsed.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(&parsed.options, lexer.options());
+        assert_eq!(parsed.source, source);
+        assert_eq!(parsed.replay(), lexer.lex_with_diagnostics(source));
+        assert_eq!(bundle_repro(source, &parsed.options), bundle);
+
+        let default = Bundle::parse("lexer-repro 1\nsource\n").unwrap();
+        assert_eq!(default.options, LexerOptions::default());
+        assert_eq!(default.source, "");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Bundle::parse("trivia true"),
+            Err(BundleError::MissingHeader)
+        );
+        assert_eq!(
+            Bundle::parse("lexer-repro 1\ntrivia yes\nsource\n"),
+            Err(BundleError::InvalidValue(2))
+        );
+        assert_eq!(
+            Bundle::parse("lexer-repro 1\nkeyword \"x\" \"unterminated\nsource\n"),
+            Err(BundleError::InvalidValue(2))
+        );
+        assert_eq!(
+            Bundle::parse("lexer-repro 1\nversion 0.1.0\ncolor red\nsource\n"),
+            Err(BundleError::UnknownOption(3))
+        );
+        assert_eq!(
+            Bundle::parse("lexer-repro 1\ntrivia true\n"),
+            Err(BundleError::MissingSource)
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Classification of tokens as LSP semantic tokens, for language servers
+//! built on this lexer.
+//!
+//! [`classify`] maps a token to one of the [`SemanticType`]s and a set of
+//! [`Modifiers`]; [`encode`] turns a token stream into the relative,
+//! five-integers-per-token form of `textDocument/semanticTokens`. Indices in
+//! that form refer to [`SemanticType::LEGEND`] and [`Modifiers::LEGEND`],
+//! which a server advertises as its legend.
+
+use alloc::vec::Vec;
+
+use crate::{Keyword, LineCol, LineIndex, Operator, Token, Type};
+
+/// A semantic token type, named after its LSP counterpart.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum SemanticType {
+    Keyword,
+    Operator,
+    Number,
+    String,
+    Variable,
+    Comment,
+}
+
+impl SemanticType {
+    /// Every type, in the order of the indices [`encode`] emits.
+    pub const LEGEND: [SemanticType; 6] = [
+        SemanticType::Keyword,
+        SemanticType::Operator,
+        SemanticType::Number,
+        SemanticType::String,
+        SemanticType::Variable,
+        SemanticType::Comment,
+    ];
+
+    /// The LSP name of the type, such as `"keyword"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SemanticType::Keyword => "keyword",
+            SemanticType::Operator => "operator",
+            SemanticType::Number => "number",
+            SemanticType::String => "string",
+            SemanticType::Variable => "variable",
+            SemanticType::Comment => "comment",
+        }
+    }
+
+    fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+/// A set of semantic token modifiers, as the bitset LSP uses.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct Modifiers(pub u32);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    /// A keyword or operator built into the lexer rather than registered
+    /// through [`crate::LexerBuilder`].
+    pub const DEFAULT_LIBRARY: Modifiers = Modifiers(1 << 0);
+    /// A literal keyword: `true`, `false` or `null`.
+    pub const READONLY: Modifiers = Modifiers(1 << 1);
+    /// A doc comment.
+    pub const DOCUMENTATION: Modifiers = Modifiers(1 << 2);
+    /// The LSP names of the modifier bits, lowest bit first.
+    pub const LEGEND: [&'static str; 3] = ["defaultLibrary", "readonly", "documentation"];
+
+    pub fn union(self, other: Modifiers) -> Modifiers {
+        Modifiers(self.0 | other.0)
+    }
+
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// The semantic type and modifiers of `token`, or `None` for punctuation,
+/// whitespace and anything else an editor colors by syntax alone.
+pub fn classify(token: &Token) -> Option<(SemanticType, Modifiers)> {
+    Some(match &token.token_type {
+        Type::Keyword(Keyword::Custom(_)) => (SemanticType::Keyword, Modifiers::NONE),
+        Type::Keyword(Keyword::True | Keyword::False | Keyword::Null) => (
+            SemanticType::Keyword,
+            Modifiers::DEFAULT_LIBRARY.union(Modifiers::READONLY),
+        ),
+        Type::Keyword(_) => (SemanticType::Keyword, Modifiers::DEFAULT_LIBRARY),
+        Type::Operator(Operator::Custom(_)) => (SemanticType::Operator, Modifiers::NONE),
+        Type::Operator(_) => (SemanticType::Operator, Modifiers::DEFAULT_LIBRARY),
+        Type::Number(_) => (SemanticType::Number, Modifiers::NONE),
+        Type::String(_) | Type::StringPart(_) | Type::StringStart | Type::StringEnd => {
+            (SemanticType::String, Modifiers::NONE)
+        }
+        Type::Identifier(_) | Type::Symbol(_) => (SemanticType::Variable, Modifiers::NONE),
+        Type::Comment(_) | Type::Shebang(_) => (SemanticType::Comment, Modifiers::NONE),
+        Type::DocComment(_) => (SemanticType::Comment, Modifiers::DOCUMENTATION),
+        _ => return None,
+    })
+}
+
+/// Encodes the classified tokens of `tokens` as LSP semantic token data:
+/// for each token, its line and start column relative to the previous one,
+/// its length, type index and modifier bits. Columns and lengths count
+/// UTF-16 code units, and a token spanning several lines is split into one
+/// entry per line. `index` must be built from the text `tokens` were lexed
+/// from.
+pub fn encode(tokens: &[Token], index: &LineIndex) -> Vec<u32> {
+    let mut data = Vec::new();
+    let mut previous = LineCol::default();
+    for token in tokens {
+        let Some((semantic, modifiers)) = classify(token) else {
+            continue;
+        };
+        let start = index.line_col(token.span.start);
+        let end = index.line_col(token.span.end);
+        for line in start.line..=end.line {
+            let Some(line_span) = index.line_span(line) else {
+                break;
+            };
+            // Leave out the newline ending the line, if there is one.
+            let line_end = match line + 1 < index.line_count() {
+                true => line_span.end - 1,
+                false => line_span.end,
+            };
+            let from = index.to_utf16(index.line_col(token.span.start.max(line_span.start)));
+            let to = index.to_utf16(index.line_col(token.span.end.min(line_end)));
+            if to.column <= from.column {
+                continue;
+            }
+            let delta_start = match from.line == previous.line {
+                true => from.column - previous.column,
+                false => from.column,
+            };
+            data.extend([
+                (from.line - previous.line) as u32,
+                delta_start as u32,
+                (to.column - from.column) as u32,
+                semantic.index(),
+                modifiers.0,
+            ]);
+            previous = from;
+        }
+    }
+    data
+}
+
+/// The legend matching [`encode`]'s indices.
+#[cfg(feature = "lsp")]
+pub fn lsp_legend() -> lsp_types::SemanticTokensLegend {
+    lsp_types::SemanticTokensLegend {
+        token_types: SemanticType::LEGEND
+            .iter()
+            .map(|t| lsp_types::SemanticTokenType::new(t.name()))
+            .collect(),
+        token_modifiers: Modifiers::LEGEND
+            .iter()
+            .map(|&m| lsp_types::SemanticTokenModifier::new(m))
+            .collect(),
+    }
+}
+
+/// [`encode`] as the `lsp-types` response payload.
+#[cfg(feature = "lsp")]
+pub fn lsp_tokens(tokens: &[Token], index: &LineIndex) -> lsp_types::SemanticTokens {
+    lsp_types::SemanticTokens {
+        result_id: None,
+        data: encode(tokens, index)
+            .chunks(5)
+            .map(|entry| lsp_types::SemanticToken {
+                delta_line: entry[0],
+                delta_start: entry[1],
+                length: entry[2],
+                token_type: entry[3],
+                token_modifiers_bitset: entry[4],
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Keyword, Lexer, LineIndex,
+        semantic::{Modifiers, SemanticType, encode},
+    };
+
+    #[test]
+    fn semantic_tokens() {
+        let source = "if x == \"é\" {\n  /* a\n b */ true <=> y2 4\n}";
+        let tokens = Lexer::builder()
+            .trivia(true)
+            .operator("<=>")
+            .keyword("y2", Keyword::Custom("y2".to_string()))
+            .build()
+            .lex(source)
+            .unwrap();
+        let keyword = SemanticType::Keyword as u32;
+        let builtin = Modifiers::DEFAULT_LIBRARY.0;
+        assert_eq!(
+            encode(&tokens, &LineIndex::new(source))
+                .chunks(5)
+                .collect::<Vec<&[u32]>>(),
+            vec![
+                [0, 0, 2, keyword, builtin].as_slice(),
+                &[0, 3, 1, SemanticType::Variable as u32, 0],
+                &[0, 2, 2, SemanticType::Operator as u32, builtin],
+                &[0, 3, 3, SemanticType::String as u32, 0],
+                &[1, 2, 4, SemanticType::Comment as u32, 0],
+                &[1, 0, 5, SemanticType::Comment as u32, 0],
+                &[0, 6, 4, keyword, builtin | Modifiers::READONLY.0],
+                &[0, 5, 3, SemanticType::Operator as u32, 0],
+                &[0, 4, 2, keyword, 0],
+                &[0, 3, 1, SemanticType::Number as u32, 0],
+            ]
+        );
+        assert_eq!(SemanticType::LEGEND[keyword as usize].name(), "keyword");
+    }
+}
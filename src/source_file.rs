@@ -0,0 +1,98 @@
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+use crate::{LineCol, LineIndex, Span};
+
+/// A piece of source text together with the name it is reported under and
+/// its [`LineIndex`], built once so every position lookup is a binary search.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SourceFile {
+    name: Option<String>,
+    text: String,
+    index: LineIndex,
+}
+
+impl SourceFile {
+    /// An unnamed source, such as text typed into a REPL.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        SourceFile {
+            name: None,
+            index: LineIndex::new(&text),
+            text,
+        }
+    }
+
+    pub fn with_name(name: impl Into<String>, text: impl Into<String>) -> Self {
+        SourceFile {
+            name: Some(name.into()),
+            ..SourceFile::new(text)
+        }
+    }
+
+    /// Reads the file at `path`, naming the source after the path.
+    #[cfg(feature = "std")]
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Ok(SourceFile::with_name(path.display().to_string(), text))
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name for messages: the source's name, or `<anonymous>`.
+    pub fn display_name(&self) -> &str {
+        self.name().unwrap_or("<anonymous>")
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_index(&self) -> &LineIndex {
+        &self.index
+    }
+
+    /// The line and byte column of `offset`; see [`LineIndex::line_col`].
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        self.index.line_col(offset)
+    }
+
+    /// The text of zero-based `line`, without its line ending.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let span = self.index.line_span(line)?;
+        Some(self.text[span.start..span.end].trim_end_matches(['\n', '\r']))
+    }
+
+    pub fn slice(&self, span: Span) -> Option<&str> {
+        self.text.get(span.start..span.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LineCol, SourceFile, Span};
+
+    #[test]
+    fn source_file() {
+        let file = SourceFile::with_name("main.lx", "define x = 1;\r\ny = \"é\"\n");
+        assert_eq!(file.name(), Some("main.lx"));
+        assert_eq!(file.line_col(21), LineCol::new(1, 6));
+        assert_eq!(file.line(0), Some("define x = 1;"));
+        assert_eq!(file.line(2), Some(""));
+        assert_eq!(file.line(3), None);
+        assert_eq!(file.slice(Span::new(0, 6)), Some("define"));
+        assert_eq!(file.slice(Span::new(20, 21)), None);
+        assert_eq!(file.line_index().line_count(), 3);
+        assert_eq!(SourceFile::new("").display_name(), "<anonymous>");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_missing_file() {
+        assert!(SourceFile::read("/nonexistent/file.lx").is_err());
+    }
+}
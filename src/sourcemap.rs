@@ -0,0 +1,200 @@
+//! Source maps from generated text back to the source its tokens were lexed
+//! from, so errors in the output of a transpiler can be reported against
+//! the original source.
+//!
+//! A [`SourceMap`] is a table of [`Mapping`]s, each pairing a span of the
+//! generated text with the span of the source it came from. Code generators
+//! [`SourceMap::push`] a mapping for each token they write;
+//! [`detokenize_mapped`] does this for [`crate::detokenize`].
+//! [`SourceMap::to_json`] renders the table as a standard version 3 source
+//! map for browsers and other tools that read them.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::{LineCol, LineIndex, Span, Token, export::push_string};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A span of generated text and the span of the source it was produced from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Mapping {
+    pub generated: Span,
+    pub original: Span,
+}
+
+/// Mappings from generated text to its source, ordered by where they start
+/// in the generated text.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SourceMap {
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    /// Records that the text at `generated` was produced from the source at
+    /// `original`. Mappings may be pushed in any order.
+    pub fn push(&mut self, generated: Span, original: Span) {
+        let at = self
+            .mappings
+            .partition_point(|m| m.generated.start <= generated.start);
+        self.mappings.insert(
+            at,
+            Mapping {
+                generated,
+                original,
+            },
+        );
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// The source span of the generated text at byte `offset`, or `None`
+    /// where no mapping covers it, such as in a separator between tokens.
+    /// Of nested mappings, the innermost wins.
+    pub fn original(&self, offset: usize) -> Option<Span> {
+        let at = self
+            .mappings
+            .partition_point(|m| m.generated.start <= offset);
+        self.mappings[..at]
+            .iter()
+            .rev()
+            .find(|m| offset < m.generated.end)
+            .map(|m| m.original)
+    }
+
+    /// A version 3 source map of `generated`, produced from the text
+    /// `original` of the source named `source_name`. Each mapping marks
+    /// where its generated span starts; columns count UTF-16 code units, as
+    /// the format requires.
+    pub fn to_json(&self, generated: &str, original: &str, source_name: &str) -> String {
+        let generated_index = LineIndex::new(generated);
+        let original_index = LineIndex::new(original);
+        let mut segments = String::new();
+        let mut line = 0;
+        let mut column = 0;
+        let mut previous = LineCol::new(0, 0);
+        for mapping in &self.mappings {
+            let at = utf16(&generated_index, mapping.generated.start);
+            let from = utf16(&original_index, mapping.original.start);
+            match at.line > line {
+                true => {
+                    for _ in line..at.line {
+                        segments.push(';');
+                    }
+                    line = at.line;
+                    column = 0;
+                }
+                false if !segments.is_empty() && !segments.ends_with(';') => segments.push(','),
+                false => (),
+            }
+            push_vlq(&mut segments, at.column as i64 - column as i64);
+            push_vlq(&mut segments, 0);
+            push_vlq(&mut segments, from.line as i64 - previous.line as i64);
+            push_vlq(&mut segments, from.column as i64 - previous.column as i64);
+            column = at.column;
+            previous = from;
+        }
+        let mut out = String::from("{\"version\":3,\"sources\":[");
+        push_string(&mut out, source_name);
+        out.push_str("],\"names\":[],\"mappings\":");
+        push_string(&mut out, &segments);
+        out.push('}');
+        out
+    }
+}
+
+fn utf16(index: &LineIndex, offset: usize) -> LineCol {
+    index.to_utf16(index.line_col(offset))
+}
+
+/// Appends `value` as a base64 VLQ: its sign in the lowest bit, then five
+/// bits per digit, lowest first, with the sixth bit set on all but the last.
+fn push_vlq(out: &mut String, value: i64) {
+    let mut rest = (value.unsigned_abs() << 1) | u64::from(value < 0);
+    loop {
+        let digit = (rest & 31) as usize;
+        rest >>= 5;
+        match rest {
+            0 => {
+                out.push(BASE64[digit] as char);
+                return;
+            }
+            _ => out.push(BASE64[digit | 32] as char),
+        }
+    }
+}
+
+/// Like [`crate::detokenize`], along with a map from each token's text in
+/// the output to its span in the source. Tokens without text, such as an
+/// inserted `;`, get no mapping.
+pub fn detokenize_mapped(tokens: &[Token]) -> (String, SourceMap) {
+    let separator = match tokens.iter().any(Token::is_trivia) {
+        true => "",
+        false => " ",
+    };
+    let mut out = String::new();
+    let mut map = SourceMap::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            out.push_str(separator);
+        }
+        let start = out.len();
+        let _ = write!(out, "{}", token);
+        if out.len() > start {
+            map.mappings.push(Mapping {
+                generated: Span::new(start, out.len()),
+                original: token.span,
+            });
+        }
+    }
+    (out, map)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lexer, Span, detokenize,
+        sourcemap::{SourceMap, detokenize_mapped, push_vlq},
+    };
+
+    #[test]
+    fn source_maps() {
+        let source = "x =\n  1 + \"é\"\n\nf(y)";
+        let tokens = Lexer::default().lex(source).unwrap();
+        let (text, map) = detokenize_mapped(&tokens);
+        assert_eq!(text, detokenize(&tokens));
+        assert_eq!(map.mappings().len(), tokens.len());
+        assert_eq!(map.original(4), Some(Span::new(6, 7)));
+        assert_eq!(map.original(3), None);
+        assert_eq!(map.original(9), Some(Span::new(10, 14)));
+        assert_eq!(
+            map.to_json(&text, source, "in.dsl"),
+            "{\"version\":3,\"sources\":[\"in.dsl\"],\"names\":[],\
+             \"mappings\":\"AAAA,EAAE,EACA,EAAE,EAAE,IAEN,EAAC,EAAC,EAAC\"}"
+        );
+
+        let mut map = SourceMap::new();
+        map.push(Span::new(4, 6), Span::new(0, 2));
+        map.push(Span::new(0, 1), Span::new(5, 6));
+        map.push(Span::new(0, 0), Span::new(9, 9));
+        assert_eq!(map.mappings()[2].generated, Span::new(4, 6));
+        assert_eq!(map.original(0), Some(Span::new(5, 6)));
+        assert_eq!(
+            map.to_json("a\n\nbc", "", "-"),
+            "{\"version\":3,\"sources\":[\"-\"],\"names\":[],\"mappings\":\"AAAA,AAAA;;CAAA\"}"
+        );
+
+        let mut out = String::new();
+        for value in [0, 1, -1, 15, 16, -17, 1000] {
+            push_vlq(&mut out, value);
+            out.push(' ');
+        }
+        assert_eq!(out, "A C D e gB jB w+B ");
+    }
+}
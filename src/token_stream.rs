@@ -0,0 +1,189 @@
+use alloc::{string::ToString, vec::Vec};
+
+use crate::{Token, TokenKind, export::kind_name};
+
+/// A position in a [`TokenStream`] to [`TokenStream::rewind`] to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Checkpoint(usize);
+
+/// Why [`TokenStream::expect`] failed: the kind it wanted and the token it
+/// found instead, or `None` at the end of the stream.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Unexpected {
+    pub expected: TokenKind,
+    pub found: Option<Token>,
+}
+
+/// A cursor over lexed tokens with the lookahead and backtracking helpers a
+/// recursive descent parser needs. Trivia is dropped on construction, so
+/// parsers only ever see significant tokens.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TokenStream {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl TokenStream {
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        tokens.retain(|t| !t.is_trivia());
+        TokenStream {
+            tokens,
+            position: 0,
+        }
+    }
+
+    /// The next token, without consuming it.
+    pub fn peek(&self) -> Option<&Token> {
+        self.peek_n(0)
+    }
+
+    /// The token `n` places ahead of the next one, without consuming
+    /// anything; `peek_n(0)` is [`TokenStream::peek`].
+    pub fn peek_n(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.position + n)
+    }
+
+    /// The token consumed last, if any.
+    pub fn previous(&self) -> Option<&Token> {
+        self.tokens.get(self.position.checked_sub(1)?)
+    }
+
+    /// Whether the next token is of `kind`.
+    pub fn at(&self, kind: TokenKind) -> bool {
+        self.peek().is_some_and(|t| t.kind() == kind)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    /// Consumes the next token if it is of `kind`, reporting whether it did.
+    pub fn eat(&mut self, kind: TokenKind) -> bool {
+        match self.at(kind) {
+            true => {
+                self.position += 1;
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// Consumes and returns the next token if it is of `kind`. Otherwise
+    /// nothing is consumed.
+    pub fn expect(&mut self, kind: TokenKind) -> Result<&Token, Unexpected> {
+        match self.at(kind) {
+            true => {
+                self.position += 1;
+                Ok(&self.tokens[self.position - 1])
+            }
+            false => Err(Unexpected {
+                expected: kind,
+                found: self.peek().cloned(),
+            }),
+        }
+    }
+
+    /// Whether every token has been consumed, or only a [`Type::Eof`] is
+    /// left.
+    ///
+    /// [`Type::Eof`]: crate::Type::Eof
+    pub fn is_at_end(&self) -> bool {
+        self.peek().is_none_or(Token::is_eof)
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.position)
+    }
+
+    /// Returns to `checkpoint`, so the tokens consumed since are read again.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.position = checkpoint.0;
+    }
+
+    /// A 64-bit FNV-1a hash of the kind and lexeme of every token, ignoring
+    /// spans and the cursor position. Since trivia is dropped, edits that
+    /// only touch whitespace or comments keep the hash. Kinds are hashed by
+    /// their names in [`crate::export`], which never change, so the value is
+    /// the same on every platform and release and can be stored as a cache
+    /// key.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        for token in &self.tokens {
+            let lexeme = token.to_string();
+            let kind = kind_name(&token.token_type);
+            feed(&(kind.len() as u64).to_le_bytes());
+            feed(kind.as_bytes());
+            feed(&(lexeme.len() as u64).to_le_bytes());
+            feed(lexeme.as_bytes());
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Span, Token, TokenKind, Type, lex_lossless,
+        token_stream::{TokenStream, Unexpected},
+    };
+
+    #[test]
+    fn token_stream() {
+        let mut stream = TokenStream::new(lex_lossless("f ( x ) /* c */ ;").unwrap());
+        assert_eq!(
+            stream.peek_n(1).map(Token::kind),
+            Some(TokenKind::LeftParen)
+        );
+        assert!(stream.at(TokenKind::Identifier));
+        let start = stream.checkpoint();
+        assert_eq!(
+            stream.expect(TokenKind::Identifier).map(|t| t.span),
+            Ok(Span::new(0, 1))
+        );
+        assert!(!stream.eat(TokenKind::RightParen));
+        assert!(stream.eat(TokenKind::LeftParen));
+        assert_eq!(
+            stream.expect(TokenKind::Number),
+            Err(Unexpected {
+                expected: TokenKind::Number,
+                found: Some(Token::with_span(
+                    Type::Identifier("x".to_string()),
+                    Span::new(4, 5)
+                )),
+            })
+        );
+        assert_eq!(stream.next().map(Token::kind), Some(TokenKind::Identifier));
+        assert!(stream.eat(TokenKind::RightParen));
+        assert_eq!(stream.peek().map(Token::kind), Some(TokenKind::Semicolon));
+        stream.rewind(start);
+        assert!(stream.at(TokenKind::Identifier));
+        while stream.next().is_some() {}
+        assert!(stream.is_at_end());
+        assert_eq!(
+            stream.expect(TokenKind::Semicolon),
+            Err(Unexpected {
+                expected: TokenKind::Semicolon,
+                found: None,
+            })
+        );
+    }
+
+    #[test]
+    fn content_hash() {
+        let hash = |source: &str| TokenStream::new(lex_lossless(source).unwrap()).content_hash();
+        assert_eq!(hash("f(x, 1)"), hash("f ( x,\n\t1 ) /* note */"));
+        assert_ne!(hash("f(x, 1)"), hash("f(x, 2)"));
+        assert_ne!(hash("ab"), hash("a b"));
+        assert_ne!(hash("\"x\""), hash("x"));
+        assert_eq!(hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash("a ; b"), 0x1a34_2ca8_516a_cd12);
+    }
+}
@@ -0,0 +1,180 @@
+//! Step-by-step recording of what the lexer did, for debugging why a
+//! configuration tokenizes some input unexpectedly.
+//!
+//! A [`Trace`] holds one [`Step`] per rule the lexer applied, in order, with
+//! the input it consumed and everything it produced. [`Trace::replay`]
+//! rebuilds the lexer's output as it stood after any step.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::{Cursor, LexOutput, Lexer, Span, error_limit_reached, lex_preamble, lex_step};
+
+/// The lexing rule applied at a step, in the order the lexer tries them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Rule {
+    /// A byte order mark or shebang line at the start of input.
+    Preamble,
+    /// An operator registered through [`crate::LexerBuilder::operator`].
+    CustomOperator,
+    String,
+    Number,
+    /// A bracket, `.`, `,`, `:`, `::`, `?` or `;`.
+    Punctuation,
+    /// A block comment, or a doc comment.
+    BlockComment,
+    /// A `//` comment; see [`crate::LexerOptions::line_comments`].
+    LineComment,
+    Operator,
+    /// An identifier, keyword, label, directive or decorator.
+    Identifier,
+    /// A `#[...]` attribute, with the steps inside it.
+    Attribute,
+    Whitespace,
+    /// A character no rule accepts.
+    Unknown,
+}
+
+/// One decision of the lexer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Step {
+    pub rule: Rule,
+    /// The input consumed by this step.
+    pub span: Span,
+    /// The tokens, errors and warnings this step produced.
+    pub output: LexOutput,
+}
+
+/// Every step of one lexer run.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Trace {
+    steps: Vec<Step>,
+}
+
+impl Trace {
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The lexer's accumulated output after the first `steps` steps; with
+    /// `steps >= self.steps().len()` this equals the output of
+    /// [`Lexer::lex_with_diagnostics`] before the lexer's token limit,
+    /// layout, filters and end of input token are applied.
+    pub fn replay(&self, steps: usize) -> LexOutput {
+        let mut out = LexOutput::default();
+        for step in self.steps.iter().take(steps) {
+            out.tokens.extend(step.output.tokens.iter().cloned());
+            out.errors.extend(step.output.errors.iter().cloned());
+            out.warnings.extend(step.output.warnings.iter().cloned());
+        }
+        out
+    }
+
+    /// Lists the steps one per line: number, rule, span and consumed text,
+    /// followed by what the step produced.
+    pub fn render(&self, source: &str) -> String {
+        let mut out = String::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let span = format!("{}..{}", step.span.start, step.span.end);
+            let _ = writeln!(
+                out,
+                "{:>4}  {:<14} {:<10} {:?}",
+                i + 1,
+                format!("{:?}", step.rule),
+                span,
+                &source[step.span.start..step.span.end]
+            );
+            for token in &step.output.tokens {
+                let _ = writeln!(out, "        token: {:?} {}", token.kind(), token);
+            }
+            for error in &step.output.errors {
+                let _ = writeln!(out, "        error: {:?}", error);
+            }
+            for warning in &step.output.warnings {
+                let _ = writeln!(out, "        warning: {:?}", warning);
+            }
+        }
+        out
+    }
+}
+
+/// Lexes `source` with `lexer`'s options, recording every step.
+pub fn trace(lexer: &Lexer, source: &str) -> Trace {
+    let options = lexer.options();
+    let mut chars = Cursor::new(source);
+    let mut steps = Vec::new();
+    let mut output = LexOutput::default();
+    lex_preamble(&mut chars, options, &mut output.tokens);
+    if chars.offset > 0 {
+        steps.push(Step {
+            rule: Rule::Preamble,
+            span: Span::new(0, chars.offset),
+            output,
+        });
+    }
+    let mut errors = 0;
+    loop {
+        if error_limit_reached(options, errors) {
+            return Trace { steps };
+        }
+        let start = chars.offset;
+        let mut output = LexOutput::default();
+        match lex_step(&mut chars, options, &mut output) {
+            Some(rule) => {
+                errors += output.errors.len();
+                steps.push(Step {
+                    rule,
+                    span: Span::new(start, chars.offset),
+                    output,
+                })
+            }
+            None => return Trace { steps },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        LexError, Lexer, Span,
+        trace::{Rule, trace},
+    };
+
+    #[test]
+    fn trace_steps() {
+        let lexer = Lexer::builder().operator("=~").build();
+        let source = "#!run\nx =~ \"a $";
+        let trace = trace(&lexer, source);
+        assert_eq!(
+            trace
+                .steps()
+                .iter()
+                .map(|step| (step.rule, step.span))
+                .collect::<Vec<(Rule, Span)>>(),
+            vec![
+                (Rule::Preamble, Span::new(0, 5)),
+                (Rule::Whitespace, Span::new(5, 6)),
+                (Rule::Identifier, Span::new(6, 7)),
+                (Rule::Whitespace, Span::new(7, 8)),
+                (Rule::CustomOperator, Span::new(8, 10)),
+                (Rule::Whitespace, Span::new(10, 11)),
+                (Rule::String, Span::new(11, 15)),
+            ]
+        );
+        assert_eq!(
+            trace.steps()[6].output.errors,
+            vec![LexError::UnterminatedString(11)]
+        );
+        assert_eq!(trace.replay(100), lexer.lex_with_diagnostics(source));
+        assert_eq!(trace.replay(3).tokens.len(), 1);
+        assert_eq!(
+            trace.render(source).lines().take(4).collect::<Vec<&str>>(),
+            vec![
+                "   1  Preamble       0..5       \"#!run\"",
+                "   2  Whitespace     5..6       \"\\n\"",
+                "   3  Identifier     6..7       \"x\"",
+                "        token: Identifier x",
+            ]
+        );
+    }
+}
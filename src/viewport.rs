@@ -0,0 +1,103 @@
+//! Tokenizing several visible regions of a document at once, for editors
+//! that highlight every open viewport or split of a buffer together.
+
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    Cursor, LexOutput, Lexer, SourceFile, Span, Token, error_limit_reached, lex_preamble, lex_step,
+    token_limit_reached,
+};
+
+impl Lexer {
+    /// The tokens of each range of zero-based lines in `viewports`, in the
+    /// order the ranges are given. A token that starts above a viewport but
+    /// reaches into it, such as a block comment opened earlier, is included.
+    ///
+    /// `file` is lexed once, from its start up to the end of the lowest
+    /// viewport, and the tokens are handed out to every viewport they
+    /// overlap. Lexers with filters or layout depend on the whole input, and
+    /// modes are only run over whole inputs, so lexers with any of these lex
+    /// all of it.
+    pub fn lex_viewports(&self, file: &SourceFile, viewports: &[Range<usize>]) -> Vec<Vec<Token>> {
+        let spans: Vec<Span> = viewports
+            .iter()
+            .map(|lines| line_span(file, lines))
+            .collect();
+        let mut result = vec![Vec::new(); spans.len()];
+        let end = spans.iter().map(|span| span.end).max().unwrap_or(0);
+        let tokens =
+            match self.filters.is_empty() && !self.options().layout && self.modes.is_empty() {
+                true => self.lex_until(file.text(), end),
+                false => self.lex_file(file).tokens,
+            };
+        for token in tokens {
+            for (span, tokens) in spans.iter().zip(&mut result) {
+                if token.span.start < span.end && token.span.end > span.start {
+                    tokens.push(token.clone());
+                }
+            }
+        }
+        result
+    }
+
+    /// The tokens of `source` up to the first one ending at or past `end`.
+    fn lex_until(&self, source: &str, end: usize) -> Vec<Token> {
+        let options = self.options();
+        let mut chars = Cursor::new(source);
+        let mut out = LexOutput::default();
+        lex_preamble(&mut chars, options, &mut out.tokens);
+        while chars.offset < end
+            && chars.peek().is_some()
+            && !error_limit_reached(options, out.errors.len())
+        {
+            lex_step(&mut chars, options, &mut out);
+            if token_limit_reached(options, 0, &mut out) {
+                break;
+            }
+        }
+        out.tokens
+    }
+}
+
+/// The bytes of `lines` in `file`, clamped to the end of the text.
+fn line_span(file: &SourceFile, lines: &Range<usize>) -> Span {
+    let index = file.line_index();
+    let len = file.text().len();
+    let start = index.line_span(lines.start).map_or(len, |span| span.start);
+    let end = match lines.end > lines.start {
+        true => index.line_span(lines.end - 1).map_or(len, |span| span.end),
+        false => start,
+    };
+    Span::new(start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Lexer, SourceFile, Span, Token};
+
+    #[test]
+    fn lex_viewports() {
+        let lexer = Lexer::builder().trivia(true).build();
+        let file = SourceFile::new("a\n/* b\nc */ d\ne\nf");
+        let viewports = lexer.lex_viewports(&file, &[3..5, 2..3, 9..10, 1..1]);
+        let spans = |tokens: &[Token]| tokens.iter().map(|t| t.span).collect::<Vec<Span>>();
+        assert_eq!(
+            spans(&viewports[0]),
+            [Span::new(14, 15), Span::new(15, 16), Span::new(16, 17)]
+        );
+        assert_eq!(
+            spans(&viewports[1]),
+            [
+                Span::new(2, 11),
+                Span::new(11, 12),
+                Span::new(12, 13),
+                Span::new(13, 14)
+            ]
+        );
+        assert!(viewports[2].is_empty());
+        assert!(viewports[3].is_empty());
+        let all = lexer.lex_file(&file).tokens;
+        assert_eq!(lexer.lex_viewports(&file, &[0..5, 4..5])[0], all);
+    }
+}
@@ -0,0 +1,88 @@
+//! Bindings for JavaScript through `wasm-bindgen`, for running the lexer in a
+//! browser. Build them as a `cdylib` with the `wasm` feature, for example with
+//! `cargo rustc --lib --crate-type cdylib --target wasm32-unknown-unknown
+//! --features wasm`, then run `wasm-bindgen` on the result.
+//!
+//! Results cross the boundary as JSON strings, ready for `JSON.parse`. The
+//! lexing functions return an object with the tokens, in the schema of
+//! [`crate::export`], and the errors, each with the message and span of its
+//! [`Diagnostic`]:
+//!
+//! ```text
+//! {"tokens": [{"kind": "Identifier", "value": "x", "start": 0, "end": 1}],
+//!  "errors": [{"message": "unterminated string", "start": 2, "end": 3}]}
+//! ```
+
+use alloc::{format, string::String};
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{LexOutput, Lexer, diagnostics::Diagnostic, export, highlight};
+
+fn output_json(output: &LexOutput) -> String {
+    let mut out = format!(
+        "{{\"tokens\":{},\"errors\":[",
+        export::to_json(&output.tokens)
+    );
+    for (i, error) in output.errors.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let diagnostic = Diagnostic::from(error);
+        out.push_str("{\"message\":");
+        export::push_string(&mut out, &diagnostic.message);
+        out.push_str(&format!(
+            ",\"start\":{},\"end\":{}}}",
+            diagnostic.span.start, diagnostic.span.end
+        ));
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Lexes `source` with the default options, skipping past errors.
+#[wasm_bindgen]
+pub fn lex(source: &str) -> String {
+    output_json(&Lexer::default().lex_with_diagnostics(source))
+}
+
+/// Like [`lex`], keeping whitespace and comments as tokens.
+#[wasm_bindgen(js_name = lexLossless)]
+pub fn lex_lossless(source: &str) -> String {
+    output_json(
+        &Lexer::builder()
+            .trivia(true)
+            .build()
+            .lex_with_diagnostics(source),
+    )
+}
+
+/// Highlights `source` as HTML; see [`highlight::to_html`].
+#[wasm_bindgen(js_name = highlightHtml)]
+pub fn highlight_html(source: &str) -> String {
+    highlight::to_html(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wasm::{highlight_html, lex, lex_lossless};
+
+    #[test]
+    fn bindings() {
+        assert_eq!(
+            lex("x \"a"),
+            concat!(
+                r#"{"tokens":[{"kind":"Identifier","value":"x","start":0,"end":1}],"#,
+                r#""errors":[{"message":"unterminated string","start":2,"end":3}]}"#
+            )
+        );
+        assert_eq!(
+            lex_lossless("1 "),
+            concat!(
+                r#"{"tokens":[{"kind":"Number","value":1,"start":0,"end":1},"#,
+                r#"{"kind":"Whitespace","value":" ","start":1,"end":2}],"errors":[]}"#
+            )
+        );
+        assert_eq!(highlight_html("if"), "<span class=\"kw\">if</span>");
+    }
+}